@@ -1,6 +1,6 @@
 //! Build an example ARP message
 
-fn main() -> () {
+fn main() {
     use catnip::*;
 
     let msg = ArpPayload::new(
@@ -18,4 +18,4 @@ fn main() -> () {
     let msg_parsed = ArpPayload::read_bytes(&bytes);
 
     assert_eq!(msg, msg_parsed);
-}
\ No newline at end of file
+}
@@ -1,11 +1,10 @@
-
-fn main() -> () {
+fn main() {
     use catnip::*;
 
     let dhcp_inform = DhcpFixedPayload::new_inform(
         IpV4Addr::new([1, 2, 3, 4]),
         MacAddr::new([5, 6, 7, 8, 9, 10]),
-        12345
+        12345,
     );
 
     // Serialize
@@ -14,4 +13,4 @@ fn main() -> () {
     let msg_parsed = DhcpFixedPayload::read_bytes(&bytes);
 
     assert_eq!(msg_parsed, dhcp_inform);
-}
\ No newline at end of file
+}
@@ -0,0 +1,403 @@
+//! IPV4 address conflict detection per RFC 5227.
+//!
+//! Before using an address, a host sends a few ARP probes for it at random intervals,
+//! and gives the address up if any other host claims it in the meantime. Once the address is claimed,
+//! the host announces it, then keeps watching ARP traffic and defends the address against
+//! other hosts that use it, reporting each conflict to the application.
+//!
+//! [AddressConflictDetector] does no I/O. The application feeds it every received ARP message with
+//! [AddressConflictDetector::process] and the current time with [AddressConflictDetector::poll],
+//! and acts on the [AcdEvent] returned by each. Times are in milliseconds.
+//!
+//! ```rust
+//! use catnip::*;
+//!
+//! let macaddr = MacAddr::new([0x02, 0xAF, 0xFF, 0x1A, 0xE5, 0x3C]);
+//! let ipaddr = IpV4Addr::new([10, 0, 0, 120]);
+//! let mut acd = AddressConflictDetector::new(macaddr, ipaddr, 12345, 0);
+//!
+//! // Run until the address is claimed, sending probes along the way
+//! let mut now = 0;
+//! let mut probes = 0;
+//! loop {
+//!     match acd.poll(now) {
+//!         AcdEvent::Transmit(frame) => {
+//!             assert_eq!(frame.data, ArpPayload::probe(macaddr, ipaddr));
+//!             probes += 1;
+//!         }
+//!         AcdEvent::Claimed(frame) => {
+//!             assert_eq!(frame.data, ArpPayload::announce(macaddr, ipaddr));
+//!             break;
+//!         }
+//!         _ => {}
+//!     }
+//!     now += 10;
+//! }
+//! assert_eq!(probes, 3);
+//!
+//! // Another host using the address is detected and reported
+//! let other = ArpPayload::announce(MacAddr::new([0x02, 0, 0, 0, 0, 1]), ipaddr);
+//! assert!(matches!(acd.process(&other, now), AcdEvent::Defend(_, _)));
+//! ```
+
+use crate::*;
+
+/// Maximum delay before the first probe (milliseconds)
+pub const PROBE_WAIT_MS: u64 = 1_000;
+/// Number of probes to send
+pub const PROBE_NUM: u8 = 3;
+/// Minimum delay between probes (milliseconds)
+pub const PROBE_MIN_MS: u64 = 1_000;
+/// Maximum delay between probes (milliseconds)
+pub const PROBE_MAX_MS: u64 = 2_000;
+/// Delay after the last probe before the address is claimed (milliseconds)
+pub const ANNOUNCE_WAIT_MS: u64 = 2_000;
+/// Number of announcements to send
+pub const ANNOUNCE_NUM: u8 = 2;
+/// Delay between announcements (milliseconds)
+pub const ANNOUNCE_INTERVAL_MS: u64 = 2_000;
+/// Number of conflicts after which probing is rate-limited
+pub const MAX_CONFLICTS: u8 = 10;
+/// Delay between attempts once probing is rate-limited (milliseconds)
+pub const RATE_LIMIT_INTERVAL_MS: u64 = 60_000;
+/// Minimum time between defenses of a claimed address (milliseconds)
+pub const DEFEND_INTERVAL_MS: u64 = 10_000;
+
+/// Progress of claiming an address
+#[derive(Clone, Copy, uDebug, Debug, PartialEq, Eq)]
+pub enum AcdState {
+    /// Checking whether the address is in use; holds the number of probes sent so far
+    Probing(u8),
+    /// The address is claimed; holds the number of announcements sent so far
+    Announcing(u8),
+    /// The address is claimed and has been announced
+    Bound,
+    /// The address is in use by another host and must not be used
+    Conflict,
+}
+
+/// Something the application should act on
+#[derive(Clone, Copy, uDebug, Debug, PartialEq, Eq)]
+pub enum AcdEvent {
+    /// Nothing to do
+    None,
+    /// Send this frame
+    Transmit(EthernetFrame<ArpPayload>),
+    /// No other host claimed the address during probing, so it may now be used;
+    /// send this first announcement
+    Claimed(EthernetFrame<ArpPayload>),
+    /// Another host with this MAC address used our claimed address, and we are defending it;
+    /// send this announcement
+    Defend(MacAddr, EthernetFrame<ArpPayload>),
+    /// Another host with this MAC address is using the address. Stop using it, and
+    /// choose another address and [AddressConflictDetector::restart].
+    Conflict(MacAddr),
+}
+
+/// Conflict detection state machine for one address
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AddressConflictDetector {
+    macaddr: MacAddr,
+    ipaddr: IpV4Addr,
+    state: AcdState,
+    /// Time of the next probe or announcement
+    next_at: u64,
+    /// Time we last defended the address, if ever
+    defended_at: Option<u64>,
+    conflicts: u8,
+    defenses: u8,
+    rng: XorShift32,
+}
+
+impl AddressConflictDetector {
+    /// Start checking an address.
+    ///
+    /// The seed randomizes probe timing, and should differ between hosts, for example
+    /// by being derived from the MAC address or a hardware random number generator.
+    pub fn new(macaddr: MacAddr, ipaddr: IpV4Addr, seed: u32, now: u64) -> Self {
+        let mut rng = XorShift32::new(seed);
+        let next_at = now.saturating_add(rng.range(0, PROBE_WAIT_MS));
+        AddressConflictDetector {
+            macaddr,
+            ipaddr,
+            state: AcdState::Probing(0),
+            next_at,
+            defended_at: None,
+            conflicts: 0,
+            defenses: 0,
+            rng,
+        }
+    }
+
+    /// Start checking a different address, as after a conflict.
+    /// After [MAX_CONFLICTS] conflicts, probing only starts once per [RATE_LIMIT_INTERVAL_MS].
+    pub fn restart(&mut self, ipaddr: IpV4Addr, now: u64) {
+        let wait = match self.conflicts >= MAX_CONFLICTS {
+            true => RATE_LIMIT_INTERVAL_MS,
+            false => self.rng.range(0, PROBE_WAIT_MS),
+        };
+        self.ipaddr = ipaddr;
+        self.state = AcdState::Probing(0);
+        self.next_at = now.saturating_add(wait);
+        self.defended_at = None;
+    }
+
+    /// Address being checked or claimed
+    pub fn ipaddr(&self) -> IpV4Addr {
+        self.ipaddr
+    }
+
+    /// Progress of claiming the address
+    pub fn state(&self) -> AcdState {
+        self.state
+    }
+
+    /// Number of conflicts seen since this detector was created
+    pub fn conflicts(&self) -> u8 {
+        self.conflicts
+    }
+
+    /// Number of times a claimed address was defended since this detector was created.
+    /// A successful defense is not a conflict, and does not count toward [MAX_CONFLICTS].
+    pub fn defenses(&self) -> u8 {
+        self.defenses
+    }
+
+    /// Whether the address has been claimed and may be used
+    pub fn is_claimed(&self) -> bool {
+        matches!(self.state, AcdState::Announcing(_) | AcdState::Bound)
+    }
+
+    /// Time at which [AddressConflictDetector::poll] next has something to do, if any
+    pub fn next_at(&self) -> Option<u64> {
+        match self.state {
+            AcdState::Probing(_) | AcdState::Announcing(_) => Some(self.next_at),
+            _ => None,
+        }
+    }
+
+    /// Send probes and announcements when they are due
+    pub fn poll(&mut self, now: u64) -> AcdEvent {
+        if now < self.next_at {
+            return AcdEvent::None;
+        }
+
+        match self.state {
+            AcdState::Probing(sent) if sent < PROBE_NUM => {
+                self.state = AcdState::Probing(sent + 1);
+                self.next_at = match sent + 1 < PROBE_NUM {
+                    true => now.saturating_add(self.rng.range(PROBE_MIN_MS, PROBE_MAX_MS)),
+                    false => now.saturating_add(ANNOUNCE_WAIT_MS),
+                };
+                AcdEvent::Transmit(EthernetFrame::arp_probe(self.macaddr, self.ipaddr))
+            }
+            AcdState::Probing(_) => {
+                self.state = AcdState::Announcing(1);
+                self.next_at = now.saturating_add(ANNOUNCE_INTERVAL_MS);
+                AcdEvent::Claimed(self.announcement())
+            }
+            AcdState::Announcing(sent) => {
+                self.state = match sent + 1 >= ANNOUNCE_NUM {
+                    true => AcdState::Bound,
+                    false => AcdState::Announcing(sent + 1),
+                };
+                self.next_at = now.saturating_add(ANNOUNCE_INTERVAL_MS);
+                AcdEvent::Transmit(self.announcement())
+            }
+            AcdState::Bound | AcdState::Conflict => AcdEvent::None,
+        }
+    }
+
+    /// Check a received ARP message for conflicts with our address
+    pub fn process(&mut self, msg: &ArpPayload, now: u64) -> AcdEvent {
+        if msg.validate().is_err() || msg.src_mac == self.macaddr {
+            return AcdEvent::None;
+        }
+        let uses_address = msg.src_ipaddr == self.ipaddr;
+        // Another host probing for the same address at the same time
+        let probes_address = msg.src_ipaddr == IpV4Addr::ANY
+            && msg.dst_ipaddr == self.ipaddr
+            && msg.operation == ArpOperation::Request;
+
+        match self.state {
+            AcdState::Probing(_) if uses_address || probes_address => self.conflict(msg.src_mac),
+            AcdState::Announcing(_) | AcdState::Bound if uses_address => {
+                let recently_defended = match self.defended_at {
+                    Some(t) => now.saturating_sub(t) < DEFEND_INTERVAL_MS,
+                    None => false,
+                };
+                match recently_defended {
+                    true => self.conflict(msg.src_mac),
+                    false => {
+                        self.defended_at = Some(now);
+                        self.defenses = self.defenses.saturating_add(1);
+                        AcdEvent::Defend(msg.src_mac, self.announcement())
+                    }
+                }
+            }
+            _ => AcdEvent::None,
+        }
+    }
+
+    fn conflict(&mut self, macaddr: MacAddr) -> AcdEvent {
+        self.state = AcdState::Conflict;
+        self.conflicts = self.conflicts.saturating_add(1);
+        AcdEvent::Conflict(macaddr)
+    }
+
+    fn announcement(&self) -> EthernetFrame<ArpPayload> {
+        EthernetFrame::arp_announce(self.macaddr, self.ipaddr)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const OUR_MAC: MacAddr = ByteArray([0x02, 0, 0, 0, 0, 1]);
+    const OTHER_MAC: MacAddr = ByteArray([0x02, 0, 0, 0, 0, 2]);
+    const IP: IpV4Addr = ByteArray([10, 0, 0, 1]);
+
+    /// Poll every millisecond until something happens
+    fn next_event(acd: &mut AddressConflictDetector, now: &mut u64) -> AcdEvent {
+        loop {
+            *now += 1;
+            match acd.poll(*now) {
+                AcdEvent::None => {}
+                x => return x,
+            }
+        }
+    }
+
+    #[test]
+    fn test_claim() {
+        let mut acd = AddressConflictDetector::new(OUR_MAC, IP, 1, 0);
+        let mut now = 0;
+        let mut times = [0; 6];
+
+        for t in times.iter_mut().take(3) {
+            assert!(matches!(
+                next_event(&mut acd, &mut now),
+                AcdEvent::Transmit(_)
+            ));
+            *t = now;
+        }
+        assert!(!acd.is_claimed());
+        assert!(matches!(
+            next_event(&mut acd, &mut now),
+            AcdEvent::Claimed(_)
+        ));
+        times[3] = now;
+        assert!(acd.is_claimed());
+        assert!(matches!(
+            next_event(&mut acd, &mut now),
+            AcdEvent::Transmit(_)
+        ));
+        times[4] = now;
+        assert_eq!(acd.state(), AcdState::Bound);
+        assert_eq!(acd.next_at(), None);
+
+        // Timing is within the limits of RFC 5227
+        assert!(times[0] <= PROBE_WAIT_MS + 1);
+        for i in 1..3 {
+            let delay = times[i] - times[i - 1];
+            assert!((PROBE_MIN_MS..=PROBE_MAX_MS).contains(&delay));
+        }
+        assert_eq!(times[3] - times[2], ANNOUNCE_WAIT_MS);
+        assert_eq!(times[4] - times[3], ANNOUNCE_INTERVAL_MS);
+
+        // Delays depend on the seed
+        let other = AddressConflictDetector::new(OUR_MAC, IP, 2, 0);
+        assert_ne!(
+            other.next_at(),
+            AddressConflictDetector::new(OUR_MAC, IP, 1, 0).next_at()
+        );
+    }
+
+    #[test]
+    fn test_conflict_while_probing() {
+        let mut now = 0;
+
+        // Another host already using the address
+        let mut acd = AddressConflictDetector::new(OUR_MAC, IP, 1, 0);
+        next_event(&mut acd, &mut now);
+        let reply = ArpPayload::new(
+            OTHER_MAC,
+            IP,
+            OUR_MAC,
+            IpV4Addr::ANY,
+            ArpOperation::Response,
+        );
+        assert_eq!(acd.process(&reply, now), AcdEvent::Conflict(OTHER_MAC));
+        assert_eq!(acd.state(), AcdState::Conflict);
+        assert_eq!(acd.poll(now + 10_000), AcdEvent::None);
+
+        // Another host probing for the same address
+        let other_ip = IpV4Addr::new([10, 0, 0, 2]);
+        acd.restart(other_ip, now);
+        next_event(&mut acd, &mut now);
+        let probe = ArpPayload::probe(OTHER_MAC, other_ip);
+        assert_eq!(acd.process(&probe, now), AcdEvent::Conflict(OTHER_MAC));
+        assert_eq!(acd.conflicts(), 2);
+
+        // Our own probes and unrelated traffic are not conflicts
+        acd.restart(other_ip, now);
+        assert_eq!(
+            acd.process(&ArpPayload::probe(OUR_MAC, other_ip), now),
+            AcdEvent::None
+        );
+        let request = ArpPayload::new(
+            OTHER_MAC,
+            IP,
+            MacAddr::ANY,
+            IpV4Addr::new([10, 0, 0, 3]),
+            ArpOperation::Request,
+        );
+        assert_eq!(acd.process(&request, now), AcdEvent::None);
+
+        // Repeated conflicts slow down probing
+        for _ in 0..MAX_CONFLICTS {
+            acd.process(&probe, now);
+            acd.restart(other_ip, now);
+        }
+        assert_eq!(acd.next_at(), Some(now + RATE_LIMIT_INTERVAL_MS));
+    }
+
+    #[test]
+    fn test_defend() {
+        let mut acd = AddressConflictDetector::new(OUR_MAC, IP, 1, 0);
+        let mut now = 0;
+        while acd.state() != AcdState::Bound {
+            next_event(&mut acd, &mut now);
+        }
+
+        // First conflict is defended, and a second one soon after gives the address up
+        let announce = ArpPayload::announce(OTHER_MAC, IP);
+        assert_eq!(
+            acd.process(&announce, now),
+            AcdEvent::Defend(OTHER_MAC, EthernetFrame::arp_announce(OUR_MAC, IP))
+        );
+        assert_eq!(
+            acd.process(&announce, now + 1_000),
+            AcdEvent::Conflict(OTHER_MAC)
+        );
+        assert!(!acd.is_claimed());
+        assert_eq!((acd.defenses(), acd.conflicts()), (1, 1));
+
+        // Conflicts far enough apart are each defended
+        let mut acd = AddressConflictDetector::new(OUR_MAC, IP, 1, 0);
+        while acd.state() != AcdState::Bound {
+            next_event(&mut acd, &mut now);
+        }
+        assert!(matches!(acd.process(&announce, now), AcdEvent::Defend(..)));
+        for _ in 1..MAX_CONFLICTS {
+            now += DEFEND_INTERVAL_MS;
+            assert!(matches!(acd.process(&announce, now), AcdEvent::Defend(..)));
+        }
+        assert_eq!((acd.defenses(), acd.conflicts()), (MAX_CONFLICTS, 0));
+
+        // ... and don't rate-limit probing of the next address
+        acd.restart(IP, now);
+        assert!(acd.next_at() < Some(now + RATE_LIMIT_INTERVAL_MS));
+    }
+}
@@ -18,12 +18,12 @@
 //! because ARP allows each host on the network to poll the others to check if an address is already taken before assigning
 //! that address to itself. The success of that method requires that all devices on the network be configured to respond to ARP requests,
 //! or to listen for conflicts and resolve them proactively, which is not necessarily the case.
-//! 
+//!
 //! In any case, most network stacks that we might interact with seem to refuse to function without it.
 //!
 //! ```rust
 //! use catnip::*;
-//! 
+//!
 //! let msg = ArpPayload::new(
 //!    MacAddr::new([1, 2, 3, 4, 5, 6]),
 //!    IpV4Addr::new([7, 8, 9, 10]),
@@ -31,10 +31,10 @@
 //!    IpV4Addr::new([17, 18, 19, 20]),
 //!    ArpOperation::Request,
 //! );
-//! 
+//!
 //! // Serialize
 //! let bytes: [u8; ArpPayload::BYTE_LEN] = msg.to_be_bytes();
-//! 
+//!
 //! // Deserialize
 //! let msg_parsed = ArpPayload::read_bytes(&bytes);
 //!
@@ -43,14 +43,17 @@
 
 use crate::*;
 
-use ufmt::derive::uDebug;
 use byte_struct::*;
 use static_assertions::const_assert;
+use ufmt::derive::uDebug;
 
-const_assert!(ArpPayload::BYTE_LEN == 46);  // Make sure the ARP frame is at least sized for the minimum ethernet payload
+/// Length of an ARP message for IPV4 on ethernet, without padding
+pub const ARP_MSG_LEN: usize = 28;
+
+const_assert!(ArpPayload::BYTE_LEN == 46); // Make sure the ARP frame is at least sized for the minimum ethernet payload
 
 /// An ARP request or response with IPV4 addresses and standard MAC addresses.
-/// Assumes 6-byte standard MAC addresses and 4-byte IPV4 addresses; this function can't be as general as the parser.
+/// Assumes 6-byte standard MAC addresses and 4-byte IPV4 addresses; this can't be as general as [ArpPacket]
 /// because we need to know the size of the output at compile time.
 /// See <https://en.wikipedia.org/wiki/Address_Resolution_Protocol>.
 #[derive(ByteStruct, Clone, Copy, uDebug, Debug, PartialEq, Eq, PartialOrd, Ord)]
@@ -76,7 +79,7 @@ pub struct ArpPayload {
     pub dst_ipaddr: IpV4Addr,
     /// Pad to minimum frame size
     _pad0: u128,
-    _pad1: u16
+    _pad1: u16,
 }
 
 impl ArpPayload {
@@ -89,17 +92,17 @@ impl ArpPayload {
         operation: ArpOperation,
     ) -> Self {
         ArpPayload {
-            htype: 1,  // Always on ethernet
-            ptype: ProtocolType::IpV4,  // Always resolving an IPV4 address
+            htype: 1,               // Always on ethernet
+            ptype: EtherType::IpV4, // Always resolving an IPV4 address
             hlen: 6,
             plen: 4,
-            operation: operation,
-            src_mac: src_mac,
-            src_ipaddr: src_ipaddr,
-            dst_mac: dst_mac,
-            dst_ipaddr: dst_ipaddr,
+            operation,
+            src_mac,
+            src_ipaddr,
+            dst_mac,
+            dst_ipaddr,
             _pad0: 0,
-            _pad1: 0
+            _pad1: 0,
         }
     }
 
@@ -109,6 +112,304 @@ impl ArpPayload {
         self.write_bytes(&mut bytes);
         bytes
     }
+
+    /// ARP probe per RFC 5227, asking whether anyone is using an address we would like to claim.
+    /// The sender IP address is 0.0.0.0 so that other hosts do not update their caches.
+    pub fn probe(src_mac: MacAddr, ipaddr: IpV4Addr) -> Self {
+        Self::new(
+            src_mac,
+            IpV4Addr::ANY,
+            MacAddr::ANY,
+            ipaddr,
+            ArpOperation::Request,
+        )
+    }
+
+    /// ARP announcement per RFC 5227, a request with our address as both sender and target
+    /// that claims the address and updates other hosts' caches
+    pub fn announce(src_mac: MacAddr, ipaddr: IpV4Addr) -> Self {
+        Self::new(src_mac, ipaddr, MacAddr::ANY, ipaddr, ArpOperation::Request)
+    }
+
+    /// Gratuitous ARP reply, an unsolicited response with our addresses as both sender and target
+    /// for hosts that only update their caches from responses
+    pub fn gratuitous_reply(src_mac: MacAddr, ipaddr: IpV4Addr) -> Self {
+        Self::new(src_mac, ipaddr, src_mac, ipaddr, ArpOperation::Response)
+    }
+
+    /// Check that the message is for ethernet hardware addresses and IPV4 protocol addresses
+    pub fn validate(&self) -> Result<(), ParseError> {
+        check_arp_format(self.htype, self.ptype as u16, self.hlen, self.plen)
+    }
+}
+
+impl TryByteStruct for ArpPayload {
+    /// Parse, rejecting anything other than ethernet hardware addresses and IPV4 protocol addresses,
+    /// since the fixed layout of this struct would misparse other address lengths.
+    fn try_read_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        let bytes = bytes.get(..Self::BYTE_LEN).ok_or(ParseError::Truncated)?;
+        // Check the raw protocol type, which would be lost converting an unknown value to [EtherType]
+        check_arp_format(
+            get_u16(bytes, 0),
+            get_u16(bytes, 2),
+            *bytes.get(4).unwrap_or(&0),
+            *bytes.get(5).unwrap_or(&0),
+        )?;
+
+        Ok(Self::read_bytes(bytes))
+    }
+}
+
+/// Hardware type for ethernet
+pub const ARP_HTYPE_ETHERNET: u16 = 1;
+
+/// Check that the address lengths are right for the hardware and protocol types,
+/// and that those are the ethernet and IPV4 types that [ArpPayload] can hold
+fn check_arp_format(htype: u16, ptype: u16, hlen: u8, plen: u8) -> Result<(), ParseError> {
+    let ethernet = htype == ARP_HTYPE_ETHERNET;
+    let ipv4 = ptype == EtherType::IpV4 as u16;
+    if (ethernet && hlen != 6) || (ipv4 && plen != 4) {
+        return Err(ParseError::BadArpFormat);
+    }
+    if !ethernet || !ipv4 {
+        return Err(ParseError::UnsupportedArp {
+            htype,
+            ptype,
+            hlen,
+            plen,
+        });
+    }
+
+    Ok(())
+}
+
+/// An ARP message with any hardware and protocol types, borrowing its addresses from the input.
+///
+/// The address fields are located using the hardware and protocol address lengths in the header,
+/// so this can read messages for other link layers or protocols that [ArpPayload] would misparse.
+/// Use [ArpPacket::to_payload] to get the fixed-layout message for ethernet and IPV4.
+#[derive(Clone, Copy, uDebug, Debug, PartialEq, Eq)]
+pub struct ArpPacket<'a> {
+    /// Hardware type (1 for ethernet)
+    pub htype: u16,
+    /// Protocol type, which may not be one of the [EtherType] values handled here
+    pub ptype: u16,
+    /// ARP operation type
+    pub operation: ArpOperation,
+    /// Source hardware address
+    pub src_hwaddr: &'a [u8],
+    /// Source protocol address
+    pub src_protoaddr: &'a [u8],
+    /// Destination hardware address
+    pub dst_hwaddr: &'a [u8],
+    /// Destination protocol address
+    pub dst_protoaddr: &'a [u8],
+}
+
+impl<'a> ArpPacket<'a> {
+    /// Parse a message of any type, checking only that the addresses fit in the input
+    /// and that their lengths are right for the types known here.
+    /// Anything after the addresses, such as padding to the minimum ethernet payload size, is ignored.
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, ParseError> {
+        let header = bytes.get(..8).ok_or(ParseError::Truncated)?;
+        let htype = get_u16(header, 0);
+        let ptype = get_u16(header, 2);
+        let hlen = *header.get(4).unwrap_or(&0);
+        let plen = *header.get(5).unwrap_or(&0);
+        // Types we don't handle are fine here, but not wrong address lengths for types we do
+        if let Err(ParseError::BadArpFormat) = check_arp_format(htype, ptype, hlen, plen) {
+            return Err(ParseError::BadArpFormat);
+        }
+        let (hlen, plen) = (hlen as usize, plen as usize);
+
+        let mut offset = 8;
+        let mut take = |len: usize| {
+            let field = bytes.get(offset..offset + len);
+            offset += len;
+            field.ok_or(ParseError::Truncated)
+        };
+        Ok(ArpPacket {
+            htype,
+            ptype,
+            operation: ArpOperation::from(get_u16(header, 6)),
+            src_hwaddr: take(hlen)?,
+            src_protoaddr: take(plen)?,
+            dst_hwaddr: take(hlen)?,
+            dst_protoaddr: take(plen)?,
+        })
+    }
+
+    /// Hardware address length
+    pub fn hlen(&self) -> u8 {
+        self.src_hwaddr.len() as u8
+    }
+
+    /// Protocol address length
+    pub fn plen(&self) -> u8 {
+        self.src_protoaddr.len() as u8
+    }
+
+    /// Protocol type as an [EtherType], which is [EtherType::Unimplemented] for values not handled here
+    pub fn protocol_type(&self) -> EtherType {
+        EtherType::from(self.ptype)
+    }
+
+    /// Length of the message without padding
+    pub fn len(&self) -> usize {
+        8 + 2 * (self.src_hwaddr.len() + self.src_protoaddr.len())
+    }
+
+    /// Whether the message has no addresses, which is never the case for a real message
+    pub fn is_empty(&self) -> bool {
+        self.src_hwaddr.is_empty() && self.src_protoaddr.is_empty()
+    }
+
+    /// Convert to the fixed-layout message, if this is for ethernet and IPV4
+    pub fn to_payload(&self) -> Result<ArpPayload, ParseError> {
+        check_arp_format(self.htype, self.ptype, self.hlen(), self.plen())?;
+
+        Ok(ArpPayload::new(
+            MacAddr::new(get_array(self.src_hwaddr, 0)),
+            IpV4Addr::new(get_array(self.src_protoaddr, 0)),
+            MacAddr::new(get_array(self.dst_hwaddr, 0)),
+            IpV4Addr::new(get_array(self.dst_protoaddr, 0)),
+            self.operation,
+        ))
+    }
+}
+
+impl EthernetFrame<ArpPayload> {
+    /// Ethernet frame from us carrying an ARP message
+    pub fn arp(dst_macaddr: MacAddr, msg: ArpPayload) -> Self {
+        EthernetFrame {
+            header: EthernetHeader {
+                dst_macaddr,
+                src_macaddr: msg.src_mac,
+                ethertype: EtherType::Arp,
+            },
+            data: msg,
+            checksum: 0,
+        }
+    }
+
+    /// Broadcast ARP probe. See [ArpPayload::probe].
+    pub fn arp_probe(src_mac: MacAddr, ipaddr: IpV4Addr) -> Self {
+        Self::arp(MacAddr::BROADCAST, ArpPayload::probe(src_mac, ipaddr))
+    }
+
+    /// Broadcast ARP announcement. See [ArpPayload::announce].
+    pub fn arp_announce(src_mac: MacAddr, ipaddr: IpV4Addr) -> Self {
+        Self::arp(MacAddr::BROADCAST, ArpPayload::announce(src_mac, ipaddr))
+    }
+
+    /// Broadcast gratuitous ARP reply. See [ArpPayload::gratuitous_reply].
+    pub fn gratuitous_arp_reply(src_mac: MacAddr, ipaddr: IpV4Addr) -> Self {
+        Self::arp(
+            MacAddr::BROADCAST,
+            ArpPayload::gratuitous_reply(src_mac, ipaddr),
+        )
+    }
+}
+
+/// Answers ARP requests for our address, and optionally, as a proxy,
+/// for up to `N` ranges of addresses belonging to hosts behind us
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ArpResponder<const N: usize = 0> {
+    /// Our MAC address, given in replies
+    pub macaddr: MacAddr,
+    /// Our IP address
+    pub ipaddr: IpV4Addr,
+    /// Other addresses to answer for with our MAC address
+    pub proxy: [IpV4Cidr; N],
+    answered: u32,
+    proxied: u32,
+}
+
+// Written out because the derive can't format an array of generic length
+impl<const N: usize> uDebug for ArpResponder<N> {
+    fn fmt<W>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error>
+    where
+        W: uWrite + ?Sized,
+    {
+        f.debug_struct("ArpResponder")?
+            .field("macaddr", &self.macaddr)?
+            .field("ipaddr", &self.ipaddr)?
+            .field("proxy", &self.proxy.as_slice())?
+            .field("answered", &self.answered)?
+            .field("proxied", &self.proxied)?
+            .finish()
+    }
+}
+
+impl ArpResponder {
+    /// New responder for our addresses only
+    pub fn new(macaddr: MacAddr, ipaddr: IpV4Addr) -> Self {
+        ArpResponder::proxy(macaddr, ipaddr, [])
+    }
+}
+
+impl<const N: usize> ArpResponder<N> {
+    /// New responder for our addresses that also answers for any address in the proxy ranges
+    pub fn proxy(macaddr: MacAddr, ipaddr: IpV4Addr, proxy: [IpV4Cidr; N]) -> Self {
+        ArpResponder {
+            macaddr,
+            ipaddr,
+            proxy,
+            answered: 0,
+            proxied: 0,
+        }
+    }
+
+    /// Number of requests for our own address that were answered
+    pub fn answered(&self) -> u32 {
+        self.answered
+    }
+
+    /// Number of requests for addresses in the proxy ranges that were answered
+    pub fn proxied(&self) -> u32 {
+        self.proxied
+    }
+
+    /// Build the reply to a received frame, addressed to the requester,
+    /// or `None` if the frame is not a valid ARP request for an address we answer for.
+    ///
+    /// Probes and announcements for proxied addresses are not answered,
+    /// so that the hosts behind us can claim their own addresses.
+    pub fn respond(
+        &mut self,
+        frame: &EthernetFrame<ArpPayload>,
+    ) -> Option<EthernetFrame<ArpPayload>> {
+        let msg = &frame.data;
+        if frame.header.ethertype != EtherType::Arp
+            || msg.validate().is_err()
+            || msg.operation != ArpOperation::Request
+        {
+            return None;
+        }
+
+        let target = msg.dst_ipaddr;
+        if self.ipaddr != IpV4Addr::ANY && target == self.ipaddr {
+            self.answered = self.answered.wrapping_add(1);
+        } else if msg.src_ipaddr != IpV4Addr::ANY
+            && msg.src_ipaddr != target
+            && self.proxy.iter().any(|x| x.contains(target))
+        {
+            self.proxied = self.proxied.wrapping_add(1);
+        } else {
+            return None;
+        }
+
+        Some(EthernetFrame::arp(
+            msg.src_mac,
+            ArpPayload::new(
+                self.macaddr,
+                target,
+                msg.src_mac,
+                msg.src_ipaddr,
+                ArpOperation::Response,
+            ),
+        ))
+    }
 }
 
 /// ARP request or response flag values
@@ -119,6 +420,10 @@ pub enum ArpOperation {
     Request = 1,
     /// This is a response to confirm our IP address and provide associated MAC address
     Response = 2,
+    /// RARP request for the IP address associated with the target MAC address
+    RarpRequest = 3,
+    /// RARP reply giving the IP address associated with the target MAC address
+    RarpReply = 4,
     /// Invalid operation
     Unimplemented,
 }
@@ -128,6 +433,8 @@ impl From<u16> for ArpOperation {
         match value {
             x if x == ArpOperation::Request as u16 => ArpOperation::Request,
             x if x == ArpOperation::Response as u16 => ArpOperation::Response,
+            x if x == ArpOperation::RarpRequest as u16 => ArpOperation::RarpRequest,
+            x if x == ArpOperation::RarpReply as u16 => ArpOperation::RarpReply,
             _ => ArpOperation::Unimplemented,
         }
     }
@@ -141,7 +448,7 @@ impl ByteStruct for ArpOperation {
     fn read_bytes(bytes: &[u8]) -> Self {
         let mut bytes_read = [0_u8; 2];
         bytes_read.copy_from_slice(&bytes[0..=1]);
-        return ArpOperation::from(u16::from_be_bytes(bytes_read));
+        ArpOperation::from(u16::from_be_bytes(bytes_read))
     }
 
     fn write_bytes(&self, bytes: &mut [u8]) {
@@ -151,6 +458,8 @@ impl ByteStruct for ArpOperation {
     }
 }
 
+impl TryByteStruct for ArpOperation {}
+
 impl ArpOperation {
     /// Convert to big-endian byte array
     pub fn to_be_bytes(&self) -> [u8; Self::BYTE_LEN] {
@@ -158,63 +467,378 @@ impl ArpOperation {
     }
 }
 
-/// Protocol Type flags are the same as EtherType but must be reimplemented to avoid run-time recursion.
+/// ARP protocol types are drawn from the same registry as ethernet's EtherType.
 /// See <https://en.wikipedia.org/wiki/EtherType>.
-#[derive(Clone, Copy, uDebug, Debug, PartialEq, Eq, PartialOrd, Ord)]
-#[repr(u16)]
-pub enum ProtocolType {
-    /// Internet protocol version 4
-    IpV4 = 0x0800,
-    /// Address resolution protocol
-    Arp = 0x0806,
-    /// Tagged virtual LAN - if this tag is encountered, then this is not the real ethertype field, and we're reading an 802.1Q Vlan tag instead
-    /// This crate does not support tagged Vlan, which is a trust-based and inefficient system. Untagged Vlan should be used instead.
-    Vlan = 0x8100,
-    /// Internet protocol version 6
-    IpV6 = 0x86DD,
-    /// EtherCat
-    EtherCat = 0x88A4,
-    /// Precision Time Protocol
-    Ptp = 0x88A7,
-    /// Catch-all for uncommon types not handled here
-    Unimplemented = 0x0,
-}
-
-impl From<u16> for ProtocolType {
-    fn from(value: u16) -> Self {
-        match value {
-            x if x == ProtocolType::Arp as u16 => ProtocolType::Arp,
-            x if x == ProtocolType::EtherCat as u16 => ProtocolType::EtherCat,
-            x if x == ProtocolType::IpV4 as u16 => ProtocolType::IpV4,
-            x if x == ProtocolType::IpV6 as u16 => ProtocolType::IpV6,
-            x if x == ProtocolType::Ptp as u16 => ProtocolType::Ptp,
-            x if x == ProtocolType::Vlan as u16 => ProtocolType::Vlan,
-            _ => ProtocolType::Unimplemented,
+pub type ProtocolType = EtherType;
+
+/// Borrowed view of an ARP message in a buffer, with typed access to fields in place.
+///
+/// Only the 28 bytes of ARP content are required; any padding to the minimum ethernet
+/// payload size is ignored. Because the address fields are located by their lengths,
+/// construction fails unless the message uses 6-byte hardware and 4-byte protocol addresses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ArpView<T: AsRef<[u8]>> {
+    buffer: T,
+}
+
+impl<T: AsRef<[u8]>> ArpView<T> {
+    /// Wrap a buffer, checking its length and the hardware and protocol address lengths
+    pub fn new(buffer: T) -> Result<Self, ParseError> {
+        let bytes = buffer.as_ref();
+        if bytes.len() < ARP_MSG_LEN {
+            return Err(ParseError::Truncated);
+        }
+        if bytes.get(4..6) != Some(&[6, 4]) {
+            return Err(ParseError::BadArpFormat);
         }
+
+        Ok(ArpView { buffer })
     }
-}
 
-impl ByteStructLen for ProtocolType {
-    const BYTE_LEN: usize = 2;
+    /// Release the underlying buffer
+    pub fn into_inner(self) -> T {
+        self.buffer
+    }
+
+    /// Hardware type (1 for ethernet)
+    pub fn htype(&self) -> u16 {
+        get_u16(self.buffer.as_ref(), 0)
+    }
+
+    /// Protocol type (same as ethertype from ethernet header)
+    pub fn ptype(&self) -> ProtocolType {
+        EtherType::from(get_u16(self.buffer.as_ref(), 2))
+    }
+
+    /// ARP operation type
+    pub fn operation(&self) -> ArpOperation {
+        ArpOperation::from(get_u16(self.buffer.as_ref(), 6))
+    }
+
+    /// Source MAC address
+    pub fn src_mac(&self) -> MacAddr {
+        MacAddr::new(get_array(self.buffer.as_ref(), 8))
+    }
+
+    /// Source IP address
+    pub fn src_ipaddr(&self) -> IpV4Addr {
+        IpV4Addr::new(get_array(self.buffer.as_ref(), 14))
+    }
+
+    /// Destination MAC address
+    pub fn dst_mac(&self) -> MacAddr {
+        MacAddr::new(get_array(self.buffer.as_ref(), 18))
+    }
+
+    /// Destination IP address
+    pub fn dst_ipaddr(&self) -> IpV4Addr {
+        IpV4Addr::new(get_array(self.buffer.as_ref(), 24))
+    }
 }
 
-impl ByteStruct for ProtocolType {
-    fn read_bytes(bytes: &[u8]) -> Self {
-        let mut bytes_read = [0_u8; 2];
-        bytes_read.copy_from_slice(&bytes[0..=1]);
-        return ProtocolType::from(u16::from_be_bytes(bytes_read));
+impl<T: AsRef<[u8]> + AsMut<[u8]>> ArpView<T> {
+    /// Set ARP operation type
+    pub fn set_operation(&mut self, value: ArpOperation) {
+        set_u16(self.buffer.as_mut(), 6, value as u16);
     }
 
-    fn write_bytes(&self, bytes: &mut [u8]) {
-        let bytes_to_write = (*self as u16).to_be_bytes();
-        bytes[0] = bytes_to_write[0];
-        bytes[1] = bytes_to_write[1];
+    /// Set source MAC address
+    pub fn set_src_mac(&mut self, value: MacAddr) {
+        set_array(self.buffer.as_mut(), 8, &value.0);
+    }
+
+    /// Set source IP address
+    pub fn set_src_ipaddr(&mut self, value: IpV4Addr) {
+        set_array(self.buffer.as_mut(), 14, &value.0);
+    }
+
+    /// Set destination MAC address
+    pub fn set_dst_mac(&mut self, value: MacAddr) {
+        set_array(self.buffer.as_mut(), 18, &value.0);
+    }
+
+    /// Set destination IP address
+    pub fn set_dst_ipaddr(&mut self, value: IpV4Addr) {
+        set_array(self.buffer.as_mut(), 24, &value.0);
     }
 }
 
-impl ProtocolType {
-    /// Pack into big-endian (network) byte array
-    pub fn to_be_bytes(&self) -> [u8; Self::BYTE_LEN] {
-        (*self as u16).to_be_bytes()
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_try_read_arp() {
+        let msg = ArpPayload::new(
+            MacAddr::new([1, 2, 3, 4, 5, 6]),
+            IpV4Addr::new([7, 8, 9, 10]),
+            MacAddr::new([11, 12, 13, 14, 15, 16]),
+            IpV4Addr::new([17, 18, 19, 20]),
+            ArpOperation::Request,
+        );
+        let bytes = msg.to_be_bytes();
+        assert_eq!(ArpPayload::try_read_bytes(&bytes), Ok(msg));
+        assert_eq!(
+            ArpPayload::try_read_bytes(&bytes[..27]),
+            Err(ParseError::Truncated)
+        );
+
+        // Hardware address length that this layout can't represent
+        let mut bad = bytes;
+        bad[4] = 8;
+        assert_eq!(
+            ArpPayload::try_read_bytes(&bad),
+            Err(ParseError::BadArpFormat)
+        );
+
+        // Valid message for a protocol this layout doesn't hold
+        let mut bad = bytes;
+        bad[2..4].copy_from_slice(&0x1234_u16.to_be_bytes());
+        assert_eq!(
+            ArpPayload::try_read_bytes(&bad),
+            Err(ParseError::UnsupportedArp {
+                htype: 1,
+                ptype: 0x1234,
+                hlen: 6,
+                plen: 4
+            })
+        );
+    }
+
+    #[test]
+    fn test_arp_view() {
+        let msg = ArpPayload::new(
+            MacAddr::new([1, 2, 3, 4, 5, 6]),
+            IpV4Addr::new([7, 8, 9, 10]),
+            MacAddr::ANY,
+            IpV4Addr::new([17, 18, 19, 20]),
+            ArpOperation::Request,
+        );
+        let mut bytes = msg.to_be_bytes();
+
+        let view = ArpView::new(&bytes[..ARP_MSG_LEN]).unwrap();
+        assert_eq!(view.operation(), ArpOperation::Request);
+        assert_eq!(view.src_mac(), msg.src_mac);
+        assert_eq!(view.src_ipaddr(), msg.src_ipaddr);
+        assert_eq!(view.dst_ipaddr(), msg.dst_ipaddr);
+
+        let mut view = ArpView::new(&mut bytes[..]).unwrap();
+        view.set_operation(ArpOperation::Response);
+        view.set_dst_mac(MacAddr::BROADCAST);
+        let parsed = ArpPayload::read_bytes(&bytes);
+        assert_eq!(parsed.operation, ArpOperation::Response);
+        assert_eq!(parsed.dst_mac, MacAddr::BROADCAST);
+    }
+
+    #[test]
+    fn test_arp_responder() {
+        let our_mac = MacAddr::new([0x02, 0, 0, 0, 0, 1]);
+        let our_ip = IpV4Addr::new([10, 0, 0, 1]);
+        let peer_mac = MacAddr::new([0x02, 0, 0, 0, 0, 2]);
+        let peer_ip = IpV4Addr::new([10, 0, 0, 2]);
+        let mut responder = ArpResponder::new(our_mac, our_ip);
+        let mut request = EthernetFrame {
+            header: EthernetHeader {
+                dst_macaddr: MacAddr::BROADCAST,
+                src_macaddr: peer_mac,
+                ethertype: EtherType::Arp,
+            },
+            data: ArpPayload::new(
+                peer_mac,
+                peer_ip,
+                MacAddr::ANY,
+                our_ip,
+                ArpOperation::Request,
+            ),
+            checksum: 0,
+        };
+
+        // Reply is addressed back to the requester
+        let reply = responder.respond(&request).unwrap();
+        assert_eq!(reply.header.dst_macaddr, peer_mac);
+        assert_eq!(reply.header.src_macaddr, our_mac);
+        assert_eq!(
+            reply.data,
+            ArpPayload::new(our_mac, our_ip, peer_mac, peer_ip, ArpOperation::Response)
+        );
+
+        // Requests for other addresses, responses, and other address formats are ignored
+        request.data.dst_ipaddr = peer_ip;
+        assert_eq!(responder.respond(&request), None);
+        request.data.dst_ipaddr = our_ip;
+        request.data.operation = ArpOperation::Response;
+        assert_eq!(responder.respond(&request), None);
+        request.data.operation = ArpOperation::Request;
+        request.data.hlen = 8;
+        assert_eq!(request.data.validate(), Err(ParseError::BadArpFormat));
+        assert_eq!(responder.respond(&request), None);
+    }
+
+    #[test]
+    fn test_arp_announcements() {
+        let mac = MacAddr::new([0x02, 0, 0, 0, 0, 1]);
+        let ip = IpV4Addr::new([10, 0, 0, 1]);
+
+        let probe = EthernetFrame::arp_probe(mac, ip);
+        assert_eq!(probe.header.dst_macaddr, MacAddr::BROADCAST);
+        assert_eq!(probe.header.src_macaddr, mac);
+        assert_eq!(probe.header.ethertype, EtherType::Arp);
+        assert_eq!(probe.data.operation, ArpOperation::Request);
+        assert_eq!(probe.data.src_ipaddr, IpV4Addr::ANY);
+        assert_eq!(probe.data.dst_mac, MacAddr::ANY);
+        assert_eq!(probe.data.dst_ipaddr, ip);
+
+        let announce = EthernetFrame::arp_announce(mac, ip);
+        assert_eq!(announce.header.dst_macaddr, MacAddr::BROADCAST);
+        assert_eq!(announce.data.operation, ArpOperation::Request);
+        assert_eq!(announce.data.src_ipaddr, ip);
+        assert_eq!(announce.data.dst_mac, MacAddr::ANY);
+        assert_eq!(announce.data.dst_ipaddr, ip);
+
+        let reply = EthernetFrame::gratuitous_arp_reply(mac, ip);
+        assert_eq!(reply.header.dst_macaddr, MacAddr::BROADCAST);
+        assert_eq!(reply.data.operation, ArpOperation::Response);
+        assert_eq!(reply.data.src_ipaddr, ip);
+        assert_eq!(reply.data.dst_mac, mac);
+        assert_eq!(reply.data.dst_ipaddr, ip);
+
+        // All are well-formed on the wire
+        for frame in [probe, announce, reply] {
+            let bytes = frame.to_be_bytes();
+            assert_eq!(
+                EthernetFrame::<ArpPayload>::try_read_bytes(&bytes),
+                Ok(frame)
+            );
+        }
+    }
+
+    #[test]
+    fn test_proxy_arp() {
+        let our_mac = MacAddr::new([0x02, 0, 0, 0, 0, 1]);
+        let our_ip = IpV4Addr::new([10, 0, 0, 1]);
+        let peer_mac = MacAddr::new([0x02, 0, 0, 0, 0, 2]);
+        let peer_ip = IpV4Addr::new([10, 0, 0, 2]);
+        let mut responder = ArpResponder::proxy(
+            our_mac,
+            our_ip,
+            [
+                IpV4Cidr::new(IpV4Addr::new([10, 0, 1, 0]), 24),
+                IpV4Cidr::single(IpV4Addr::new([10, 0, 0, 50])),
+            ],
+        );
+        let request = |dst_ipaddr| {
+            EthernetFrame::arp(
+                MacAddr::BROADCAST,
+                ArpPayload::new(
+                    peer_mac,
+                    peer_ip,
+                    MacAddr::ANY,
+                    dst_ipaddr,
+                    ArpOperation::Request,
+                ),
+            )
+        };
+
+        // Addresses in the configured ranges are answered with our MAC address
+        for ip in [[10, 0, 1, 7], [10, 0, 1, 255], [10, 0, 0, 50]] {
+            let reply = responder.respond(&request(IpV4Addr::new(ip))).unwrap();
+            assert_eq!(reply.header.dst_macaddr, peer_mac);
+            assert_eq!(reply.data.src_mac, our_mac);
+            assert_eq!(reply.data.src_ipaddr, IpV4Addr::new(ip));
+            assert_eq!(reply.data.dst_ipaddr, peer_ip);
+        }
+        assert_eq!(responder.proxied(), 3);
+
+        // Our own address is answered as usual, and others are not
+        assert!(responder.respond(&request(our_ip)).is_some());
+        assert!(responder
+            .respond(&request(IpV4Addr::new([10, 0, 2, 7])))
+            .is_none());
+        assert!(responder
+            .respond(&request(IpV4Addr::new([10, 0, 0, 51])))
+            .is_none());
+        assert_eq!(responder.answered(), 1);
+        assert_eq!(responder.proxied(), 3);
+
+        // Probes are left for the proxied host to answer
+        let probe = EthernetFrame::arp_probe(peer_mac, IpV4Addr::new([10, 0, 1, 7]));
+        assert!(responder.respond(&probe).is_none());
+    }
+
+    #[test]
+    fn test_arp_packet() {
+        // Ethernet and IPV4 matches the fixed layout
+        let msg = ArpPayload::new(
+            MacAddr::new([1, 2, 3, 4, 5, 6]),
+            IpV4Addr::new([7, 8, 9, 10]),
+            MacAddr::new([11, 12, 13, 14, 15, 16]),
+            IpV4Addr::new([17, 18, 19, 20]),
+            ArpOperation::Response,
+        );
+        let bytes = msg.to_be_bytes();
+        let packet = ArpPacket::parse(&bytes).unwrap();
+        assert_eq!(packet.len(), ARP_MSG_LEN);
+        assert_eq!(packet.protocol_type(), EtherType::IpV4);
+        assert_eq!(packet.src_hwaddr, &[1, 2, 3, 4, 5, 6]);
+        assert_eq!(packet.dst_protoaddr, &[17, 18, 19, 20]);
+        assert_eq!(packet.to_payload(), Ok(msg));
+
+        // IPV6 addresses on ethernet are located by their lengths
+        let mut bytes = [0_u8; 52];
+        bytes[..8].copy_from_slice(&[0, 1, 0x86, 0xDD, 6, 16, 0, 1]);
+        bytes[8..14].copy_from_slice(&[1; 6]);
+        bytes[14..30].copy_from_slice(&[2; 16]);
+        bytes[30..36].copy_from_slice(&[3; 6]);
+        bytes[36..52].copy_from_slice(&[4; 16]);
+        let packet = ArpPacket::parse(&bytes).unwrap();
+        assert_eq!((packet.hlen(), packet.plen()), (6, 16));
+        assert_eq!(packet.protocol_type(), EtherType::IpV6);
+        assert_eq!(packet.operation, ArpOperation::Request);
+        assert_eq!(packet.src_protoaddr, &[2; 16]);
+        assert_eq!(packet.dst_hwaddr, &[3; 6]);
+        assert_eq!(packet.dst_protoaddr, &[4; 16]);
+        assert_eq!(
+            packet.to_payload(),
+            Err(ParseError::UnsupportedArp {
+                htype: 1,
+                ptype: 0x86DD,
+                hlen: 6,
+                plen: 16
+            })
+        );
+        assert_eq!(ArpPacket::parse(&bytes[..51]), Err(ParseError::Truncated));
+
+        // Address lengths that contradict a known type
+        bytes[4] = 8;
+        assert_eq!(ArpPacket::parse(&bytes), Err(ParseError::BadArpFormat));
+    }
+
+    #[test]
+    fn test_arp_responder_udebug() {
+        extern crate std;
+        use std::string::String;
+
+        struct Buffer(String);
+
+        impl uWrite for Buffer {
+            type Error = core::convert::Infallible;
+
+            fn write_str(&mut self, s: &str) -> Result<(), Self::Error> {
+                self.0.push_str(s);
+                Ok(())
+            }
+        }
+
+        let responder = ArpResponder::proxy(
+            MacAddr::new([0x02, 0, 0, 0, 0, 1]),
+            IpV4Addr::new([10, 0, 0, 1]),
+            [IpV4Cidr::new(IpV4Addr::new([10, 0, 1, 0]), 24)],
+        );
+        let mut buffer = Buffer(String::new());
+        ufmt::uwrite!(buffer, "{:?}", responder).unwrap();
+        assert!(buffer.0.starts_with("ArpResponder { macaddr: "));
+        assert!(buffer
+            .0
+            .contains("proxy: [IpV4Cidr { network: [10, 0, 1, 0], prefix_len: 24 }]"));
     }
 }
@@ -0,0 +1,442 @@
+//! Fixed-capacity table of neighbors' hardware addresses learned from ARP.
+//!
+//! Entries move through the states described in [ArpState]:
+//! an entry is `Incomplete` while a request is outstanding, `Reachable` once an answer is received,
+//! and `Stale` once it has gone unconfirmed for longer than the timeout. Stale entries are still
+//! used for sending, but should be refreshed; they are removed after a second timeout.
+//!
+//! Static entries pinned by the application are never aged out, evicted, or changed by ARP traffic.
+//! An ARP message that contradicts a static entry or a recently confirmed entry is reported as an [ArpMismatch].
+//!
+//! When the table is full, a new entry replaces the oldest stale entry, or failing that
+//! the oldest incomplete entry, or failing that the oldest reachable entry. Ties go to the lowest index,
+//! so the result depends only on the sequence of updates and their times.
+//!
+//! ```rust
+//! use catnip::*;
+//!
+//! let mut cache: ArpCache<4> = ArpCache::new(1000);
+//! let ipaddr = IpV4Addr::new([10, 0, 0, 1]);
+//! let macaddr = MacAddr::new([0x02, 0, 0, 0, 0, 1]);
+//!
+//! cache.insert(ipaddr, macaddr, 0);
+//! assert_eq!(cache.lookup(ipaddr), Some(macaddr));
+//!
+//! // After the timeout, the entry is still usable but should be refreshed
+//! cache.age(1001);
+//! assert_eq!(cache.state(ipaddr), Some(ArpState::Stale));
+//! assert_eq!(cache.lookup(ipaddr), Some(macaddr));
+//!
+//! // After twice the timeout, it is gone
+//! cache.age(2001);
+//! assert_eq!(cache.lookup(ipaddr), None);
+//! ```
+
+use crate::*;
+
+/// Default time in milliseconds before an entry must be refreshed
+pub const ARP_CACHE_TIMEOUT_MS: u64 = 60_000;
+
+/// Resolution state of an [ArpCache] entry
+#[derive(Clone, Copy, uDebug, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ArpState {
+    /// A request has been sent and no answer received yet
+    Incomplete,
+    /// The hardware address was confirmed within the timeout
+    Reachable,
+    /// The hardware address has not been confirmed within the timeout
+    Stale,
+    /// The hardware address was pinned by the application, and is never aged out,
+    /// evicted, or changed by ARP traffic
+    Static,
+}
+
+/// A received ARP message gave a different hardware address for a neighbor than the one we have,
+/// which may mean that a host's network interface was replaced, or that another host is impersonating it
+#[derive(Clone, Copy, uDebug, Debug, PartialEq, Eq)]
+pub struct ArpMismatch {
+    /// Neighbor's IP address
+    pub ipaddr: IpV4Addr,
+    /// Hardware address we have for the neighbor
+    pub known_macaddr: MacAddr,
+    /// Hardware address in the received message
+    pub received_macaddr: MacAddr,
+    /// Whether the known address is a static entry, which was kept;
+    /// otherwise, the recently learned address was replaced
+    pub pinned: bool,
+}
+
+/// A neighbor's addresses and when they were last updated
+#[derive(Clone, Copy, uDebug, Debug, PartialEq, Eq)]
+pub struct ArpEntry {
+    /// Neighbor's IP address
+    pub ipaddr: IpV4Addr,
+    /// Neighbor's hardware address, or [MacAddr::ANY] if not yet resolved
+    pub macaddr: MacAddr,
+    /// Resolution state
+    pub state: ArpState,
+    /// Time in milliseconds the entry was created or last confirmed
+    pub updated_at: u64,
+}
+
+/// Table of up to `N` neighbors' hardware addresses.
+///
+/// Times are in milliseconds from an arbitrary monotonic epoch chosen by the application.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ArpCache<const N: usize> {
+    entries: [Option<ArpEntry>; N],
+    timeout: u64,
+}
+
+impl<const N: usize> ArpCache<N> {
+    /// New empty table with a timeout in milliseconds after which entries become stale
+    pub const fn new(timeout: u64) -> Self {
+        ArpCache {
+            entries: [None; N],
+            timeout,
+        }
+    }
+
+    /// Time in milliseconds after which entries become stale
+    pub fn timeout(&self) -> u64 {
+        self.timeout
+    }
+
+    /// Change the time in milliseconds after which entries become stale
+    pub fn set_timeout(&mut self, timeout: u64) {
+        self.timeout = timeout;
+    }
+
+    /// Entry for an IP address, if there is one
+    pub fn get(&self, ipaddr: IpV4Addr) -> Option<&ArpEntry> {
+        self.entries.iter().flatten().find(|x| x.ipaddr == ipaddr)
+    }
+
+    /// Resolution state of an IP address, if it has an entry
+    pub fn state(&self, ipaddr: IpV4Addr) -> Option<ArpState> {
+        self.get(ipaddr).map(|x| x.state)
+    }
+
+    /// Hardware address of a neighbor, if it has been resolved
+    pub fn lookup(&self, ipaddr: IpV4Addr) -> Option<MacAddr> {
+        match self.get(ipaddr) {
+            Some(x) if x.state != ArpState::Incomplete => Some(x.macaddr),
+            _ => None,
+        }
+    }
+
+    /// All entries, in table order
+    pub fn iter(&self) -> impl Iterator<Item = &ArpEntry> {
+        self.entries.iter().flatten()
+    }
+
+    /// Number of entries
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    /// Whether there are no entries
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Record a confirmed hardware address, creating or refreshing an entry.
+    /// Does nothing if the address has a static entry.
+    pub fn insert(&mut self, ipaddr: IpV4Addr, macaddr: MacAddr, now: u64) {
+        if self.state(ipaddr) == Some(ArpState::Static) {
+            return;
+        }
+        self.put(ArpEntry {
+            ipaddr,
+            macaddr,
+            state: ArpState::Reachable,
+            updated_at: now,
+        });
+    }
+
+    /// Pin a neighbor's hardware address, replacing any existing entry.
+    /// Returns `false` if there was no room because every entry is static.
+    pub fn insert_static(&mut self, ipaddr: IpV4Addr, macaddr: MacAddr) -> bool {
+        self.put(ArpEntry {
+            ipaddr,
+            macaddr,
+            state: ArpState::Static,
+            updated_at: 0,
+        })
+    }
+
+    /// Note that a request has been sent for an IP address.
+    /// Does nothing if the address already has an entry.
+    pub fn insert_incomplete(&mut self, ipaddr: IpV4Addr, now: u64) {
+        if self.get(ipaddr).is_none() {
+            self.put(ArpEntry {
+                ipaddr,
+                macaddr: MacAddr::ANY,
+                state: ArpState::Incomplete,
+                updated_at: now,
+            });
+        }
+    }
+
+    /// Update an existing entry's hardware address, returning whether there was one.
+    /// Used for traffic not addressed to us, which should refresh but not add entries.
+    /// Static entries are left unchanged.
+    pub fn update(&mut self, ipaddr: IpV4Addr, macaddr: MacAddr, now: u64) -> bool {
+        match self
+            .entries
+            .iter_mut()
+            .flatten()
+            .find(|x| x.ipaddr == ipaddr)
+        {
+            Some(x) if x.state == ArpState::Static => true,
+            Some(x) => {
+                x.macaddr = macaddr;
+                x.state = ArpState::Reachable;
+                x.updated_at = now;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Remove an entry, returning it if there was one
+    pub fn remove(&mut self, ipaddr: IpV4Addr) -> Option<ArpEntry> {
+        self.entries
+            .iter_mut()
+            .find(|x| matches!(x, Some(x) if x.ipaddr == ipaddr))
+            .and_then(|x| x.take())
+    }
+
+    /// Remove all entries, including static ones
+    pub fn clear(&mut self) {
+        self.entries = [None; N];
+    }
+
+    /// Learn from a received ARP message per RFC 826: refresh the sender's entry if
+    /// there is one, and add it if the message is addressed to our IP address.
+    ///
+    /// Returns a mismatch if the sender's hardware address contradicts a static or reachable entry.
+    pub fn process(
+        &mut self,
+        msg: &ArpPayload,
+        our_ipaddr: IpV4Addr,
+        now: u64,
+    ) -> Option<ArpMismatch> {
+        if msg.src_ipaddr == IpV4Addr::ANY {
+            return None; // Probes carry no address to learn
+        }
+        let mismatch = match self.get(msg.src_ipaddr) {
+            Some(x) if x.macaddr != msg.src_mac => match x.state {
+                ArpState::Static | ArpState::Reachable => Some(ArpMismatch {
+                    ipaddr: x.ipaddr,
+                    known_macaddr: x.macaddr,
+                    received_macaddr: msg.src_mac,
+                    pinned: x.state == ArpState::Static,
+                }),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        let updated = self.update(msg.src_ipaddr, msg.src_mac, now);
+        if !updated && our_ipaddr != IpV4Addr::ANY && msg.dst_ipaddr == our_ipaddr {
+            self.insert(msg.src_ipaddr, msg.src_mac, now);
+        }
+
+        mismatch
+    }
+
+    /// Mark entries older than the timeout as stale, and remove incomplete entries older than
+    /// the timeout and stale entries older than twice the timeout
+    pub fn age(&mut self, now: u64) {
+        let timeout = self.timeout;
+        for slot in self.entries.iter_mut() {
+            if let Some(x) = slot {
+                let age = now.saturating_sub(x.updated_at);
+                match x.state {
+                    ArpState::Incomplete if age > timeout => *slot = None,
+                    ArpState::Reachable if age > timeout => x.state = ArpState::Stale,
+                    ArpState::Stale if age > timeout.saturating_mul(2) => *slot = None,
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// Write an entry over the existing one for its IP address, an empty slot, or the evicted entry,
+    /// returning whether there was room
+    fn put(&mut self, entry: ArpEntry) -> bool {
+        let slot = self
+            .entries
+            .iter()
+            .position(|x| matches!(x, Some(x) if x.ipaddr == entry.ipaddr))
+            .or_else(|| self.entries.iter().position(|x| x.is_none()))
+            .or_else(|| self.evict());
+        match slot.and_then(|i| self.entries.get_mut(i)) {
+            Some(x) => {
+                *x = Some(entry);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Index of the entry to replace when the table is full, never a static entry
+    fn evict(&self) -> Option<usize> {
+        let priority = |state: ArpState| match state {
+            ArpState::Stale => Some(0),
+            ArpState::Incomplete => Some(1),
+            ArpState::Reachable => Some(2),
+            ArpState::Static => None,
+        };
+        self.entries
+            .iter()
+            .enumerate()
+            .filter_map(|(i, x)| x.and_then(|x| Some(((priority(x.state)?, x.updated_at), i))))
+            .min()
+            .map(|(_, i)| i)
+    }
+}
+
+impl<const N: usize> Default for ArpCache<N> {
+    fn default() -> Self {
+        Self::new(ARP_CACHE_TIMEOUT_MS)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_eviction() {
+        let ip = |x| IpV4Addr::new([10, 0, 0, x]);
+        let mac = |x| MacAddr::new([0x02, 0, 0, 0, 0, x]);
+        let mut cache: ArpCache<3> = ArpCache::new(100);
+
+        // Oldest reachable entry goes first, with ties broken by index
+        cache.insert(ip(1), mac(1), 10);
+        cache.insert(ip(2), mac(2), 10);
+        cache.insert(ip(3), mac(3), 20);
+        cache.insert(ip(4), mac(4), 30);
+        assert_eq!(cache.lookup(ip(1)), None);
+        assert_eq!(cache.lookup(ip(2)), Some(mac(2)));
+
+        // Incomplete entries go before reachable ones, and do not resolve
+        cache.insert_incomplete(ip(5), 40);
+        assert_eq!(cache.lookup(ip(2)), None);
+        assert_eq!(cache.state(ip(5)), Some(ArpState::Incomplete));
+        assert_eq!(cache.lookup(ip(5)), None);
+        cache.insert(ip(6), mac(6), 50);
+        assert_eq!(cache.get(ip(5)), None);
+
+        // Stale entries go first
+        cache.age(125);
+        assert_eq!(cache.state(ip(3)), Some(ArpState::Stale));
+        assert_eq!(cache.state(ip(4)), Some(ArpState::Reachable));
+        cache.insert_incomplete(ip(7), 125);
+        assert_eq!(cache.get(ip(3)), None);
+
+        // ... even before incomplete ones
+        cache.age(140);
+        assert_eq!(cache.state(ip(4)), Some(ArpState::Stale));
+        cache.insert(ip(8), mac(8), 140);
+        assert_eq!(cache.get(ip(4)), None);
+        assert_eq!(cache.state(ip(7)), Some(ArpState::Incomplete));
+        assert_eq!(cache.len(), 3);
+
+        // Incomplete entries expire after the timeout, and stale ones after twice the timeout
+        cache.age(226);
+        assert_eq!(cache.get(ip(7)), None);
+        assert_eq!(cache.state(ip(6)), Some(ArpState::Stale));
+        cache.age(251);
+        assert_eq!(cache.get(ip(6)), None);
+    }
+
+    #[test]
+    fn test_process() {
+        let our_ip = IpV4Addr::new([10, 0, 0, 1]);
+        let peer_ip = IpV4Addr::new([10, 0, 0, 2]);
+        let other_ip = IpV4Addr::new([10, 0, 0, 3]);
+        let peer_mac = MacAddr::new([0x02, 0, 0, 0, 0, 2]);
+        let mut cache: ArpCache<4> = ArpCache::default();
+
+        // Requests between other hosts are not learned
+        let msg = ArpPayload::new(
+            peer_mac,
+            peer_ip,
+            MacAddr::ANY,
+            other_ip,
+            ArpOperation::Request,
+        );
+        cache.process(&msg, our_ip, 0);
+        assert!(cache.is_empty());
+
+        // ... unless the sender is already known
+        cache.insert_incomplete(peer_ip, 0);
+        cache.process(&msg, our_ip, 1);
+        assert_eq!(cache.lookup(peer_ip), Some(peer_mac));
+
+        // Requests to us are learned
+        cache.clear();
+        let msg = ArpPayload::new(
+            peer_mac,
+            peer_ip,
+            MacAddr::ANY,
+            our_ip,
+            ArpOperation::Request,
+        );
+        cache.process(&msg, our_ip, 2);
+        assert_eq!(cache.get(peer_ip).map(|x| x.updated_at), Some(2));
+        assert_eq!(cache.remove(peer_ip).map(|x| x.macaddr), Some(peer_mac));
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_static() {
+        let our_ip = IpV4Addr::new([10, 0, 0, 1]);
+        let ip = |x| IpV4Addr::new([10, 0, 0, x]);
+        let mac = |x| MacAddr::new([0x02, 0, 0, 0, 0, x]);
+        let mut cache: ArpCache<2> = ArpCache::new(100);
+
+        // Static entries are not changed by the network, and contradictions are reported
+        assert!(cache.insert_static(ip(2), mac(2)));
+        let spoof = ArpPayload::new(mac(9), ip(2), MacAddr::ANY, our_ip, ArpOperation::Request);
+        assert_eq!(
+            cache.process(&spoof, our_ip, 0),
+            Some(ArpMismatch {
+                ipaddr: ip(2),
+                known_macaddr: mac(2),
+                received_macaddr: mac(9),
+                pinned: true
+            })
+        );
+        cache.insert(ip(2), mac(9), 0);
+        assert_eq!(cache.lookup(ip(2)), Some(mac(2)));
+
+        // ... or aged out, or evicted
+        cache.age(1_000_000);
+        assert_eq!(cache.state(ip(2)), Some(ArpState::Static));
+        cache.insert(ip(3), mac(3), 0);
+        cache.insert(ip(4), mac(4), 0);
+        assert_eq!(cache.lookup(ip(2)), Some(mac(2)));
+        assert_eq!(cache.lookup(ip(4)), Some(mac(4)));
+
+        // Contradicting a recently learned entry is reported, but the entry is updated
+        let msg = ArpPayload::new(mac(5), ip(4), MacAddr::ANY, our_ip, ArpOperation::Request);
+        assert!(matches!(
+            cache.process(&msg, our_ip, 1),
+            Some(ArpMismatch { pinned: false, .. })
+        ));
+        assert_eq!(cache.lookup(ip(4)), Some(mac(5)));
+
+        // Stale entries are not considered recent
+        cache.age(200);
+        let msg = ArpPayload::new(mac(6), ip(4), MacAddr::ANY, our_ip, ArpOperation::Request);
+        assert_eq!(cache.process(&msg, our_ip, 200), None);
+
+        // Table full of static entries
+        assert!(cache.insert_static(ip(4), mac(4)));
+        assert!(!cache.insert_static(ip(5), mac(5)));
+    }
+}
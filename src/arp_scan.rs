@@ -0,0 +1,321 @@
+//! Discovery of hosts on a subnet by ARP.
+//!
+//! [ArpScanner] sends an ARP request to each address in a CIDR range at a fixed interval,
+//! collects the replies, and completes a fixed time after the last request.
+//! Like the other protocol state machines, it does no I/O: the application sends the frames
+//! returned by [ArpScanner::poll] and passes received ARP messages to [ArpScanner::process].
+//!
+//! ```rust
+//! use catnip::*;
+//!
+//! let our_mac = MacAddr::new([0x02, 0xAF, 0xFF, 0x1A, 0xE5, 0x3C]);
+//! let our_ip = IpV4Addr::new([10, 0, 0, 120]);
+//! let mut scanner: ArpScanner<16> =
+//!     ArpScanner::new(our_mac, our_ip, IpV4Addr::new([10, 0, 0, 0]), 30, 10, 100, 0);
+//!
+//! // Requests go out to 10.0.0.1 and 10.0.0.2; 10.0.0.0 and 10.0.0.3 are the network and broadcast addresses
+//! let mut now = 0;
+//! loop {
+//!     match scanner.poll(now) {
+//!         ArpScanEvent::Transmit(frame) => {
+//!             // One of the hosts answers
+//!             if frame.data.dst_ipaddr == IpV4Addr::new([10, 0, 0, 2]) {
+//!                 let peer_mac = MacAddr::new([0x02, 0, 0, 0, 0, 2]);
+//!                 let reply = ArpPayload::new(peer_mac, frame.data.dst_ipaddr, our_mac, our_ip, ArpOperation::Response);
+//!                 scanner.process(&reply);
+//!             }
+//!         }
+//!         ArpScanEvent::Complete => break,
+//!         ArpScanEvent::None => now += 1,
+//!     }
+//! }
+//!
+//! assert_eq!(now, 110);
+//! assert_eq!(scanner.results().count(), 1);
+//! ```
+
+use crate::*;
+
+/// Something the application should act on
+#[derive(Clone, Copy, uDebug, Debug, PartialEq, Eq)]
+pub enum ArpScanEvent {
+    /// Nothing to do right now
+    None,
+    /// Send this request
+    Transmit(EthernetFrame<ArpPayload>),
+    /// Every address has been asked and the timeout has passed; the results are final
+    Complete,
+}
+
+/// Sweep of a CIDR range collecting up to `N` hosts' addresses
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ArpScanner<const N: usize> {
+    macaddr: MacAddr,
+    ipaddr: IpV4Addr,
+    /// First address to ask, as an integer
+    first: u32,
+    /// Next address to ask, as an integer, or `None` once every address has been asked
+    next: Option<u32>,
+    /// Last address to ask, as an integer
+    last: u32,
+    interval: u64,
+    timeout: u64,
+    next_at: u64,
+    complete: bool,
+    results: [Option<(IpV4Addr, MacAddr)>; N],
+    missed: usize,
+}
+
+impl<const N: usize> ArpScanner<N> {
+    /// Start a sweep from our addresses over the subnet `network/prefix_len`, sending one request
+    /// every `interval` milliseconds and completing `timeout` milliseconds after the last one.
+    ///
+    /// The network and broadcast addresses are skipped for prefixes shorter than 31 bits,
+    /// and our own address is always skipped.
+    pub fn new(
+        macaddr: MacAddr,
+        ipaddr: IpV4Addr,
+        network: IpV4Addr,
+        prefix_len: u8,
+        interval: u64,
+        timeout: u64,
+        now: u64,
+    ) -> Self {
+        let prefix_len = prefix_len.min(32) as u32;
+        let mask = u32::MAX.checked_shl(32 - prefix_len).unwrap_or(0);
+        let first = u32::from_be_bytes(network.0) & mask;
+        let last = first | !mask;
+        let (first, last) = match prefix_len < 31 {
+            true => (first + 1, last - 1),
+            false => (first, last),
+        };
+
+        ArpScanner {
+            macaddr,
+            ipaddr,
+            first,
+            next: next_candidate(first, last, ipaddr),
+            last,
+            interval,
+            timeout,
+            next_at: now,
+            complete: false,
+            results: [None; N],
+            missed: 0,
+        }
+    }
+
+    /// Whether the sweep has finished
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    /// Time at which [ArpScanner::poll] next has something to do, if any
+    pub fn next_at(&self) -> Option<u64> {
+        match self.complete {
+            true => None,
+            false => Some(self.next_at),
+        }
+    }
+
+    /// Hosts that have answered so far, in the order they answered
+    pub fn results(&self) -> impl Iterator<Item = (IpV4Addr, MacAddr)> + '_ {
+        self.results.iter().flatten().copied()
+    }
+
+    /// Number of hosts that answered but did not fit in the results
+    pub fn missed(&self) -> usize {
+        self.missed
+    }
+
+    /// Send the next request when it is due, or complete the sweep once the timeout has passed
+    pub fn poll(&mut self, now: u64) -> ArpScanEvent {
+        if self.complete || now < self.next_at {
+            return ArpScanEvent::None;
+        }
+
+        let next = match self.next {
+            Some(x) => x,
+            None => {
+                self.complete = true;
+                return ArpScanEvent::Complete;
+            }
+        };
+
+        self.next = next
+            .checked_add(1)
+            .and_then(|x| next_candidate(x, self.last, self.ipaddr));
+        self.next_at = match self.next {
+            Some(_) => now.saturating_add(self.interval),
+            None => now.saturating_add(self.timeout), // Last request; wait for stragglers
+        };
+
+        let dst_ipaddr = IpV4Addr::new(next.to_be_bytes());
+        ArpScanEvent::Transmit(EthernetFrame::arp(
+            MacAddr::BROADCAST,
+            ArpPayload::new(
+                self.macaddr,
+                self.ipaddr,
+                MacAddr::ANY,
+                dst_ipaddr,
+                ArpOperation::Request,
+            ),
+        ))
+    }
+
+    /// Record the sender of a received ARP reply to us, if it is an address we have asked
+    pub fn process(&mut self, msg: &ArpPayload) {
+        let src = u32::from_be_bytes(msg.src_ipaddr.0);
+        let asked = match self.next {
+            Some(next) => src < next,
+            None => true,
+        };
+        if self.complete
+            || msg.validate().is_err()
+            || msg.operation != ArpOperation::Response
+            || msg.dst_ipaddr != self.ipaddr
+            || msg.src_ipaddr == IpV4Addr::ANY
+            || msg.src_ipaddr == self.ipaddr
+            || src < self.first
+            || src > self.last
+            || !asked
+        {
+            return;
+        }
+        if self.results().any(|(ip, _)| ip == msg.src_ipaddr) {
+            return;
+        }
+
+        match self.results.iter_mut().find(|x| x.is_none()) {
+            Some(x) => *x = Some((msg.src_ipaddr, msg.src_mac)),
+            None => self.missed = self.missed.saturating_add(1),
+        }
+    }
+}
+
+/// First address to ask at or after `from`, skipping our own
+fn next_candidate(from: u32, last: u32, ipaddr: IpV4Addr) -> Option<u32> {
+    let x = match from == u32::from_be_bytes(ipaddr.0) {
+        true => from.checked_add(1)?,
+        false => from,
+    };
+    (x <= last).then_some(x)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const OUR_MAC: MacAddr = ByteArray([0x02, 0, 0, 0, 0, 1]);
+    const OUR_IP: IpV4Addr = ByteArray([192, 168, 1, 10]);
+
+    /// Run a sweep to completion with every host answering, returning the requested addresses
+    fn sweep<const N: usize>(scanner: &mut ArpScanner<N>) -> ([IpV4Addr; 256], usize, u64) {
+        let mut asked = [IpV4Addr::ANY; 256];
+        let mut n = 0;
+        let mut now = 0;
+        loop {
+            match scanner.poll(now) {
+                ArpScanEvent::Transmit(frame) => {
+                    assert_eq!(frame.header.dst_macaddr, MacAddr::BROADCAST);
+                    let ip = frame.data.dst_ipaddr;
+                    asked[n] = ip;
+                    n += 1;
+                    let mac = MacAddr::new([0x02, 0, 0, 0, ip.0[2], ip.0[3]]);
+                    scanner.process(&ArpPayload::new(
+                        mac,
+                        ip,
+                        OUR_MAC,
+                        OUR_IP,
+                        ArpOperation::Response,
+                    ));
+                }
+                ArpScanEvent::Complete => return (asked, n, now),
+                ArpScanEvent::None => now += 1,
+            }
+        }
+    }
+
+    #[test]
+    fn test_sweep() {
+        // /24 skips the network, broadcast, and our own addresses
+        let mut scanner: ArpScanner<300> = ArpScanner::new(
+            OUR_MAC,
+            OUR_IP,
+            IpV4Addr::new([192, 168, 1, 77]),
+            24,
+            5,
+            500,
+            0,
+        );
+        let (asked, n, now) = sweep(&mut scanner);
+        assert_eq!(n, 253);
+        assert_eq!(asked[0], IpV4Addr::new([192, 168, 1, 1]));
+        assert_eq!(asked[252], IpV4Addr::new([192, 168, 1, 254]));
+        assert!(!asked[..n].contains(&OUR_IP));
+        assert_eq!(now, 252 * 5 + 500);
+        assert_eq!(scanner.results().count(), 253);
+        assert_eq!(scanner.next_at(), None);
+
+        // Results beyond capacity are counted
+        let mut scanner: ArpScanner<4> = ArpScanner::new(
+            OUR_MAC,
+            OUR_IP,
+            IpV4Addr::new([192, 168, 1, 0]),
+            29,
+            1,
+            1,
+            0,
+        );
+        let (_, n, _) = sweep(&mut scanner);
+        assert_eq!(n, 6);
+        assert_eq!(scanner.results().count(), 4);
+        assert_eq!(scanner.missed(), 2);
+
+        // /32 asks the one address
+        let mut scanner: ArpScanner<4> = ArpScanner::new(
+            OUR_MAC,
+            OUR_IP,
+            IpV4Addr::new([192, 168, 1, 3]),
+            32,
+            1,
+            1,
+            0,
+        );
+        let (asked, n, _) = sweep(&mut scanner);
+        assert_eq!(&asked[..n], &[IpV4Addr::new([192, 168, 1, 3])]);
+
+        // Requests, unsolicited replies, and duplicate replies are ignored
+        let mut scanner: ArpScanner<4> = ArpScanner::new(
+            OUR_MAC,
+            OUR_IP,
+            IpV4Addr::new([192, 168, 1, 0]),
+            24,
+            1,
+            1,
+            0,
+        );
+        let mac = MacAddr::new([0x02, 0, 0, 0, 0, 2]);
+        let ip = IpV4Addr::new([192, 168, 1, 2]);
+        let reply = |ip| ArpPayload::new(mac, ip, OUR_MAC, OUR_IP, ArpOperation::Response);
+        scanner.process(&ArpPayload::new(
+            mac,
+            ip,
+            MacAddr::ANY,
+            OUR_IP,
+            ArpOperation::Request,
+        ));
+        scanner.process(&reply(ip)); // Not asked yet
+        assert_eq!(scanner.results().count(), 0);
+
+        assert!(matches!(scanner.poll(0), ArpScanEvent::Transmit(_)));
+        assert!(matches!(scanner.poll(1), ArpScanEvent::Transmit(_)));
+        scanner.process(&reply(ip));
+        scanner.process(&reply(ip));
+        scanner.process(&reply(IpV4Addr::new([192, 168, 1, 3]))); // Not asked yet
+        scanner.process(&reply(IpV4Addr::new([192, 168, 2, 1]))); // Outside the range
+        scanner.process(&reply(IpV4Addr::new([192, 168, 1, 0]))); // Network address
+        assert_eq!(scanner.results().count(), 1);
+        assert_eq!(scanner.results().next(), Some((ip, mac)));
+    }
+}
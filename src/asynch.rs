@@ -0,0 +1,346 @@
+//! Executor-agnostic async sending and receiving.
+//!
+//! Devices and sockets implement [AsyncDevice] and [AsyncDatagramSocket], which register the
+//! task's waker when they cannot make progress and wake it when a frame or datagram arrives or
+//! queue space frees up. Only `core::future` and `core::task` are used, so any executor will do.
+//!
+//! The `*_async` functions take the device or socket in a [Shared] cell and borrow it only while
+//! polling, so that the task running the poll loop and the tasks waiting on data can share it.
+//! A task that finds it borrowed elsewhere waits to be woken when that borrow is released.
+//!
+//! ```rust
+//! use catnip::*;
+//! use core::future::Future;
+//! use core::pin::pin;
+//! use core::task::{Context, Waker};
+//!
+//! let socket: Shared<UdpSocket<4, 64>> = Shared::new(UdpSocket::bind(8125));
+//! let mut buffer = [0_u8; 64];
+//! let mut cx = Context::from_waker(Waker::noop());
+//!
+//! // Nothing has arrived yet
+//! let mut recv = pin!(recv_from_async(&socket, &mut buffer));
+//! assert!(recv.as_mut().poll(&mut cx).is_pending());
+//!
+//! // The poll loop hands a datagram to the socket, waking the waiting task
+//! let mut frame = [0_u8; 128];
+//! let len = UdpBuilder::new(
+//!     MacAddr::new([0x02, 0, 0, 0, 0, 1]),
+//!     IpV4Addr::new([10, 0, 0, 121]),
+//!     8123,
+//!     MacAddr::new([0x02, 0, 0, 0, 0, 2]),
+//!     IpV4Addr::new([10, 0, 0, 120]),
+//!     8125,
+//! )
+//! .write_bytes(&[1, 2, 3], &mut frame)
+//! .unwrap();
+//! if let (Ok(Packet::Udp(udp)), Some(mut socket)) =
+//!     (Packet::classify(&frame[..len], true), socket.try_borrow_mut())
+//! {
+//!     socket.process(&udp);
+//! }
+//!
+//! assert!(recv.as_mut().poll(&mut cx).is_ready());
+//! ```
+
+use crate::*;
+
+use core::cell::{RefCell, RefMut};
+use core::future::poll_fn;
+use core::ops::{Deref, DerefMut};
+use core::task::{Context, Poll, Waker};
+
+/// Storage for the waker of a task waiting on a device or socket.
+///
+/// Registrations always compare equal, so that devices and sockets holding them
+/// compare by their contents alone.
+#[derive(Clone, Debug, Default)]
+pub struct WakerRegistration {
+    waker: Option<Waker>,
+}
+
+impl WakerRegistration {
+    /// Empty registration
+    pub const fn new() -> Self {
+        WakerRegistration { waker: None }
+    }
+
+    /// Store a task's waker, replacing any other task's
+    pub fn register(&mut self, waker: &Waker) {
+        match &self.waker {
+            Some(x) if x.will_wake(waker) => {}
+            _ => self.waker = Some(waker.clone()),
+        }
+    }
+
+    /// Wake the registered task, if there is one
+    pub fn wake(&mut self) {
+        if let Some(x) = self.waker.take() {
+            x.wake();
+        }
+    }
+}
+
+impl PartialEq for WakerRegistration {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for WakerRegistration {}
+
+/// A [Device] that can wake a task when a frame is received or there is room to transmit
+pub trait AsyncDevice: Device {
+    /// Copy the next received frame into the start of the buffer and return its length,
+    /// or register the task's waker if there is none
+    fn poll_receive(&mut self, cx: &mut Context<'_>, buffer: &mut [u8]) -> Poll<usize>;
+
+    /// Queue a frame for transmission, or register the task's waker if the device is busy
+    fn poll_transmit(
+        &mut self,
+        cx: &mut Context<'_>,
+        frame: &[u8],
+    ) -> Poll<Result<(), DeviceError>>;
+}
+
+/// A [DatagramSocket] that can wake a task when a datagram is received or there is room to send
+pub trait AsyncDatagramSocket: DatagramSocket {
+    /// Queue a datagram for sending, or register the task's waker if the send queue is full
+    fn poll_send_to(
+        &mut self,
+        cx: &mut Context<'_>,
+        dst_ipaddr: IpV4Addr,
+        dst_port: u16,
+        payload: &[u8],
+    ) -> Poll<Result<(), SocketError>>;
+
+    /// Copy the oldest received datagram into the start of the buffer,
+    /// or register the task's waker if there is none
+    fn poll_recv_from(
+        &mut self,
+        cx: &mut Context<'_>,
+        buffer: &mut [u8],
+    ) -> Poll<Result<(IpV4Addr, u16, usize), SocketError>>;
+}
+
+/// A device or socket shared between the task running the poll loop and tasks awaiting it.
+///
+/// Borrows are exclusive, like a [RefCell]'s, and releasing one wakes the task that found the
+/// cell borrowed, if any. As with the other wakers here, one task may wait on the cell at a time.
+#[derive(Debug, Default)]
+pub struct Shared<T> {
+    value: RefCell<T>,
+    released: RefCell<WakerRegistration>,
+}
+
+impl<T> Shared<T> {
+    /// Wrap a device or socket for sharing
+    pub const fn new(value: T) -> Self {
+        Shared {
+            value: RefCell::new(value),
+            released: RefCell::new(WakerRegistration::new()),
+        }
+    }
+
+    /// Borrow the contents, or `None` if they are already borrowed
+    pub fn try_borrow_mut(&self) -> Option<SharedGuard<'_, T>> {
+        Some(SharedGuard {
+            value: self.value.try_borrow_mut().ok()?,
+            released: &self.released,
+        })
+    }
+
+    /// Borrow the contents for one poll, or register the task's waker if they are already borrowed
+    fn poll_borrowed<R>(
+        &self,
+        cx: &mut Context<'_>,
+        f: impl FnOnce(&mut T, &mut Context<'_>) -> Poll<R>,
+    ) -> Poll<R> {
+        match self.try_borrow_mut() {
+            Some(mut x) => f(&mut x, cx),
+            None => {
+                if let Ok(mut released) = self.released.try_borrow_mut() {
+                    released.register(cx.waker());
+                }
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Exclusive borrow of the contents of a [Shared] cell, which wakes a waiting task when dropped
+#[derive(Debug)]
+pub struct SharedGuard<'a, T> {
+    value: RefMut<'a, T>,
+    released: &'a RefCell<WakerRegistration>,
+}
+
+impl<T> Deref for SharedGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T> DerefMut for SharedGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+impl<T> Drop for SharedGuard<'_, T> {
+    fn drop(&mut self) {
+        if let Ok(mut released) = self.released.try_borrow_mut() {
+            released.wake();
+        }
+    }
+}
+
+/// Wait for the next received frame, copying it into the start of the buffer and returning its length
+pub async fn receive_frame_async<D: AsyncDevice>(device: &Shared<D>, buffer: &mut [u8]) -> usize {
+    poll_fn(|cx| device.poll_borrowed(cx, |x, cx| x.poll_receive(cx, buffer))).await
+}
+
+/// Wait for room to transmit a frame, then queue it
+pub async fn transmit_frame_async<D: AsyncDevice>(
+    device: &Shared<D>,
+    frame: &[u8],
+) -> Result<(), DeviceError> {
+    poll_fn(|cx| device.poll_borrowed(cx, |x, cx| x.poll_transmit(cx, frame))).await
+}
+
+/// Wait for room in the socket's send queue, then queue a datagram
+pub async fn send_to_async<S: AsyncDatagramSocket>(
+    socket: &Shared<S>,
+    dst_ipaddr: IpV4Addr,
+    dst_port: u16,
+    payload: &[u8],
+) -> Result<(), SocketError> {
+    poll_fn(|cx| {
+        socket.poll_borrowed(cx, |x, cx| {
+            x.poll_send_to(cx, dst_ipaddr, dst_port, payload)
+        })
+    })
+    .await
+}
+
+/// Wait for the next datagram on the socket, copying it into the start of the buffer and
+/// returning the remote address and port it came from and its length
+pub async fn recv_from_async<S: AsyncDatagramSocket>(
+    socket: &Shared<S>,
+    buffer: &mut [u8],
+) -> Result<(IpV4Addr, u16, usize), SocketError> {
+    poll_fn(|cx| socket.poll_borrowed(cx, |x, cx| x.poll_recv_from(cx, buffer))).await
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use core::future::Future;
+    use core::pin::pin;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use core::task::{RawWaker, RawWakerVTable};
+
+    /// Waker that counts how many times it has been woken
+    fn counting_waker(wakes: &'static AtomicUsize) -> Waker {
+        fn count(data: *const ()) {
+            // Safety: data is always a pointer to a static counter
+            unsafe { &*(data as *const AtomicUsize) }.fetch_add(1, Ordering::SeqCst);
+        }
+        const VTABLE: RawWakerVTable =
+            RawWakerVTable::new(|data| RawWaker::new(data, &VTABLE), count, count, |_| {});
+        let data = wakes as *const AtomicUsize as *const ();
+        unsafe { Waker::from_raw(RawWaker::new(data, &VTABLE)) }
+    }
+
+    #[test]
+    fn test_async_device() {
+        static WAKES: AtomicUsize = AtomicUsize::new(0);
+        let waker = counting_waker(&WAKES);
+        let mut cx = Context::from_waker(&waker);
+        let device: Shared<QueueDevice<1, 64>> = Shared::new(QueueDevice::new());
+        let mut buffer = [0_u8; 64];
+        let wakes = WAKES.load(Ordering::SeqCst);
+
+        // Receive waits for a frame
+        {
+            let mut rx = pin!(receive_frame_async(&device, &mut buffer));
+            assert_eq!(rx.as_mut().poll(&mut cx), Poll::Pending);
+            device.try_borrow_mut().unwrap().push_rx(&[1, 2]).unwrap();
+            assert_eq!(WAKES.load(Ordering::SeqCst), wakes + 1);
+            assert_eq!(rx.as_mut().poll(&mut cx), Poll::Ready(2));
+        }
+
+        // Transmit waits for room
+        device.try_borrow_mut().unwrap().transmit(&[3]).unwrap();
+        let mut tx = pin!(transmit_frame_async(&device, &[4]));
+        assert_eq!(tx.as_mut().poll(&mut cx), Poll::Pending);
+        assert_eq!(
+            device.try_borrow_mut().unwrap().pop_tx(&mut buffer),
+            Some(1)
+        );
+        assert_eq!(WAKES.load(Ordering::SeqCst), wakes + 2);
+        assert_eq!(tx.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+
+        // A task that finds the device borrowed waits for the borrow to be released
+        let mut rx = pin!(receive_frame_async(&device, &mut buffer));
+        let mut guard = device.try_borrow_mut().unwrap();
+        assert!(device.try_borrow_mut().is_none());
+        assert_eq!(rx.as_mut().poll(&mut cx), Poll::Pending);
+        assert_eq!(WAKES.load(Ordering::SeqCst), wakes + 2);
+        guard.push_rx(&[5, 6, 7]).unwrap();
+        drop(guard);
+        assert_eq!(WAKES.load(Ordering::SeqCst), wakes + 3);
+        assert_eq!(rx.as_mut().poll(&mut cx), Poll::Ready(3));
+    }
+
+    #[test]
+    fn test_async_socket() {
+        static WAKES: AtomicUsize = AtomicUsize::new(0);
+        let waker = counting_waker(&WAKES);
+        let mut cx = Context::from_waker(&waker);
+        let socket: Shared<UdpSocket<1, 16>> = Shared::new(UdpSocket::bind(8125));
+        let iface: Interface = Interface::new(
+            MacAddr::new([0x02, 0, 0, 0, 0, 1]),
+            IpV4Addr::new([10, 0, 0, 2]),
+            IpV4Addr::new([255, 255, 255, 0]),
+            IpV4Addr::ANY,
+        );
+        let mut device: Loopback<1, 128> = Loopback::new();
+        let mut frame = [0_u8; 128];
+
+        // Send waits for room in the queue, which is made when the oldest datagram goes out
+        socket
+            .try_borrow_mut()
+            .unwrap()
+            .send_to(IpV4Addr::BROADCAST, 8125, &[1])
+            .unwrap();
+        let mut send = pin!(send_to_async(&socket, IpV4Addr::BROADCAST, 8125, &[2]));
+        assert_eq!(send.as_mut().poll(&mut cx), Poll::Pending);
+        let wakes = WAKES.load(Ordering::SeqCst);
+        assert_eq!(
+            socket
+                .try_borrow_mut()
+                .unwrap()
+                .dispatch(&iface, &mut device, &mut frame),
+            Ok(true)
+        );
+        assert_eq!(WAKES.load(Ordering::SeqCst), wakes + 1);
+        assert_eq!(send.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+
+        // Receive waits for a datagram
+        let mut buffer = [0_u8; 16];
+        let mut recv = pin!(recv_from_async(&socket, &mut buffer));
+        assert_eq!(recv.as_mut().poll(&mut cx), Poll::Pending);
+        let len = device.receive(&mut frame).unwrap();
+        match Packet::classify(&frame[..len], true) {
+            Ok(Packet::Udp(udp)) => assert!(socket.try_borrow_mut().unwrap().process(&udp)),
+            x => panic!("{x:?}"),
+        }
+        assert_eq!(
+            recv.as_mut().poll(&mut cx),
+            Poll::Ready(Ok((IpV4Addr::new([10, 0, 0, 2]), 8125, 1)))
+        );
+    }
+}
@@ -0,0 +1,368 @@
+//! Abstraction over the Ethernet MAC driver.
+//!
+//! Implementing [Device] for a peripheral lets the [Interface] exchange frames with it
+//! without knowing anything about the hardware. [Loopback] and [QueueDevice] are
+//! implementations backed by fixed-size memory, for running the stack on a host without hardware.
+//!
+//! ```rust
+//! use catnip::*;
+//!
+//! // A frame sent on the loopback device is received by the same device
+//! let mut device: Loopback<4, 1514> = Loopback::new();
+//! device.transmit(&[1, 2, 3]).unwrap();
+//!
+//! let mut rx_buffer = [0_u8; 1514];
+//! assert_eq!(device.receive(&mut rx_buffer), Some(3));
+//! assert_eq!(&rx_buffer[..3], &[1, 2, 3]);
+//! assert_eq!(device.receive(&mut rx_buffer), None);
+//! ```
+
+use crate::*;
+
+use core::task::{Context, Poll};
+
+/// What the hardware can do on its own
+#[derive(Clone, Copy, uDebug, Debug, PartialEq, Eq)]
+pub struct DeviceCapabilities {
+    /// Largest IP packet that can be sent or received, not including the Ethernet header
+    pub mtu: usize,
+    /// Whether the hardware checks IP and UDP checksums of received frames and drops frames that fail
+    pub rx_checksum_offload: bool,
+    /// Whether the hardware fills in IP and UDP checksums of transmitted frames
+    pub tx_checksum_offload: bool,
+}
+
+impl Default for DeviceCapabilities {
+    /// Standard Ethernet MTU with no offloading
+    fn default() -> Self {
+        DeviceCapabilities {
+            mtu: 1500,
+            rx_checksum_offload: false,
+            tx_checksum_offload: false,
+        }
+    }
+}
+
+/// Why a frame could not be transmitted
+#[derive(Clone, Copy, uDebug, Debug, PartialEq, Eq)]
+pub enum DeviceError {
+    /// No transmit descriptor or buffer is free right now
+    Busy,
+    /// The frame is longer than the device can send
+    FrameTooLong,
+}
+
+/// An Ethernet MAC driver.
+///
+/// Frames are passed without the frame check sequence, which is handled by the hardware.
+pub trait Device {
+    /// Copy the next received frame, if there is one, into the start of the buffer and return its length.
+    /// A frame that does not fit in the buffer is discarded.
+    fn receive(&mut self, buffer: &mut [u8]) -> Option<usize>;
+
+    /// Queue a frame for transmission
+    fn transmit(&mut self, frame: &[u8]) -> Result<(), DeviceError>;
+
+    /// What the hardware can do on its own
+    fn capabilities(&self) -> DeviceCapabilities;
+}
+
+/// Fixed-capacity first-in, first-out queue of up to `N` frames of up to `M` bytes each
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct FrameQueue<const N: usize, const M: usize> {
+    frames: [[u8; M]; N],
+    lens: [usize; N],
+    head: usize,
+    count: usize,
+}
+
+impl<const N: usize, const M: usize> FrameQueue<N, M> {
+    const fn new() -> Self {
+        FrameQueue {
+            frames: [[0_u8; M]; N],
+            lens: [0; N],
+            head: 0,
+            count: 0,
+        }
+    }
+
+    fn push(&mut self, frame: &[u8]) -> Result<(), DeviceError> {
+        if frame.len() > M {
+            return Err(DeviceError::FrameTooLong);
+        }
+        if self.count >= N {
+            return Err(DeviceError::Busy);
+        }
+        let i = (self.head + self.count) % N;
+        match (self.frames.get_mut(i), self.lens.get_mut(i)) {
+            (Some(dst), Some(len)) => {
+                set_array(dst, 0, frame);
+                *len = frame.len();
+                self.count += 1;
+                Ok(())
+            }
+            _ => Err(DeviceError::Busy),
+        }
+    }
+
+    fn pop(&mut self, buffer: &mut [u8]) -> Option<usize> {
+        if self.count == 0 {
+            return None;
+        }
+        let i = self.head;
+        self.head = (self.head + 1) % N;
+        self.count -= 1;
+
+        let len = *self.lens.get(i)?;
+        let frame = self.frames.get(i)?.get(..len)?;
+        let dst = buffer.get_mut(..len)?;
+        dst.copy_from_slice(frame);
+        Some(len)
+    }
+
+    fn len(&self) -> usize {
+        self.count
+    }
+}
+
+/// A device that receives every frame it transmits, holding up to `N` frames of up to `M` bytes
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Loopback<const N: usize, const M: usize> {
+    queue: FrameQueue<N, M>,
+    rx_waker: WakerRegistration,
+    tx_waker: WakerRegistration,
+}
+
+impl<const N: usize, const M: usize> Loopback<N, M> {
+    /// New loopback device with no frames queued
+    pub const fn new() -> Self {
+        Loopback {
+            queue: FrameQueue::new(),
+            rx_waker: WakerRegistration::new(),
+            tx_waker: WakerRegistration::new(),
+        }
+    }
+}
+
+impl<const N: usize, const M: usize> Default for Loopback<N, M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize, const M: usize> Device for Loopback<N, M> {
+    fn receive(&mut self, buffer: &mut [u8]) -> Option<usize> {
+        let len = self.queue.pop(buffer);
+        self.tx_waker.wake();
+        len
+    }
+
+    fn transmit(&mut self, frame: &[u8]) -> Result<(), DeviceError> {
+        self.queue.push(frame)?;
+        self.rx_waker.wake();
+        Ok(())
+    }
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        DeviceCapabilities {
+            mtu: M.saturating_sub(EthernetHeader::BYTE_LEN),
+            ..Default::default()
+        }
+    }
+}
+
+/// A device with separate receive and transmit queues of up to `N` frames of up to `M` bytes each,
+/// which the application (or a test) fills and drains to stand in for the network
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QueueDevice<const N: usize, const M: usize> {
+    rx: FrameQueue<N, M>,
+    tx: FrameQueue<N, M>,
+    capabilities: DeviceCapabilities,
+    rx_waker: WakerRegistration,
+    tx_waker: WakerRegistration,
+}
+
+impl<const N: usize, const M: usize> QueueDevice<N, M> {
+    /// New device with empty queues
+    pub const fn new() -> Self {
+        QueueDevice {
+            rx: FrameQueue::new(),
+            tx: FrameQueue::new(),
+            capabilities: DeviceCapabilities {
+                mtu: M.saturating_sub(EthernetHeader::BYTE_LEN),
+                rx_checksum_offload: false,
+                tx_checksum_offload: false,
+            },
+            rx_waker: WakerRegistration::new(),
+            tx_waker: WakerRegistration::new(),
+        }
+    }
+
+    /// Change the capabilities the device reports
+    pub fn with_capabilities(mut self, capabilities: DeviceCapabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// Queue a frame to be received by the stack, as if it arrived from the network
+    pub fn push_rx(&mut self, frame: &[u8]) -> Result<(), DeviceError> {
+        self.rx.push(frame)?;
+        self.rx_waker.wake();
+        Ok(())
+    }
+
+    /// Take the oldest frame transmitted by the stack, copying it into the start of the buffer
+    pub fn pop_tx(&mut self, buffer: &mut [u8]) -> Option<usize> {
+        let len = self.tx.pop(buffer);
+        self.tx_waker.wake();
+        len
+    }
+
+    /// Number of received frames waiting to be read by the stack
+    pub fn rx_len(&self) -> usize {
+        self.rx.len()
+    }
+
+    /// Number of transmitted frames waiting to be taken with [QueueDevice::pop_tx]
+    pub fn tx_len(&self) -> usize {
+        self.tx.len()
+    }
+}
+
+impl<const N: usize, const M: usize> Default for QueueDevice<N, M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize, const M: usize> Device for QueueDevice<N, M> {
+    fn receive(&mut self, buffer: &mut [u8]) -> Option<usize> {
+        self.rx.pop(buffer)
+    }
+
+    fn transmit(&mut self, frame: &[u8]) -> Result<(), DeviceError> {
+        if frame.len()
+            > self
+                .capabilities
+                .mtu
+                .saturating_add(EthernetHeader::BYTE_LEN)
+        {
+            return Err(DeviceError::FrameTooLong);
+        }
+        self.tx.push(frame)
+    }
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        self.capabilities
+    }
+}
+
+impl<const N: usize, const M: usize> AsyncDevice for Loopback<N, M> {
+    fn poll_receive(&mut self, cx: &mut Context<'_>, buffer: &mut [u8]) -> Poll<usize> {
+        if self.queue.len() == 0 {
+            self.rx_waker.register(cx.waker());
+            return Poll::Pending;
+        }
+        match self.receive(buffer) {
+            Some(len) => Poll::Ready(len),
+            None => {
+                // Frame was discarded; try the next one
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }
+    }
+
+    fn poll_transmit(
+        &mut self,
+        cx: &mut Context<'_>,
+        frame: &[u8],
+    ) -> Poll<Result<(), DeviceError>> {
+        match self.transmit(frame) {
+            Err(DeviceError::Busy) => {
+                self.tx_waker.register(cx.waker());
+                Poll::Pending
+            }
+            x => Poll::Ready(x),
+        }
+    }
+}
+
+impl<const N: usize, const M: usize> AsyncDevice for QueueDevice<N, M> {
+    fn poll_receive(&mut self, cx: &mut Context<'_>, buffer: &mut [u8]) -> Poll<usize> {
+        if self.rx.len() == 0 {
+            self.rx_waker.register(cx.waker());
+            return Poll::Pending;
+        }
+        match self.receive(buffer) {
+            Some(len) => Poll::Ready(len),
+            None => {
+                // Frame was discarded; try the next one
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }
+    }
+
+    fn poll_transmit(
+        &mut self,
+        cx: &mut Context<'_>,
+        frame: &[u8],
+    ) -> Poll<Result<(), DeviceError>> {
+        match self.transmit(frame) {
+            Err(DeviceError::Busy) => {
+                self.tx_waker.register(cx.waker());
+                Poll::Pending
+            }
+            x => Poll::Ready(x),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_queue_device() {
+        let mut device: QueueDevice<2, 64> = QueueDevice::new();
+        let mut buffer = [0_u8; 64];
+
+        // Queues are first-in, first-out and bounded
+        device.push_rx(&[1]).unwrap();
+        device.push_rx(&[2, 2]).unwrap();
+        assert_eq!(device.push_rx(&[3]), Err(DeviceError::Busy));
+        assert_eq!(device.push_rx(&[0; 65]), Err(DeviceError::FrameTooLong));
+        assert_eq!(device.receive(&mut buffer), Some(1));
+        assert_eq!(buffer[0], 1);
+        device.push_rx(&[3, 3, 3]).unwrap();
+        assert_eq!(device.receive(&mut buffer), Some(2));
+        assert_eq!(device.receive(&mut buffer), Some(3));
+        assert_eq!(&buffer[..3], &[3, 3, 3]);
+        assert_eq!(device.receive(&mut buffer), None);
+
+        // Frame too long for the caller's buffer is discarded
+        device.push_rx(&[4; 10]).unwrap();
+        assert_eq!(device.receive(&mut buffer[..5]), None);
+        assert_eq!(device.rx_len(), 0);
+
+        // Transmitted frames are held separately and limited by MTU
+        device.transmit(&[5, 5]).unwrap();
+        assert_eq!(device.rx_len(), 0);
+        assert_eq!(device.tx_len(), 1);
+        assert_eq!(device.pop_tx(&mut buffer), Some(2));
+        let mut device = device.with_capabilities(DeviceCapabilities {
+            mtu: 10,
+            ..Default::default()
+        });
+        assert_eq!(device.transmit(&[0; 25]), Err(DeviceError::FrameTooLong));
+
+        // An effectively unlimited MTU leaves only the queue's own frame size as a limit
+        let mut device = device.with_capabilities(DeviceCapabilities {
+            mtu: usize::MAX,
+            ..Default::default()
+        });
+        device.transmit(&[6; 25]).unwrap();
+        assert_eq!(device.pop_tx(&mut buffer), Some(25));
+    }
+}
@@ -4,8 +4,9 @@
 //!
 //! Partial implementation per IETF-RFC-2131; see https://datatracker.ietf.org/doc/html/rfc2131#page-22
 //!
-//! This is intended to provide just enough functionality to accept a statically-assigned address on
-//! networks that require confirmation of static addresses with an indefinite lease duration via DHCP.
+//! This module holds the message formats. An INFORM message alone is enough to accept a statically-assigned
+//! address on networks that require confirmation of static addresses with an indefinite lease duration via DHCP;
+//! for dynamically-assigned addresses, see [DhcpClient].
 //!
 //! In this case, the server refers to the router or similar hardware orchestrating the address space,
 //! while the client refers to the endpoints requesting addresses.
@@ -37,6 +38,10 @@ pub const DHCP_CLIENT_PORT: u16 = 68;
 
 /// "Magic Cookie" placed at the end of the fixed portion of the DHCP payload
 const DHCP_COOKIE: u32 = 0x63_82_53_63;
+/// Offset of the options section, after the BOOTP fields and the magic cookie
+const DHCP_OPTIONS_OFFSET: usize = 240;
+/// Minimum length of a DHCP message from the start of the BOOTP fields, which some servers and relays enforce
+pub const DHCP_MIN_MESSAGE_LEN: usize = 300;
 /// A full word containing 255 in the options segment indicates end of message
 const DHCP_END: u32 = 0xff;
 
@@ -86,6 +91,7 @@ pub struct DhcpFixedPayload {
 
 impl DhcpFixedPayload {
     /// Convenience function to remove boilerplate for predetermined fields.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         end_of_message: bool,
         op: DhcpOperation,
@@ -98,18 +104,18 @@ impl DhcpFixedPayload {
         chaddr: MacAddr,
     ) -> Self {
         DhcpFixedPayload {
-            op: op,
+            op,
             htype: 1_u8, // Always 1 for ethernet
             hlen: 6_u8,  // Always 6 byte standard mac address
             hops: 0,
             xid: transaction_id,
             secs: 0,
             flags: (broadcast as u16) * 32768,
-            ciaddr: ciaddr,
-            yiaddr: yiaddr,
-            siaddr: siaddr,
+            ciaddr,
+            yiaddr,
+            siaddr,
             giaddr: IpV4Addr::ANY,
-            chaddr: chaddr,
+            chaddr,
             _pad0: [0_u16; 5],
             _pad1: [0_u128; 12],
             cookie: DHCP_COOKIE,
@@ -121,7 +127,8 @@ impl DhcpFixedPayload {
     /// taking a pre-assigned IP address which may have already be assigned statically
     /// in the configuration of the router. This message should also be accompanied by
     /// an ARP "announce" message to broadcast the presence of the machine to others on
-    /// the network that may or may not receive a forwarded copy of the DHCP INFORM.
+    /// the network that may or may not receive a forwarded copy of the DHCP INFORM;
+    /// see [EthernetFrame::arp_announce].
     pub fn new_inform(ipaddr: IpV4Addr, macaddr: MacAddr, transaction_id: u32) -> Self {
         Self::new(
             true,
@@ -145,6 +152,368 @@ impl DhcpFixedPayload {
     }
 }
 
+impl TryByteStruct for DhcpFixedPayload {
+    fn try_read_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        let bytes = bytes.get(..Self::BYTE_LEN).ok_or(ParseError::Truncated)?;
+        let msg = Self::read_bytes(bytes);
+        if msg.cookie != DHCP_COOKIE {
+            return Err(ParseError::BadDhcpCookie(msg.cookie));
+        }
+
+        Ok(msg)
+    }
+}
+
+/// Borrowed view of a DHCP message in a buffer, with typed access to the fixed fields in place
+/// and to the variable-length options section as raw bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DhcpView<T: AsRef<[u8]>> {
+    buffer: T,
+}
+
+impl<T: AsRef<[u8]>> DhcpView<T> {
+    /// Wrap a buffer, checking that it holds the fixed fields and the magic cookie
+    pub fn new(buffer: T) -> Result<Self, ParseError> {
+        let bytes = buffer.as_ref();
+        if bytes.len() < DHCP_OPTIONS_OFFSET {
+            return Err(ParseError::Truncated);
+        }
+        let cookie = get_u32(bytes, 236);
+        if cookie != DHCP_COOKIE {
+            return Err(ParseError::BadDhcpCookie(cookie));
+        }
+
+        Ok(DhcpView { buffer })
+    }
+
+    /// Release the underlying buffer
+    pub fn into_inner(self) -> T {
+        self.buffer
+    }
+
+    /// Message op code; request from client or reply from server
+    pub fn op(&self) -> DhcpOperation {
+        DhcpOperation::from(self.buffer.as_ref().first().copied().unwrap_or(0))
+    }
+
+    /// Transaction ID
+    pub fn xid(&self) -> u32 {
+        get_u32(self.buffer.as_ref(), 4)
+    }
+
+    /// Seconds elapsed since client started transaction
+    pub fn secs(&self) -> u16 {
+        get_u16(self.buffer.as_ref(), 8)
+    }
+
+    /// Whether the broadcast flag is set
+    pub fn broadcast(&self) -> bool {
+        get_u16(self.buffer.as_ref(), 10) & 0x8000 != 0
+    }
+
+    /// Client IP Address
+    pub fn ciaddr(&self) -> IpV4Addr {
+        IpV4Addr::new(get_array(self.buffer.as_ref(), 12))
+    }
+
+    /// Your IP Address
+    pub fn yiaddr(&self) -> IpV4Addr {
+        IpV4Addr::new(get_array(self.buffer.as_ref(), 16))
+    }
+
+    /// Server IP Address
+    pub fn siaddr(&self) -> IpV4Addr {
+        IpV4Addr::new(get_array(self.buffer.as_ref(), 20))
+    }
+
+    /// Gateway IP Address
+    pub fn giaddr(&self) -> IpV4Addr {
+        IpV4Addr::new(get_array(self.buffer.as_ref(), 24))
+    }
+
+    /// Client hardware address
+    pub fn chaddr(&self) -> MacAddr {
+        MacAddr::new(get_array(self.buffer.as_ref(), 28))
+    }
+
+    /// Variable-length options section after the magic cookie
+    pub fn options(&self) -> &[u8] {
+        self.buffer
+            .as_ref()
+            .get(DHCP_OPTIONS_OFFSET..)
+            .unwrap_or(&[])
+    }
+
+    /// Iterate over the options section. See [DhcpOptions].
+    pub fn iter_options(&self) -> DhcpOptions<'_> {
+        DhcpOptions::new(self.options())
+    }
+}
+
+impl<T: AsRef<[u8]> + AsMut<[u8]>> DhcpView<T> {
+    /// Set message op code
+    pub fn set_op(&mut self, value: DhcpOperation) {
+        set_array(self.buffer.as_mut(), 0, &[u8::from(value)]);
+    }
+
+    /// Set transaction ID
+    pub fn set_xid(&mut self, value: u32) {
+        set_u32(self.buffer.as_mut(), 4, value);
+    }
+
+    /// Set seconds elapsed since client started transaction
+    pub fn set_secs(&mut self, value: u16) {
+        set_u16(self.buffer.as_mut(), 8, value);
+    }
+
+    /// Set or clear the broadcast flag
+    pub fn set_broadcast(&mut self, value: bool) {
+        set_u16(self.buffer.as_mut(), 10, (value as u16) * 0x8000);
+    }
+
+    /// Set client IP Address
+    pub fn set_ciaddr(&mut self, value: IpV4Addr) {
+        set_array(self.buffer.as_mut(), 12, &value.0);
+    }
+
+    /// Set your IP Address
+    pub fn set_yiaddr(&mut self, value: IpV4Addr) {
+        set_array(self.buffer.as_mut(), 16, &value.0);
+    }
+
+    /// Set server IP Address
+    pub fn set_siaddr(&mut self, value: IpV4Addr) {
+        set_array(self.buffer.as_mut(), 20, &value.0);
+    }
+
+    /// Set gateway IP Address
+    pub fn set_giaddr(&mut self, value: IpV4Addr) {
+        set_array(self.buffer.as_mut(), 24, &value.0);
+    }
+
+    /// Set client hardware address
+    pub fn set_chaddr(&mut self, value: MacAddr) {
+        set_array(self.buffer.as_mut(), 28, &value.0);
+    }
+
+    /// Mutable access to the options section after the magic cookie
+    pub fn options_mut(&mut self) -> &mut [u8] {
+        self.buffer
+            .as_mut()
+            .get_mut(DHCP_OPTIONS_OFFSET..)
+            .unwrap_or(&mut [])
+    }
+}
+
+/// Iterator over the type-length-value entries in a DHCP options section,
+/// yielding each option's kind and data without copying.
+///
+/// Pad bytes are skipped and iteration stops at the End option, or at the end of the input
+/// if there is no End option. An option whose length runs past the end of the input
+/// produces an error, after which iteration stops.
+/// Options carried in the `sname` and `file` fields by option overload are not visited.
+#[derive(Clone, Copy, uDebug, Debug, PartialEq, Eq)]
+pub struct DhcpOptions<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    done: bool,
+}
+
+impl<'a> DhcpOptions<'a> {
+    /// Iterate over an options section, starting after the magic cookie
+    pub fn new(bytes: &'a [u8]) -> Self {
+        DhcpOptions {
+            bytes,
+            offset: 0,
+            done: false,
+        }
+    }
+
+    /// Data of the first option of this kind, if present
+    pub fn get(self, kind: DhcpOptionKind) -> Result<Option<&'a [u8]>, ParseError> {
+        for option in self {
+            match option {
+                Ok((x, data)) if x == kind => return Ok(Some(data)),
+                Ok(_) => {}
+                Err(x) => return Err(x),
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+impl<'a> Iterator for DhcpOptions<'a> {
+    type Item = Result<(DhcpOptionKind, &'a [u8]), ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            let code = *self.bytes.get(self.offset)?;
+            match DhcpOptionKind::from(code) {
+                DhcpOptionKind::Pad => self.offset += 1,
+                DhcpOptionKind::End => self.done = true,
+                kind => {
+                    let start = self.offset + 2;
+                    let data = self
+                        .bytes
+                        .get(self.offset + 1)
+                        .and_then(|&len| self.bytes.get(start..start + len as usize));
+                    match data {
+                        Some(data) => {
+                            self.offset = start + data.len();
+                            return Some(Ok((kind, data)));
+                        }
+                        None => {
+                            self.done = true;
+                            return Some(Err(ParseError::BadDhcpOption(code)));
+                        }
+                    }
+                }
+            }
+        }
+
+        None
+    }
+}
+
+/// Writes a DHCP message with options into a caller-provided buffer: the fixed part first,
+/// then each option appended in the order given, then the End option and padding.
+///
+/// Each option is checked against the space left, including the End option, and is not written
+/// at all if it does not fit, so a failed option leaves the message as it was.
+///
+/// ```rust
+/// use catnip::*;
+///
+/// let msg = DhcpFixedPayload::new(
+///     false,
+///     DhcpOperation::Request,
+///     DhcpMessageKind::Request,
+///     12345,
+///     true,
+///     IpV4Addr::ANY,
+///     IpV4Addr::ANY,
+///     IpV4Addr::ANY,
+///     MacAddr::new([5, 6, 7, 8, 9, 10]),
+/// );
+/// let mut buffer = [0_u8; 512];
+/// let mut writer = DhcpOptionsWriter::new(&mut buffer, &msg).unwrap();
+/// writer
+///     .requested_ip(IpV4Addr::new([10, 0, 0, 120]))
+///     .unwrap()
+///     .server_id(IpV4Addr::new([10, 0, 0, 1]))
+///     .unwrap()
+///     .hostname("plc-7")
+///     .unwrap();
+/// let len = writer.finish().unwrap();
+///
+/// assert_eq!(len, DHCP_MIN_MESSAGE_LEN);
+/// let view = DhcpView::new(&buffer[..len]).unwrap();
+/// assert_eq!(
+///     view.iter_options().get(DhcpOptionKind::HostName),
+///     Ok(Some(&b"plc-7"[..]))
+/// );
+/// ```
+#[derive(uDebug, Debug, PartialEq, Eq)]
+pub struct DhcpOptionsWriter<'a> {
+    buffer: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> DhcpOptionsWriter<'a> {
+    /// Write the fixed part of the message, including the message type option, to the start of the buffer.
+    /// The byte after the message type option is overwritten by the options that follow.
+    ///
+    /// The buffer must hold at least [DHCP_MIN_MESSAGE_LEN] bytes so that the finished message can be padded.
+    pub fn new(buffer: &'a mut [u8], msg: &DhcpFixedPayload) -> Result<Self, BuildError> {
+        if buffer.len() < DHCP_MIN_MESSAGE_LEN {
+            return Err(BuildError::BufferTooSmall);
+        }
+        set_array(buffer, 0, &msg.to_be_bytes());
+
+        Ok(DhcpOptionsWriter {
+            buffer,
+            len: DhcpFixedPayload::BYTE_LEN - 1,
+        })
+    }
+
+    /// Append an option with arbitrary data
+    pub fn option(&mut self, kind: DhcpOptionKind, data: &[u8]) -> Result<&mut Self, BuildError> {
+        self.option_with(kind, data.len(), |x| set_array(x, 0, data))
+    }
+
+    /// Append an option of `len` bytes filled in by `fill`, if it fits
+    fn option_with(
+        &mut self,
+        kind: DhcpOptionKind,
+        len: usize,
+        fill: impl FnOnce(&mut [u8]),
+    ) -> Result<&mut Self, BuildError> {
+        let data_len = u8::try_from(len).map_err(|_| BuildError::PayloadTooLong)?;
+        let start = self.len + 2;
+        let end = start + len;
+        // The End option must still fit afterward
+        if end >= self.buffer.len() {
+            return Err(BuildError::BufferTooSmall);
+        }
+        set_array(self.buffer, self.len, &[u8::from(kind), data_len]);
+        if let Some(x) = self.buffer.get_mut(start..end) {
+            fill(x);
+        }
+        self.len = end;
+
+        Ok(self)
+    }
+
+    /// Append the requested IP address option, asking the server for a particular address
+    pub fn requested_ip(&mut self, ipaddr: IpV4Addr) -> Result<&mut Self, BuildError> {
+        self.option(DhcpOptionKind::RequestedIpAddress, &ipaddr.0)
+    }
+
+    /// Append the server identifier option, naming the server whose offer is being accepted
+    pub fn server_id(&mut self, ipaddr: IpV4Addr) -> Result<&mut Self, BuildError> {
+        self.option(DhcpOptionKind::ServerIdentifier, &ipaddr.0)
+    }
+
+    /// Append the parameter request list option, asking the server for these options in its reply
+    pub fn parameter_request_list(
+        &mut self,
+        kinds: &[DhcpOptionKind],
+    ) -> Result<&mut Self, BuildError> {
+        self.option_with(DhcpOptionKind::ParameterRequestList, kinds.len(), |x| {
+            for (dst, kind) in x.iter_mut().zip(kinds) {
+                *dst = u8::from(*kind);
+            }
+        })
+    }
+
+    /// Append the client identifier option with an ethernet hardware address, per RFC 2132
+    pub fn client_id(&mut self, macaddr: MacAddr) -> Result<&mut Self, BuildError> {
+        self.option_with(DhcpOptionKind::ClientId, 7, |x| {
+            set_array(x, 0, &[1]); // Hardware type for ethernet
+            set_array(x, 1, &macaddr.0);
+        })
+    }
+
+    /// Append the host name option
+    pub fn hostname(&mut self, name: &str) -> Result<&mut Self, BuildError> {
+        self.option(DhcpOptionKind::HostName, name.as_bytes())
+    }
+
+    /// Write the End option and pad to [DHCP_MIN_MESSAGE_LEN], returning the length of the message
+    pub fn finish(self) -> Result<usize, BuildError> {
+        let len = (self.len + 1).max(DHCP_MIN_MESSAGE_LEN);
+        let rest = self
+            .buffer
+            .get_mut(self.len..len)
+            .ok_or(BuildError::BufferTooSmall)?;
+        rest.fill(0);
+        set_array(rest, 0, &[u8::from(DhcpOptionKind::End)]);
+
+        Ok(len)
+    }
+}
+
 /// The options field for message kind is technically part of the
 /// variable-length portion, but is always required and always the first option
 /// so it's really part of the fixed-length portion.
@@ -176,6 +545,8 @@ impl DhcpMessageKindOption {
     }
 }
 
+impl TryByteStruct for DhcpMessageKindOption {}
+
 enum_with_unknown! {
     /// Message op code / message type. 1 = BOOTREQUEST, 2 = BOOTREPLY
     /// Legacy operation type field from BOOTP.
@@ -203,6 +574,8 @@ impl ByteStruct for DhcpOperation {
     }
 }
 
+impl TryByteStruct for DhcpOperation {}
+
 enum_with_unknown! {
     /// Contents of option field kind 53
     #[allow(missing_docs)]
@@ -257,6 +630,8 @@ impl ByteStruct for DhcpMessageKind {
     }
 }
 
+impl TryByteStruct for DhcpMessageKind {}
+
 enum_with_unknown! {
     /// Option type codes for parsing options section.
     /// Most of these are useless.
@@ -376,6 +751,8 @@ impl ByteStruct for DhcpOptionKind {
     }
 }
 
+impl TryByteStruct for DhcpOptionKind {}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -396,4 +773,197 @@ mod test {
 
         assert_eq!(msg_parsed, dhcp_inform);
     }
+
+    #[test]
+    fn test_try_read_bytes() {
+        let dhcp_inform = DhcpFixedPayload::new_inform(
+            IpV4Addr::new([1, 2, 3, 4]),
+            MacAddr::new([5, 6, 7, 8, 9, 10]),
+            12345,
+        );
+        let mut bytes = dhcp_inform.to_be_bytes();
+        assert_eq!(DhcpFixedPayload::try_read_bytes(&bytes), Ok(dhcp_inform));
+        assert_eq!(
+            DhcpFixedPayload::try_read_bytes(&bytes[..100]),
+            Err(ParseError::Truncated)
+        );
+
+        // Cookie is the 4 bytes before the message kind option
+        bytes[236] = 0;
+        assert_eq!(
+            DhcpFixedPayload::try_read_bytes(&bytes),
+            Err(ParseError::BadDhcpCookie(0x00_82_53_63))
+        );
+    }
+
+    #[test]
+    fn test_view() {
+        let dhcp_inform = DhcpFixedPayload::new_inform(
+            IpV4Addr::new([1, 2, 3, 4]),
+            MacAddr::new([5, 6, 7, 8, 9, 10]),
+            12345,
+        );
+        let mut bytes = dhcp_inform.to_be_bytes();
+
+        let view = DhcpView::new(&bytes[..]).unwrap();
+        assert_eq!(view.op(), DhcpOperation::Request);
+        assert_eq!(view.xid(), 12345);
+        assert!(view.broadcast());
+        assert_eq!(view.ciaddr(), IpV4Addr::new([1, 2, 3, 4]));
+        assert_eq!(view.chaddr(), MacAddr::new([5, 6, 7, 8, 9, 10]));
+        assert_eq!(view.options(), &[53, 1, 8, 255]);
+
+        let mut view = DhcpView::new(&mut bytes[..]).unwrap();
+        view.set_xid(54321);
+        view.set_broadcast(false);
+        let parsed = DhcpFixedPayload::read_bytes(&bytes);
+        assert_eq!(parsed.xid, 54321);
+        assert_eq!(parsed.flags, 0);
+    }
+
+    #[test]
+    fn test_options() {
+        let options = [
+            53, 1, 5, // Message type ACK
+            0, 0, // Pad
+            1, 4, 255, 255, 255, 0, // Subnet mask
+            6, 8, 1, 1, 1, 1, 8, 8, 8, 8, // Two DNS servers
+            224, 0,   // Unlisted option with no data
+            255, // End
+            3, 4, 1, 2, 3, 4, // Ignored after End
+        ];
+        let mut iter = DhcpOptions::new(&options);
+        assert_eq!(
+            iter.next(),
+            Some(Ok((DhcpOptionKind::DhcpMessageType, &[5][..])))
+        );
+        assert_eq!(
+            iter.next(),
+            Some(Ok((DhcpOptionKind::SubnetMask, &[255, 255, 255, 0][..])))
+        );
+        assert_eq!(
+            iter.next(),
+            Some(Ok((
+                DhcpOptionKind::DomainNameServers,
+                &[1, 1, 1, 1, 8, 8, 8, 8][..]
+            )))
+        );
+        assert_eq!(
+            iter.next(),
+            Some(Ok((DhcpOptionKind::Unknown(224), &[][..])))
+        );
+        assert_eq!(iter.next(), None);
+        assert_eq!(
+            DhcpOptions::new(&options).get(DhcpOptionKind::SubnetMask),
+            Ok(Some(&[255, 255, 255, 0][..]))
+        );
+        assert_eq!(
+            DhcpOptions::new(&options).get(DhcpOptionKind::Router),
+            Ok(None)
+        );
+
+        // Missing End stops at the end of the input
+        assert_eq!(DhcpOptions::new(&options[..11]).count(), 2);
+
+        // Overruns are reported once, with the option code
+        let mut iter = DhcpOptions::new(&options[..15]);
+        assert!(matches!(iter.next(), Some(Ok(_))));
+        assert!(matches!(iter.next(), Some(Ok(_))));
+        assert_eq!(iter.next(), Some(Err(ParseError::BadDhcpOption(6))));
+        assert_eq!(iter.next(), None);
+        assert_eq!(
+            DhcpOptions::new(&options[..15]).get(DhcpOptionKind::Router),
+            Err(ParseError::BadDhcpOption(6))
+        );
+        assert_eq!(
+            DhcpOptions::new(&[53]).next(),
+            Some(Err(ParseError::BadDhcpOption(53)))
+        );
+
+        // From a whole message
+        let bytes = DhcpFixedPayload::new_inform(
+            IpV4Addr::new([1, 2, 3, 4]),
+            MacAddr::new([5, 6, 7, 8, 9, 10]),
+            12345,
+        )
+        .to_be_bytes();
+        let view = DhcpView::new(&bytes[..]).unwrap();
+        assert_eq!(
+            view.iter_options().get(DhcpOptionKind::DhcpMessageType),
+            Ok(Some(&[u8::from(DhcpMessageKind::Inform)][..]))
+        );
+    }
+
+    #[test]
+    fn test_options_writer() {
+        let mac = MacAddr::new([5, 6, 7, 8, 9, 10]);
+        let msg = DhcpFixedPayload::new(
+            true,
+            DhcpOperation::Request,
+            DhcpMessageKind::Discover,
+            12345,
+            true,
+            IpV4Addr::ANY,
+            IpV4Addr::ANY,
+            IpV4Addr::ANY,
+            mac,
+        );
+        let mut buffer = [0xAA_u8; 400];
+        let mut writer = DhcpOptionsWriter::new(&mut buffer, &msg).unwrap();
+        writer
+            .client_id(mac)
+            .unwrap()
+            .parameter_request_list(&[DhcpOptionKind::SubnetMask, DhcpOptionKind::Router])
+            .unwrap();
+        assert_eq!(
+            writer.option(DhcpOptionKind::VendorInfo, &[0; 256]).err(),
+            Some(BuildError::PayloadTooLong)
+        );
+        let len = writer.finish().unwrap();
+
+        // End option replaces the one in the fixed part, and padding is zeroed
+        assert_eq!(len, DHCP_MIN_MESSAGE_LEN);
+        let view = DhcpView::new(&buffer[..len]).unwrap();
+        assert_eq!(view.xid(), 12345);
+        assert_eq!(
+            &view.options()[..18],
+            &[53, 1, 1, 61, 7, 1, 5, 6, 7, 8, 9, 10, 55, 2, 1, 3, 255, 0]
+        );
+        assert!(view.options()[17..].iter().all(|&x| x == 0));
+        assert_eq!(view.iter_options().count(), 3);
+
+        // Longer messages are not padded
+        let mut buffer = [0_u8; 400];
+        let mut writer = DhcpOptionsWriter::new(&mut buffer, &msg).unwrap();
+        writer
+            .option(DhcpOptionKind::VendorInfo, &[7; 100])
+            .unwrap();
+        assert_eq!(writer.finish(), Ok(DhcpFixedPayload::BYTE_LEN + 102));
+
+        // Options that don't fit, leaving room for End, are not written
+        let mut buffer = [0_u8; DHCP_MIN_MESSAGE_LEN];
+        let mut writer = DhcpOptionsWriter::new(&mut buffer, &msg).unwrap();
+        writer
+            .hostname("0123456789")
+            .unwrap()
+            .option(DhcpOptionKind::VendorInfo, &[7; 40])
+            .unwrap();
+        assert_eq!(
+            writer.requested_ip(IpV4Addr::new([10, 0, 0, 2])).err(),
+            Some(BuildError::BufferTooSmall)
+        );
+        assert_eq!(writer.finish(), Ok(DHCP_MIN_MESSAGE_LEN));
+        let options = DhcpOptions::new(&buffer[DHCP_OPTIONS_OFFSET..]);
+        assert_eq!(
+            options.get(DhcpOptionKind::HostName),
+            Ok(Some(&b"0123456789"[..]))
+        );
+        assert_eq!(options.get(DhcpOptionKind::RequestedIpAddress), Ok(None));
+
+        // Buffers too short for a padded message are rejected up front
+        assert_eq!(
+            DhcpOptionsWriter::new(&mut buffer[..DHCP_MIN_MESSAGE_LEN - 1], &msg).err(),
+            Some(BuildError::BufferTooSmall)
+        );
+    }
 }
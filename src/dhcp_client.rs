@@ -0,0 +1,927 @@
+//! DHCP client state machine per RFC 2131.
+//!
+//! [DhcpClient] finds a server, requests an address, and keeps the lease renewed,
+//! retransmitting with exponential backoff while waiting for replies.
+//! Like the other protocol state machines, it does no I/O: the application sends the frames
+//! written by [DhcpClient::poll] and passes received DHCP datagrams ([InterfaceEvent::Dhcp])
+//! to [DhcpClient::process], then acts on the [DhcpEvent] returned by each. Times are in milliseconds.
+//! When a lease is granted, apply it to the interface with [DhcpLease::configure].
+//!
+//! If no server answers, [DhcpEvent::Failed] is reported once while the client keeps trying,
+//! so that the application can fall back to a link-local address with [LinkLocal] in the meantime.
+//!
+//! ```rust
+//! use catnip::*;
+//!
+//! let macaddr = MacAddr::new([0x02, 0xAF, 0xFF, 0x1A, 0xE5, 0x3C]);
+//! let mut client = DhcpClient::new(macaddr, 12345, 0);
+//! let mut tx_buffer = [0_u8; 1514];
+//!
+//! // After a short random delay, a DISCOVER is broadcast
+//! let now = client.next_at().unwrap();
+//! let len = match client.poll(&mut tx_buffer, now) {
+//!     Ok(DhcpEvent::Transmit(len)) => len,
+//!     x => panic!("{x:?}"),
+//! };
+//! assert_eq!(client.state(), DhcpState::Selecting);
+//!
+//! match Packet::classify(&tx_buffer[..len], true) {
+//!     Ok(Packet::Udp(udp)) => {
+//!         assert_eq!(udp.ip.dst_ipaddr, IpV4Addr::BROADCAST);
+//!         assert_eq!(udp.udp.dst_port, DHCP_SERVER_PORT);
+//!         let msg = DhcpView::new(udp.payload).unwrap();
+//!         assert_eq!(
+//!             msg.iter_options().get(DhcpOptionKind::DhcpMessageType),
+//!             Ok(Some(&[u8::from(DhcpMessageKind::Discover)][..]))
+//!         );
+//!     }
+//!     x => panic!("{x:?}"),
+//! }
+//! ```
+
+use crate::*;
+
+/// Maximum delay before the first DISCOVER, to avoid every host on a network starting at once (milliseconds)
+pub const DHCP_INIT_WAIT_MS: u64 = 10_000;
+/// Wait for a reply to the first DISCOVER or REQUEST, doubled after each retransmission (milliseconds)
+pub const DHCP_INITIAL_TIMEOUT_MS: u64 = 4_000;
+/// Longest wait for a reply before retransmitting (milliseconds)
+pub const DHCP_MAX_TIMEOUT_MS: u64 = 64_000;
+/// Number of DISCOVERs without an offer after which [DhcpEvent::Failed] is reported
+pub const DHCP_DISCOVER_ATTEMPTS: u8 = 4;
+/// Number of REQUESTs without an answer after which the client starts over with a DISCOVER
+pub const DHCP_REQUEST_ATTEMPTS: u8 = 4;
+/// Shortest wait before retransmitting while renewing or rebinding (milliseconds)
+pub const DHCP_MIN_RENEW_RETRY_MS: u64 = 60_000;
+/// Lease time meaning that the lease never expires (seconds)
+pub const DHCP_INFINITE_LEASE: u32 = u32::MAX;
+/// Number of DNS servers kept from a lease
+pub const DHCP_MAX_DNS_SERVERS: usize = 3;
+
+/// Options the server is asked to include in its replies
+const PARAMETER_REQUEST_LIST: [DhcpOptionKind; 4] = [
+    DhcpOptionKind::SubnetMask,
+    DhcpOptionKind::Router,
+    DhcpOptionKind::DomainNameServers,
+    DhcpOptionKind::BroadcastAddress,
+];
+
+/// Progress of acquiring and keeping a lease
+#[derive(Clone, Copy, uDebug, Debug, PartialEq, Eq)]
+pub enum DhcpState {
+    /// Waiting to start
+    Init,
+    /// Broadcasting DISCOVERs and waiting for an offer
+    Selecting,
+    /// Requesting the offered address and waiting for the server to acknowledge it
+    Requesting,
+    /// Holding a lease until it is time to renew it
+    Bound,
+    /// Asking the server that granted the lease to extend it
+    Renewing,
+    /// Asking any server to extend the lease, because the one that granted it did not answer
+    Rebinding,
+}
+
+/// An address granted by a server, the network configuration that goes with it,
+/// and when it must be renewed
+#[derive(Clone, Copy, uDebug, Debug, PartialEq, Eq)]
+pub struct DhcpLease {
+    /// Our address
+    pub ipaddr: IpV4Addr,
+    /// Server that granted the lease
+    pub server_id: IpV4Addr,
+    /// Subnet mask, if the server gave one
+    pub subnet_mask: Option<IpV4Addr>,
+    /// Default gateway; the first router, if the server gave any
+    pub router: Option<IpV4Addr>,
+    /// DNS servers in order of preference; unused entries are 0.0.0.0
+    pub dns_servers: [IpV4Addr; DHCP_MAX_DNS_SERVERS],
+    /// Subnet broadcast address, if the server gave one
+    pub broadcast: Option<IpV4Addr>,
+    /// Duration of the lease (seconds), or [DHCP_INFINITE_LEASE]
+    pub lease_time: u32,
+    /// Time after the lease was granted at which to start renewing it, T1 (seconds)
+    pub renewal_time: u32,
+    /// Time after the lease was granted at which to start rebinding it, T2 (seconds)
+    pub rebinding_time: u32,
+    /// Time at which the lease was granted (milliseconds)
+    pub acquired_at: u64,
+}
+
+impl DhcpLease {
+    /// Decode the lease in an ACK received at time `now`.
+    ///
+    /// The server identifier and lease time options are required. Other options that are malformed
+    /// are skipped, as if the server had not sent them. T1 and T2 default to half and seven eighths
+    /// of the lease time, and per RFC 2131 4.4.5 the defaults are also used in place of a T2 past the
+    /// end of the lease or a T1 past T2. Extra DNS servers past [DHCP_MAX_DNS_SERVERS] are dropped.
+    pub fn from_reply<T: AsRef<[u8]>>(msg: &DhcpView<T>, now: u64) -> Result<Self, ParseError> {
+        let mut lease = DhcpLease {
+            ipaddr: msg.yiaddr(),
+            server_id: IpV4Addr::ANY,
+            subnet_mask: None,
+            router: None,
+            dns_servers: [IpV4Addr::ANY; DHCP_MAX_DNS_SERVERS],
+            broadcast: None,
+            lease_time: 0,
+            renewal_time: 0,
+            rebinding_time: 0,
+            acquired_at: now,
+        };
+        let mut server_id = None;
+        let mut lease_time = None;
+        let mut renewal_time = None;
+        let mut rebinding_time = None;
+
+        // A malformed options section ends the search, and is reported if a required option is missing
+        let mut malformed = None;
+        for option in msg.iter_options() {
+            let (kind, data) = match option {
+                Ok(x) => x,
+                Err(x) => {
+                    malformed = Some(x);
+                    break;
+                }
+            };
+            let code = u8::from(kind);
+            match kind {
+                DhcpOptionKind::SubnetMask => lease.subnet_mask = option_ipaddr(data, code).ok(),
+                DhcpOptionKind::Router => {
+                    lease.router = option_ipaddrs(data, code).ok().and_then(|mut x| x.next())
+                }
+                DhcpOptionKind::DomainNameServers => {
+                    lease.dns_servers = [IpV4Addr::ANY; DHCP_MAX_DNS_SERVERS];
+                    if let Ok(servers) = option_ipaddrs(data, code) {
+                        for (dst, x) in lease.dns_servers.iter_mut().zip(servers) {
+                            *dst = x;
+                        }
+                    }
+                }
+                DhcpOptionKind::BroadcastAddress => {
+                    lease.broadcast = option_ipaddr(data, code).ok()
+                }
+                DhcpOptionKind::ServerIdentifier => server_id = Some(option_ipaddr(data, code)?),
+                DhcpOptionKind::IpAddressLeaseTime => lease_time = Some(option_u32(data, code)?),
+                DhcpOptionKind::RenewalTime => renewal_time = option_u32(data, code).ok(),
+                DhcpOptionKind::RebindingTime => rebinding_time = option_u32(data, code).ok(),
+                _ => {}
+            }
+        }
+
+        let missing = |kind: DhcpOptionKind| {
+            malformed.unwrap_or(ParseError::MissingDhcpOption(u8::from(kind)))
+        };
+        lease.server_id = server_id.ok_or_else(|| missing(DhcpOptionKind::ServerIdentifier))?;
+        lease.lease_time = lease_time.ok_or_else(|| missing(DhcpOptionKind::IpAddressLeaseTime))?;
+        lease.rebinding_time = rebinding_time
+            .filter(|x| *x <= lease.lease_time)
+            .unwrap_or((lease.lease_time as u64 * 7 / 8) as u32);
+        lease.renewal_time = renewal_time
+            .filter(|x| *x <= lease.rebinding_time)
+            .unwrap_or((lease.lease_time / 2).min(lease.rebinding_time));
+
+        Ok(lease)
+    }
+
+    /// Set the interface's address, subnet mask, and gateway from the lease.
+    /// A subnet mask or router that the server did not give is set to 0.0.0.0.
+    pub fn configure<const N: usize, const P: usize>(&self, iface: &mut Interface<N, P>) {
+        iface.set_ipaddr(self.ipaddr);
+        iface.set_subnet_mask(self.subnet_mask.unwrap_or(IpV4Addr::ANY));
+        iface.set_gateway(self.router.unwrap_or(IpV4Addr::ANY));
+    }
+
+    /// Configured DNS servers, skipping unused entries
+    pub fn iter_dns_servers(&self) -> impl Iterator<Item = IpV4Addr> + '_ {
+        self.dns_servers
+            .iter()
+            .copied()
+            .filter(|x| *x != IpV4Addr::ANY)
+    }
+
+    /// Time at which to start renewing the lease (milliseconds)
+    pub fn renew_at(&self) -> u64 {
+        self.after(self.renewal_time)
+    }
+
+    /// Time at which to start rebinding the lease (milliseconds)
+    pub fn rebind_at(&self) -> u64 {
+        self.after(self.rebinding_time)
+    }
+
+    /// Time at which the lease expires and the address must no longer be used (milliseconds)
+    pub fn expires_at(&self) -> u64 {
+        self.after(self.lease_time)
+    }
+
+    /// Time some number of seconds after the lease was granted, or never if the lease is infinite
+    fn after(&self, secs: u32) -> u64 {
+        match self.lease_time {
+            DHCP_INFINITE_LEASE => u64::MAX,
+            _ => self
+                .acquired_at
+                .saturating_add((secs as u64).saturating_mul(1000)),
+        }
+    }
+}
+
+/// Something the application should act on
+#[derive(Clone, Copy, uDebug, Debug, PartialEq, Eq)]
+pub enum DhcpEvent {
+    /// Nothing to do right now
+    None,
+    /// Send the frame of this length at the start of the transmit buffer
+    Transmit(usize),
+    /// A lease was granted or renewed; configure the interface with its address
+    Bound(DhcpLease),
+    /// No server has answered yet; the client keeps trying, but a link-local address can be used meanwhile
+    Failed,
+    /// The lease expired or the server refused to renew it; stop using the address
+    LeaseLost,
+}
+
+/// DHCP client for one interface
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DhcpClient {
+    macaddr: MacAddr,
+    state: DhcpState,
+    rng: XorShift32,
+    /// Transaction ID of the current exchange
+    xid: u32,
+    /// Messages sent in the current state
+    attempts: u8,
+    next_at: u64,
+    /// Whether [DhcpEvent::Failed] has been reported since the last lease
+    failed: bool,
+    /// Address offered by the selected server
+    offered: IpV4Addr,
+    /// Server whose offer was selected, or that granted the lease
+    server_id: IpV4Addr,
+    /// Next hop toward the server, for renewing by unicast
+    server_macaddr: MacAddr,
+    lease: Option<DhcpLease>,
+}
+
+impl DhcpClient {
+    /// New client that sends its first DISCOVER after a random delay of up to [DHCP_INIT_WAIT_MS].
+    ///
+    /// The seed randomizes the delay, retransmission timing, and transaction IDs;
+    /// it should differ between hosts.
+    pub fn new(macaddr: MacAddr, seed: u32, now: u64) -> Self {
+        let mut rng = XorShift32::new(seed);
+        let next_at = now.saturating_add(rng.range(1_000, DHCP_INIT_WAIT_MS));
+
+        DhcpClient {
+            macaddr,
+            state: DhcpState::Init,
+            rng,
+            xid: 0,
+            attempts: 0,
+            next_at,
+            failed: false,
+            offered: IpV4Addr::ANY,
+            server_id: IpV4Addr::ANY,
+            server_macaddr: MacAddr::BROADCAST,
+            lease: None,
+        }
+    }
+
+    /// Progress of acquiring and keeping a lease
+    pub fn state(&self) -> DhcpState {
+        self.state
+    }
+
+    /// The current lease, if there is one
+    pub fn lease(&self) -> Option<DhcpLease> {
+        self.lease
+    }
+
+    /// Time at which [DhcpClient::poll] next has something to do, if any
+    pub fn next_at(&self) -> Option<u64> {
+        match self.next_at {
+            u64::MAX => None,
+            x => Some(x),
+        }
+    }
+
+    /// Send, retransmit, and renew when due, writing any frame to the start of the transmit buffer.
+    ///
+    /// The buffer must hold a frame with a 300-byte DHCP message. If it does not,
+    /// nothing is sent and the same step is tried again on the next call.
+    pub fn poll(&mut self, tx_buffer: &mut [u8], now: u64) -> Result<DhcpEvent, BuildError> {
+        if now < self.next_at {
+            return Ok(DhcpEvent::None);
+        }
+
+        match self.state {
+            DhcpState::Init => self.start(DhcpState::Selecting),
+            DhcpState::Selecting => {
+                if self.attempts >= DHCP_DISCOVER_ATTEMPTS && !self.failed {
+                    self.failed = true;
+                    return Ok(DhcpEvent::Failed);
+                }
+            }
+            DhcpState::Requesting => {
+                if self.attempts >= DHCP_REQUEST_ATTEMPTS {
+                    self.start(DhcpState::Selecting);
+                }
+            }
+            DhcpState::Bound => self.start(DhcpState::Renewing),
+            DhcpState::Renewing => {
+                if self.lease.is_some_and(|x| now >= x.rebind_at()) {
+                    self.start(DhcpState::Rebinding);
+                }
+            }
+            DhcpState::Rebinding => {
+                if self.lease.is_none_or(|x| now >= x.expires_at()) {
+                    self.reset(now);
+                    return Ok(DhcpEvent::LeaseLost);
+                }
+            }
+        }
+
+        self.send(tx_buffer, now)
+    }
+
+    /// Handle a received DHCP datagram, ignoring any that are not replies to our current exchange
+    pub fn process(&mut self, udp: &UdpPacket, now: u64) -> DhcpEvent {
+        if udp.udp.src_port != DHCP_SERVER_PORT {
+            return DhcpEvent::None;
+        }
+        let msg = match DhcpView::new(udp.payload) {
+            Ok(x) => x,
+            Err(_) => return DhcpEvent::None,
+        };
+        if msg.op() != DhcpOperation::Reply || msg.xid() != self.xid || msg.chaddr() != self.macaddr
+        {
+            return DhcpEvent::None;
+        }
+        let options = msg.iter_options();
+        let kind = match options.get(DhcpOptionKind::DhcpMessageType) {
+            Ok(Some(&[x])) => DhcpMessageKind::from(x),
+            _ => return DhcpEvent::None,
+        };
+        let server_id = match options.get(DhcpOptionKind::ServerIdentifier) {
+            Ok(Some(&[a, b, c, d])) => Some(IpV4Addr::new([a, b, c, d])),
+            _ => None,
+        };
+
+        match (self.state, kind) {
+            (DhcpState::Selecting, DhcpMessageKind::Offer) => match server_id {
+                Some(server_id) => {
+                    // Take the first offer, and request it right away
+                    self.offered = msg.yiaddr();
+                    self.server_id = server_id;
+                    self.state = DhcpState::Requesting;
+                    self.attempts = 0;
+                    self.next_at = now;
+                    DhcpEvent::None
+                }
+                None => DhcpEvent::None,
+            },
+            // Only the selected server's answer counts
+            (DhcpState::Requesting, _) if server_id != Some(self.server_id) => DhcpEvent::None,
+            (
+                DhcpState::Requesting | DhcpState::Renewing | DhcpState::Rebinding,
+                DhcpMessageKind::Ack,
+            ) => match DhcpLease::from_reply(&msg, now) {
+                Ok(lease) => {
+                    self.state = DhcpState::Bound;
+                    self.attempts = 0;
+                    self.failed = false;
+                    self.server_id = lease.server_id;
+                    self.server_macaddr = udp.ethernet.src_macaddr;
+                    self.next_at = lease.renew_at();
+                    self.lease = Some(lease);
+                    DhcpEvent::Bound(lease)
+                }
+                Err(_) => DhcpEvent::None,
+            },
+            (
+                DhcpState::Requesting | DhcpState::Renewing | DhcpState::Rebinding,
+                DhcpMessageKind::Nak,
+            ) => {
+                let had_lease = self.lease.is_some();
+                self.reset(now);
+                match had_lease {
+                    true => DhcpEvent::LeaseLost,
+                    false => DhcpEvent::None,
+                }
+            }
+            _ => DhcpEvent::None,
+        }
+    }
+
+    /// Drop any lease and start over with a DISCOVER
+    fn reset(&mut self, now: u64) {
+        self.state = DhcpState::Init;
+        self.lease = None;
+        self.next_at = now;
+    }
+
+    /// Begin a new exchange
+    fn start(&mut self, state: DhcpState) {
+        self.state = state;
+        self.attempts = 0;
+        self.xid = self.rng.next_u32();
+    }
+
+    /// Write the message for the current state and schedule its retransmission
+    fn send(&mut self, tx_buffer: &mut [u8], now: u64) -> Result<DhcpEvent, BuildError> {
+        let leased = self.lease.map(|x| x.ipaddr).unwrap_or(IpV4Addr::ANY);
+        let (kind, ciaddr, dst_macaddr, dst_ipaddr) = match self.state {
+            DhcpState::Selecting => (
+                DhcpMessageKind::Discover,
+                IpV4Addr::ANY,
+                MacAddr::BROADCAST,
+                IpV4Addr::BROADCAST,
+            ),
+            DhcpState::Requesting => (
+                DhcpMessageKind::Request,
+                IpV4Addr::ANY,
+                MacAddr::BROADCAST,
+                IpV4Addr::BROADCAST,
+            ),
+            DhcpState::Renewing => (
+                DhcpMessageKind::Request,
+                leased,
+                self.server_macaddr,
+                self.server_id,
+            ),
+            DhcpState::Rebinding => (
+                DhcpMessageKind::Request,
+                leased,
+                MacAddr::BROADCAST,
+                IpV4Addr::BROADCAST,
+            ),
+            DhcpState::Init | DhcpState::Bound => return Ok(DhcpEvent::None),
+        };
+
+        // Ask for replies to be broadcast until we have an address to receive them on
+        let broadcast = ciaddr == IpV4Addr::ANY;
+        let msg = DhcpFixedPayload::new(
+            false,
+            DhcpOperation::Request,
+            kind,
+            self.xid,
+            broadcast,
+            ciaddr,
+            IpV4Addr::ANY,
+            IpV4Addr::ANY,
+            self.macaddr,
+        );
+        let mut payload = [0_u8; DHCP_MIN_MESSAGE_LEN];
+        let mut writer = DhcpOptionsWriter::new(&mut payload, &msg)?;
+        writer.client_id(self.macaddr)?;
+        if self.state == DhcpState::Requesting {
+            writer
+                .requested_ip(self.offered)?
+                .server_id(self.server_id)?;
+        }
+        writer.parameter_request_list(&PARAMETER_REQUEST_LIST)?;
+        let len = writer.finish()?;
+
+        let len = UdpBuilder::new(
+            self.macaddr,
+            ciaddr,
+            DHCP_CLIENT_PORT,
+            dst_macaddr,
+            dst_ipaddr,
+            DHCP_SERVER_PORT,
+        )
+        .write_bytes(payload.get(..len).unwrap_or(&[]), tx_buffer)?;
+
+        self.next_at = match (self.state, self.lease) {
+            (DhcpState::Renewing, Some(lease)) => half_remaining(now, lease.rebind_at()),
+            (DhcpState::Rebinding, Some(lease)) => half_remaining(now, lease.expires_at()),
+            _ => {
+                let timeout = DHCP_INITIAL_TIMEOUT_MS
+                    .saturating_mul(1 << self.attempts.min(16))
+                    .min(DHCP_MAX_TIMEOUT_MS);
+                // Randomized by up to a second either way
+                let jitter = self.rng.range(0, 2_000);
+                now.saturating_add(timeout.saturating_sub(1_000).saturating_add(jitter))
+            }
+        };
+        self.attempts = self.attempts.saturating_add(1);
+
+        Ok(DhcpEvent::Transmit(len))
+    }
+}
+
+/// Retransmission time while renewing or rebinding: half of the time left before the deadline,
+/// but not less than a minute, and not past the deadline
+fn half_remaining(now: u64, deadline: u64) -> u64 {
+    let wait = (deadline.saturating_sub(now) / 2).max(DHCP_MIN_RENEW_RETRY_MS);
+    now.saturating_add(wait).min(deadline)
+}
+
+/// Address in an option holding exactly one address
+fn option_ipaddr(data: &[u8], code: u8) -> Result<IpV4Addr, ParseError> {
+    match data {
+        &[a, b, c, d] => Ok(IpV4Addr::new([a, b, c, d])),
+        _ => Err(ParseError::BadDhcpOption(code)),
+    }
+}
+
+/// Addresses in an option holding a list of one or more addresses
+fn option_ipaddrs(
+    data: &[u8],
+    code: u8,
+) -> Result<impl Iterator<Item = IpV4Addr> + '_, ParseError> {
+    if data.is_empty() || !data.len().is_multiple_of(4) {
+        return Err(ParseError::BadDhcpOption(code));
+    }
+
+    Ok(data.chunks_exact(4).map(|x| IpV4Addr::new(get_array(x, 0))))
+}
+
+/// Value of an option holding one 32-bit integer
+fn option_u32(data: &[u8], code: u8) -> Result<u32, ParseError> {
+    match data {
+        &[a, b, c, d] => Ok(u32::from_be_bytes([a, b, c, d])),
+        _ => Err(ParseError::BadDhcpOption(code)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const OUR_MAC: MacAddr = ByteArray([0x02, 0, 0, 0, 0, 1]);
+    const SERVER_MAC: MacAddr = ByteArray([0x02, 0, 0, 0, 0, 2]);
+    const SERVER_IP: IpV4Addr = ByteArray([10, 0, 0, 1]);
+    const OFFERED_IP: IpV4Addr = ByteArray([10, 0, 0, 120]);
+
+    /// Poll at the next scheduled time, returning the time and the DHCP message sent
+    fn next(client: &mut DhcpClient, tx: &mut [u8; 1514]) -> (u64, UdpHeader, IpV4Header) {
+        let now = client.next_at().unwrap();
+        let len = match client.poll(tx, now) {
+            Ok(DhcpEvent::Transmit(len)) => len,
+            x => panic!("{x:?}"),
+        };
+        match Packet::classify(&tx[..len], true) {
+            Ok(Packet::Udp(udp)) => (now, udp.udp, udp.ip),
+            x => panic!("{x:?}"),
+        }
+    }
+
+    /// Message kind and transaction ID of a sent message
+    fn sent(tx: &[u8]) -> (DhcpMessageKind, u32, DhcpView<&[u8]>) {
+        let view = DhcpView::new(&tx[42..]).unwrap();
+        let kind = view.iter_options().get(DhcpOptionKind::DhcpMessageType);
+        let kind = DhcpMessageKind::from(kind.unwrap().unwrap()[0]);
+        (kind, view.xid(), view)
+    }
+
+    /// Server reply with the given options, as received by the interface
+    fn reply(
+        kind: DhcpMessageKind,
+        xid: u32,
+        lease_time: Option<u32>,
+        buffer: &mut [u8],
+    ) -> UdpPacket<'_> {
+        let msg = DhcpFixedPayload::new(
+            false,
+            DhcpOperation::Reply,
+            kind,
+            xid,
+            false,
+            IpV4Addr::ANY,
+            OFFERED_IP,
+            SERVER_IP,
+            OUR_MAC,
+        );
+        let mut payload = [0_u8; 400];
+        let mut writer = DhcpOptionsWriter::new(&mut payload, &msg).unwrap();
+        writer.server_id(SERVER_IP).unwrap();
+        if let Some(x) = lease_time {
+            writer
+                .option(DhcpOptionKind::IpAddressLeaseTime, &x.to_be_bytes())
+                .unwrap();
+        }
+        let len = writer.finish().unwrap();
+        let builder = UdpBuilder::new(
+            SERVER_MAC,
+            SERVER_IP,
+            DHCP_SERVER_PORT,
+            OUR_MAC,
+            OFFERED_IP,
+            DHCP_CLIENT_PORT,
+        );
+        let len = builder.write_bytes(&payload[..len], buffer).unwrap();
+        match Packet::classify(&buffer[..len], true) {
+            Ok(Packet::Dhcp(udp)) => udp,
+            x => panic!("{x:?}"),
+        }
+    }
+
+    /// Run through DISCOVER, OFFER, REQUEST, and ACK, returning the lease
+    fn bind(client: &mut DhcpClient, tx: &mut [u8; 1514]) -> DhcpLease {
+        let mut rx = [0_u8; 1514];
+        let (now, _, _) = next(client, tx);
+        let (kind, xid, _) = sent(tx);
+        assert_eq!(kind, DhcpMessageKind::Discover);
+        let offer = reply(DhcpMessageKind::Offer, xid, None, &mut rx);
+        assert_eq!(client.process(&offer, now), DhcpEvent::None);
+        assert_eq!(client.state(), DhcpState::Requesting);
+
+        // Request goes out right away, naming the offered address and the server
+        let (now, _, ip) = next(client, tx);
+        assert_eq!(ip.dst_ipaddr, IpV4Addr::BROADCAST);
+        let (kind, request_xid, view) = sent(tx);
+        assert_eq!(kind, DhcpMessageKind::Request);
+        assert_eq!(request_xid, xid);
+        assert_eq!(
+            view.iter_options().get(DhcpOptionKind::RequestedIpAddress),
+            Ok(Some(&OFFERED_IP.0[..]))
+        );
+        assert_eq!(
+            view.iter_options().get(DhcpOptionKind::ServerIdentifier),
+            Ok(Some(&SERVER_IP.0[..]))
+        );
+
+        let ack = reply(DhcpMessageKind::Ack, xid, Some(3600), &mut rx);
+        match client.process(&ack, now) {
+            DhcpEvent::Bound(lease) => lease,
+            x => panic!("{x:?}"),
+        }
+    }
+
+    #[test]
+    fn test_lease() {
+        let mut client = DhcpClient::new(OUR_MAC, 1, 0);
+        let mut tx = [0_u8; 1514];
+        let mut rx = [0_u8; 1514];
+
+        let lease = bind(&mut client, &mut tx);
+        assert_eq!(client.state(), DhcpState::Bound);
+        assert_eq!(client.lease(), Some(lease));
+        assert_eq!(lease.ipaddr, OFFERED_IP);
+        assert_eq!(lease.server_id, SERVER_IP);
+        assert_eq!(lease.renewal_time, 1800);
+        assert_eq!(lease.rebinding_time, 3150);
+        assert_eq!(lease.renew_at(), lease.acquired_at + 1_800_000);
+        assert_eq!(client.next_at(), Some(lease.renew_at()));
+
+        // Renewal is unicast to the server from our address
+        let (now, udp, ip) = next(&mut client, &mut tx);
+        assert_eq!(now, lease.renew_at());
+        assert_eq!(client.state(), DhcpState::Renewing);
+        assert_eq!(ip.src_ipaddr, OFFERED_IP);
+        assert_eq!(ip.dst_ipaddr, SERVER_IP);
+        assert_eq!(udp.dst_port, DHCP_SERVER_PORT);
+        assert_eq!(&tx[..6], &SERVER_MAC.0);
+        let (kind, xid, view) = sent(&tx);
+        assert_eq!(kind, DhcpMessageKind::Request);
+        assert_eq!(view.ciaddr(), OFFERED_IP);
+        assert!(!view.broadcast());
+        assert_eq!(
+            view.iter_options().get(DhcpOptionKind::RequestedIpAddress),
+            Ok(None)
+        );
+
+        // Retransmitted at half the time left before rebinding
+        let (later, _, _) = next(&mut client, &mut tx);
+        assert_eq!(later, now + (lease.rebind_at() - now) / 2);
+
+        // Renewed lease is timed from the ACK
+        let ack = reply(DhcpMessageKind::Ack, xid, Some(7200), &mut rx);
+        let renewed = match client.process(&ack, later) {
+            DhcpEvent::Bound(x) => x,
+            x => panic!("{x:?}"),
+        };
+        assert_eq!(renewed.acquired_at, later);
+        assert_eq!(renewed.lease_time, 7200);
+
+        // No answer while renewing or rebinding loses the lease at expiry
+        let mut rebinding = false;
+        loop {
+            let now = client.next_at().unwrap();
+            match client.poll(&mut tx, now).unwrap() {
+                DhcpEvent::Transmit(_) => {
+                    if client.state() == DhcpState::Rebinding {
+                        rebinding = true;
+                        assert!(now >= renewed.rebind_at());
+                        assert_eq!(&tx[..6], &MacAddr::BROADCAST.0);
+                    }
+                }
+                DhcpEvent::LeaseLost => {
+                    assert_eq!(now, renewed.expires_at());
+                    break;
+                }
+                x => panic!("{x:?}"),
+            }
+        }
+        assert!(rebinding);
+        assert_eq!(client.state(), DhcpState::Init);
+        assert_eq!(client.lease(), None);
+
+        // Starts over right away
+        let (_, _, _) = next(&mut client, &mut tx);
+        assert_eq!(sent(&tx).0, DhcpMessageKind::Discover);
+    }
+
+    #[test]
+    fn test_retransmit() {
+        let mut client = DhcpClient::new(OUR_MAC, 2, 0);
+        let mut tx = [0_u8; 1514];
+        let first = client.next_at().unwrap();
+        assert!((1_000..=DHCP_INIT_WAIT_MS).contains(&first));
+
+        // Backoff doubles up to the maximum, with a second of jitter either way
+        let (mut prev, _, _) = next(&mut client, &mut tx);
+        let xid = sent(&tx).1;
+        for i in 0..DHCP_DISCOVER_ATTEMPTS - 1 {
+            let (now, _, _) = next(&mut client, &mut tx);
+            let expected = DHCP_INITIAL_TIMEOUT_MS << i;
+            assert!((expected - 1_000..=expected + 1_000).contains(&(now - prev)));
+            assert_eq!(sent(&tx).1, xid);
+            prev = now;
+        }
+
+        // Failure is reported once, and the client keeps trying
+        let now = client.next_at().unwrap();
+        assert_eq!(client.poll(&mut tx, now), Ok(DhcpEvent::Failed));
+        for _ in 0..4 {
+            let (now, _, _) = next(&mut client, &mut tx);
+            assert!(now - prev <= DHCP_MAX_TIMEOUT_MS + 1_000);
+            prev = now;
+        }
+        assert_eq!(client.state(), DhcpState::Selecting);
+
+        // The timeout stays at the maximum however long there is no server
+        for _ in 0..300 {
+            let (now, _, _) = next(&mut client, &mut tx);
+            assert!(now - prev >= DHCP_MAX_TIMEOUT_MS - 1_000);
+            assert!(now - prev <= DHCP_MAX_TIMEOUT_MS + 1_000);
+            prev = now;
+        }
+
+        // A buffer that is too small sends nothing, and the same step is tried again
+        let now = client.next_at().unwrap();
+        assert_eq!(
+            client.poll(&mut [0; 100], now),
+            Err(BuildError::BufferTooSmall)
+        );
+        assert_eq!(client.next_at(), Some(now));
+    }
+
+    #[test]
+    fn test_nak() {
+        let mut client = DhcpClient::new(OUR_MAC, 3, 0);
+        let mut tx = [0_u8; 1514];
+        let mut rx = [0_u8; 1514];
+        bind(&mut client, &mut tx);
+
+        // Replies to other exchanges are ignored
+        let now = client.lease().unwrap().renew_at();
+        let (_, _, _) = next(&mut client, &mut tx);
+        let xid = sent(&tx).1;
+        let stale = reply(DhcpMessageKind::Nak, xid.wrapping_add(1), None, &mut rx);
+        assert_eq!(client.process(&stale, now), DhcpEvent::None);
+        assert_eq!(client.state(), DhcpState::Renewing);
+
+        // Refusal to renew loses the lease
+        let nak = reply(DhcpMessageKind::Nak, xid, None, &mut rx);
+        assert_eq!(client.process(&nak, now), DhcpEvent::LeaseLost);
+        assert_eq!(client.state(), DhcpState::Init);
+        assert_eq!(client.lease(), None);
+
+        // An ACK without a lease time is not a usable lease
+        let (_, _, _) = next(&mut client, &mut tx);
+        let xid = sent(&tx).1;
+        let offer = reply(DhcpMessageKind::Offer, xid, None, &mut rx);
+        client.process(&offer, now);
+        let (_, _, _) = next(&mut client, &mut tx);
+        let ack = reply(DhcpMessageKind::Ack, xid, None, &mut rx);
+        assert_eq!(client.process(&ack, now), DhcpEvent::None);
+        assert_eq!(client.state(), DhcpState::Requesting);
+    }
+
+    #[test]
+    fn test_lease_options() {
+        let msg = DhcpFixedPayload::new(
+            false,
+            DhcpOperation::Reply,
+            DhcpMessageKind::Ack,
+            1,
+            false,
+            IpV4Addr::ANY,
+            OFFERED_IP,
+            SERVER_IP,
+            OUR_MAC,
+        );
+        let mut buffer = [0_u8; 400];
+        let mut writer = DhcpOptionsWriter::new(&mut buffer, &msg).unwrap();
+        writer
+            .server_id(SERVER_IP)
+            .unwrap()
+            .option(DhcpOptionKind::IpAddressLeaseTime, &86400_u32.to_be_bytes())
+            .unwrap()
+            .option(DhcpOptionKind::RenewalTime, &600_u32.to_be_bytes())
+            .unwrap()
+            .option(DhcpOptionKind::SubnetMask, &[255, 255, 255, 0])
+            .unwrap()
+            .option(DhcpOptionKind::Router, &[10, 0, 0, 254, 10, 0, 0, 253])
+            .unwrap()
+            .option(
+                DhcpOptionKind::DomainNameServers,
+                &[1, 1, 1, 1, 8, 8, 8, 8, 9, 9, 9, 9, 4, 4, 4, 4],
+            )
+            .unwrap()
+            .option(DhcpOptionKind::BroadcastAddress, &[10, 0, 0, 255])
+            .unwrap();
+        let len = writer.finish().unwrap();
+
+        let lease = DhcpLease::from_reply(&DhcpView::new(&buffer[..len]).unwrap(), 5).unwrap();
+        assert_eq!(lease.ipaddr, OFFERED_IP);
+        assert_eq!(lease.server_id, SERVER_IP);
+        assert_eq!(lease.subnet_mask, Some(IpV4Addr::new([255, 255, 255, 0])));
+        assert_eq!(lease.router, Some(IpV4Addr::new([10, 0, 0, 254])));
+        assert_eq!(lease.broadcast, Some(IpV4Addr::new([10, 0, 0, 255])));
+        assert_eq!(lease.iter_dns_servers().count(), DHCP_MAX_DNS_SERVERS);
+        assert_eq!(lease.dns_servers[1], IpV4Addr::new([8, 8, 8, 8]));
+        assert_eq!((lease.lease_time, lease.renewal_time), (86400, 600));
+        assert_eq!(lease.rebinding_time, 75600);
+        assert_eq!(lease.acquired_at, 5);
+
+        // Applied to the interface
+        let mut iface: Interface =
+            Interface::new(OUR_MAC, IpV4Addr::ANY, IpV4Addr::ANY, IpV4Addr::ANY);
+        lease.configure(&mut iface);
+        assert_eq!(iface.ipaddr(), OFFERED_IP);
+        assert_eq!(iface.subnet_mask(), IpV4Addr::new([255, 255, 255, 0]));
+        assert_eq!(iface.gateway(), IpV4Addr::new([10, 0, 0, 254]));
+        assert_eq!(iface.subnet_broadcast(), lease.broadcast.unwrap());
+
+        // Optional options of the wrong length are skipped
+        let mut writer = DhcpOptionsWriter::new(&mut buffer, &msg).unwrap();
+        writer
+            .server_id(SERVER_IP)
+            .unwrap()
+            .option(DhcpOptionKind::IpAddressLeaseTime, &86400_u32.to_be_bytes())
+            .unwrap()
+            .option(DhcpOptionKind::SubnetMask, &[255, 255, 255])
+            .unwrap()
+            .option(DhcpOptionKind::Router, &[10, 0, 0])
+            .unwrap()
+            .option(DhcpOptionKind::RenewalTime, &[0, 0])
+            .unwrap();
+        let len = writer.finish().unwrap();
+        let lease = DhcpLease::from_reply(&DhcpView::new(&buffer[..len]).unwrap(), 5).unwrap();
+        assert_eq!(lease.server_id, SERVER_IP);
+        assert_eq!((lease.subnet_mask, lease.router), (None, None));
+        assert_eq!(lease.renewal_time, 43200);
+
+        // Inconsistent T1 and T2 fall back to the defaults
+        for (t1, t2, expected) in [
+            (Some(80000), Some(70000), (43200, 70000)),
+            (Some(600), Some(90000), (600, 75600)),
+            (None, Some(3600), (3600, 3600)),
+        ] {
+            let mut writer = DhcpOptionsWriter::new(&mut buffer, &msg).unwrap();
+            writer
+                .server_id(SERVER_IP)
+                .unwrap()
+                .option(DhcpOptionKind::IpAddressLeaseTime, &86400_u32.to_be_bytes())
+                .unwrap();
+            if let Some(t1) = t1 {
+                writer
+                    .option(DhcpOptionKind::RenewalTime, &u32::to_be_bytes(t1))
+                    .unwrap();
+            }
+            if let Some(t2) = t2 {
+                writer
+                    .option(DhcpOptionKind::RebindingTime, &u32::to_be_bytes(t2))
+                    .unwrap();
+            }
+            let len = writer.finish().unwrap();
+            let lease = DhcpLease::from_reply(&DhcpView::new(&buffer[..len]).unwrap(), 5).unwrap();
+            assert_eq!((lease.renewal_time, lease.rebinding_time), expected);
+        }
+
+        // Required options of the wrong length are reported
+        let mut writer = DhcpOptionsWriter::new(&mut buffer, &msg).unwrap();
+        writer
+            .option(DhcpOptionKind::ServerIdentifier, &[10, 0, 0])
+            .unwrap()
+            .option(DhcpOptionKind::IpAddressLeaseTime, &86400_u32.to_be_bytes())
+            .unwrap();
+        let len = writer.finish().unwrap();
+        assert_eq!(
+            DhcpLease::from_reply(&DhcpView::new(&buffer[..len]).unwrap(), 5),
+            Err(ParseError::BadDhcpOption(54))
+        );
+
+        // Missing required options are reported
+        let mut writer = DhcpOptionsWriter::new(&mut buffer, &msg).unwrap();
+        writer.server_id(SERVER_IP).unwrap();
+        let len = writer.finish().unwrap();
+        assert_eq!(
+            DhcpLease::from_reply(&DhcpView::new(&buffer[..len]).unwrap(), 5),
+            Err(ParseError::MissingDhcpOption(51))
+        );
+    }
+}
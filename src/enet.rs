@@ -1,11 +1,11 @@
 //! Link layer: Ethernet II protocol.
 //! See <https://en.wikipedia.org/wiki/Ethernet_frame#Ethernet_II>.
 
-use crate::MacAddr;
+use crate::{get_array, get_u16, set_array, set_u16, MacAddr, ParseError, TryByteStruct};
 
 use byte_struct::*;
-use ufmt::derive::uDebug;
 use static_assertions::const_assert;
+use ufmt::derive::uDebug;
 
 // In general, this could be 18 bytes for a 802.1Q tagged vlan,
 // but that is not supported here because tagged vlan is spammy and unsecure.
@@ -32,6 +32,15 @@ impl EthernetHeader {
     }
 }
 
+impl TryByteStruct for EthernetHeader {
+    fn try_read_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        let bytes = bytes.get(..Self::BYTE_LEN).ok_or(ParseError::Truncated)?;
+        // Reject ethertypes that would be flattened to Unimplemented
+        EtherType::try_read_bytes(&bytes[12..])?;
+        Ok(Self::read_bytes(bytes))
+    }
+}
+
 /// Ethernet frame around arbitrary data
 #[derive(Clone, Copy, uDebug, Debug, PartialEq, Eq)]
 pub struct EthernetFrame<T>
@@ -79,6 +88,25 @@ where
     }
 }
 
+impl<T> TryByteStruct for EthernetFrame<T>
+where
+    T: TryByteStruct,
+{
+    fn try_read_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        let bytes = bytes.get(..Self::BYTE_LEN).ok_or(ParseError::Truncated)?;
+        let (header_bytes, rest) = bytes.split_at(EthernetHeader::BYTE_LEN);
+        let (data_bytes, checksum_bytes) = rest.split_at(T::BYTE_LEN);
+        let mut checksum = [0_u8; 4];
+        checksum.copy_from_slice(checksum_bytes);
+
+        Ok(EthernetFrame::<T> {
+            header: EthernetHeader::try_read_bytes(header_bytes)?,
+            data: T::try_read_bytes(data_bytes)?,
+            checksum: u32::from_be_bytes(checksum),
+        })
+    }
+}
+
 impl<T> EthernetFrame<T>
 where
     T: ByteStruct,
@@ -100,6 +128,8 @@ pub enum EtherType {
     IpV4 = 0x0800,
     /// Address resolution protocol
     Arp = 0x0806,
+    /// Reverse address resolution protocol
+    Rarp = 0x8035,
     /// Tagged virtual LAN - if this tag is encountered, then this is not the real ethertype field, and we're reading an 802.1Q Vlan tag instead
     /// This crate does not support tagged Vlan, which is a trust-based and inefficient system. Untagged Vlan should be used instead.
     Vlan = 0x8100,
@@ -121,6 +151,7 @@ impl From<u16> for EtherType {
             x if x == EtherType::IpV4 as u16 => EtherType::IpV4,
             x if x == EtherType::IpV6 as u16 => EtherType::IpV6,
             x if x == EtherType::Ptp as u16 => EtherType::Ptp,
+            x if x == EtherType::Rarp as u16 => EtherType::Rarp,
             x if x == EtherType::Vlan as u16 => EtherType::Vlan,
             _ => EtherType::Unimplemented,
         }
@@ -135,7 +166,7 @@ impl ByteStruct for EtherType {
     fn read_bytes(bytes: &[u8]) -> Self {
         let mut bytes_read = [0_u8; 2];
         bytes_read.copy_from_slice(&bytes[0..=1]);
-        return EtherType::from(u16::from_be_bytes(bytes_read));
+        EtherType::from(u16::from_be_bytes(bytes_read))
     }
 
     fn write_bytes(&self, bytes: &mut [u8]) {
@@ -145,9 +176,112 @@ impl ByteStruct for EtherType {
     }
 }
 
+impl TryByteStruct for EtherType {
+    fn try_read_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        let mut bytes_read = [0_u8; 2];
+        bytes_read.copy_from_slice(bytes.get(0..=1).ok_or(ParseError::Truncated)?);
+        let value = u16::from_be_bytes(bytes_read);
+        match EtherType::from(value) {
+            EtherType::Unimplemented => Err(ParseError::UnknownEtherType(value)),
+            x => Ok(x),
+        }
+    }
+}
+
 impl EtherType {
     /// Pack into big-endian (network) byte array
     pub fn to_be_bytes(&self) -> [u8; Self::BYTE_LEN] {
         (*self as u16).to_be_bytes()
     }
 }
+
+/// Borrowed view of an Ethernet II frame in a buffer, such as a DMA descriptor's memory,
+/// with typed access to header fields in place and without copying the frame.
+///
+/// The buffer may be a received frame (`&[u8]`) or a frame being assembled for transmit (`&mut [u8]`).
+/// The payload is everything after the header, including the frame check sequence
+/// if the hardware leaves it in the buffer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EthernetView<T: AsRef<[u8]>> {
+    buffer: T,
+}
+
+impl<T: AsRef<[u8]>> EthernetView<T> {
+    /// Wrap a buffer, checking that it is long enough to hold an ethernet header
+    pub fn new(buffer: T) -> Result<Self, ParseError> {
+        if buffer.as_ref().len() < EthernetHeader::BYTE_LEN {
+            return Err(ParseError::Truncated);
+        }
+
+        Ok(EthernetView { buffer })
+    }
+
+    /// Release the underlying buffer
+    pub fn into_inner(self) -> T {
+        self.buffer
+    }
+
+    /// Destination MAC address
+    pub fn dst_macaddr(&self) -> MacAddr {
+        MacAddr::new(get_array(self.buffer.as_ref(), 0))
+    }
+
+    /// Source MAC address
+    pub fn src_macaddr(&self) -> MacAddr {
+        MacAddr::new(get_array(self.buffer.as_ref(), 6))
+    }
+
+    /// Type of content
+    pub fn ethertype(&self) -> EtherType {
+        EtherType::from(get_u16(self.buffer.as_ref(), 12))
+    }
+
+    /// Copy out the header
+    pub fn header(&self) -> EthernetHeader {
+        EthernetHeader {
+            dst_macaddr: self.dst_macaddr(),
+            src_macaddr: self.src_macaddr(),
+            ethertype: self.ethertype(),
+        }
+    }
+
+    /// Everything after the header
+    pub fn payload(&self) -> &[u8] {
+        self.buffer
+            .as_ref()
+            .get(EthernetHeader::BYTE_LEN..)
+            .unwrap_or(&[])
+    }
+}
+
+impl<T: AsRef<[u8]> + AsMut<[u8]>> EthernetView<T> {
+    /// Set destination MAC address
+    pub fn set_dst_macaddr(&mut self, value: MacAddr) {
+        set_array(self.buffer.as_mut(), 0, &value.0);
+    }
+
+    /// Set source MAC address
+    pub fn set_src_macaddr(&mut self, value: MacAddr) {
+        set_array(self.buffer.as_mut(), 6, &value.0);
+    }
+
+    /// Set type of content
+    pub fn set_ethertype(&mut self, value: EtherType) {
+        set_u16(self.buffer.as_mut(), 12, value as u16);
+    }
+
+    /// Overwrite the whole header
+    pub fn set_header(&mut self, value: &EthernetHeader) {
+        self.set_dst_macaddr(value.dst_macaddr);
+        self.set_src_macaddr(value.src_macaddr);
+        self.set_ethertype(value.ethertype);
+    }
+
+    /// Mutable access to everything after the header
+    pub fn payload_mut(&mut self) -> &mut [u8] {
+        self.buffer
+            .as_mut()
+            .get_mut(EthernetHeader::BYTE_LEN..)
+            .unwrap_or(&mut [])
+    }
+}
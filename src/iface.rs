@@ -0,0 +1,799 @@
+//! Network interface holding our addressing state.
+//!
+//! The [Interface] ties the wire types together into a minimal stack: it filters received
+//! frames down to those addressed to us, answers ARP requests for our address and any proxy ARP
+//! ranges, remembers the hardware addresses of neighbors that contact us, and hands UDP data to
+//! the application.
+//!
+//! The interface does not own any buffers or hardware. Each received frame is passed to
+//! [Interface::poll] along with a transmit buffer, and the returned [InterfaceEvent] says
+//! whether a response was written to the transmit buffer or data was received.
+//!
+//! ```rust
+//! use catnip::*;
+//!
+//! let mut iface: Interface = Interface::new(
+//!     MacAddr::new([0x02, 0xAF, 0xFF, 0x1A, 0xE5, 0x3C]),
+//!     IpV4Addr::new([10, 0, 0, 120]),
+//!     IpV4Addr::new([255, 255, 255, 0]),
+//!     IpV4Addr::new([10, 0, 0, 1]),
+//! );
+//!
+//! // A peer asks who has our address
+//! let request = EthernetFrame {
+//!     header: EthernetHeader {
+//!         dst_macaddr: MacAddr::BROADCAST,
+//!         src_macaddr: MacAddr::new([0x02, 0, 0, 0, 0, 1]),
+//!         ethertype: EtherType::Arp,
+//!     },
+//!     data: ArpPayload::new(
+//!         MacAddr::new([0x02, 0, 0, 0, 0, 1]),
+//!         IpV4Addr::new([10, 0, 0, 121]),
+//!         MacAddr::ANY,
+//!         IpV4Addr::new([10, 0, 0, 120]),
+//!         ArpOperation::Request,
+//!     ),
+//!     checksum: 0,
+//! };
+//!
+//! // ... and gets an answer
+//! let rx_frame = request.to_be_bytes();
+//! let mut tx_buffer = [0_u8; 1514];
+//! let event = iface.poll(&rx_frame, &mut tx_buffer, 0);
+//! assert_eq!(event, InterfaceEvent::Transmit(60));
+//! assert_eq!(
+//!     iface.arp_cache().lookup(IpV4Addr::new([10, 0, 0, 121])),
+//!     Some(MacAddr::new([0x02, 0, 0, 0, 0, 1]))
+//! );
+//! ```
+
+use crate::*;
+
+/// Something the application should act on after a received frame has been processed
+#[derive(Clone, Copy, uDebug, Debug, PartialEq, Eq)]
+pub enum InterfaceEvent<'a> {
+    /// The frame was handled internally and nothing needs to be sent
+    None,
+    /// A response was written to the start of the transmit buffer; send this many bytes
+    Transmit(usize),
+    /// A UDP datagram addressed to us
+    Udp(UdpPacket<'a>),
+    /// A UDP datagram addressed to the DHCP client port, which is accepted
+    /// even before we have an address
+    Dhcp(UdpPacket<'a>),
+    /// An ARP message contradicted a static or recently learned entry in the ARP cache.
+    ///
+    /// A static entry is kept and the message is not answered. Otherwise the new address
+    /// was learned and the message was answered as usual: if a reply was written to the start
+    /// of the transmit buffer, this is the number of bytes to send.
+    ArpMismatch(ArpMismatch, Option<usize>),
+    /// The frame was discarded
+    Dropped(DropReason),
+}
+
+/// Why a received frame was discarded
+#[derive(Clone, Copy, uDebug, Debug, PartialEq, Eq)]
+pub enum DropReason {
+    /// The frame was truncated, corrupted, or had inconsistent length fields
+    Malformed(ParseError),
+    /// The frame was addressed to another host
+    NotForUs,
+    /// The frame uses an ethertype or protocol that is not handled here, or is an IPV4 fragment
+    Unsupported,
+    /// A response was needed, but the transmit buffer was too small to hold it
+    TransmitBufferTooSmall,
+    /// A response was written, but the device would not send it
+    TransmitFailed(DeviceError),
+}
+
+/// Why a datagram could not be sent
+#[derive(Clone, Copy, uDebug, Debug, PartialEq, Eq)]
+pub enum SendError {
+    /// The hardware address of the next hop (the destination, or the gateway for
+    /// destinations outside the subnet) is not known yet. See [Interface::write_arp_request].
+    Unresolved(IpV4Addr),
+    /// The frame could not be written to the transmit buffer
+    Build(BuildError),
+}
+
+/// Our addressing state, an [ArpCache] of up to `N` neighbors' hardware addresses,
+/// and an [ArpResponder] answering for our address and up to `P` proxy ARP ranges.
+///
+/// Times are in milliseconds from an arbitrary monotonic epoch chosen by the application.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Interface<const N: usize = 8, const P: usize = 0> {
+    macaddr: MacAddr,
+    ipaddr: IpV4Addr,
+    subnet_mask: IpV4Addr,
+    gateway: IpV4Addr,
+    verify_checksums: bool,
+    fill_checksums: bool,
+    arp_cache: ArpCache<N>,
+    arp_responder: ArpResponder<P>,
+}
+
+impl<const N: usize> Interface<N> {
+    /// New interface with a given address.
+    ///
+    /// The IP address may be [IpV4Addr::ANY] if it is not known yet, and the gateway may be
+    /// [IpV4Addr::ANY] if there is none, in which case only hosts in the subnet are reachable.
+    pub fn new(
+        macaddr: MacAddr,
+        ipaddr: IpV4Addr,
+        subnet_mask: IpV4Addr,
+        gateway: IpV4Addr,
+    ) -> Self {
+        Interface {
+            macaddr,
+            ipaddr,
+            subnet_mask,
+            gateway,
+            verify_checksums: true,
+            fill_checksums: true,
+            arp_cache: ArpCache::new(ARP_CACHE_TIMEOUT_MS),
+            arp_responder: ArpResponder::new(macaddr, ipaddr),
+        }
+    }
+
+    /// Also answer ARP requests for addresses in these ranges with our MAC address,
+    /// for hosts that are reached through us. See [ArpResponder::proxy].
+    pub fn with_proxy_arp<const P: usize>(self, ranges: [IpV4Cidr; P]) -> Interface<N, P> {
+        Interface {
+            macaddr: self.macaddr,
+            ipaddr: self.ipaddr,
+            subnet_mask: self.subnet_mask,
+            gateway: self.gateway,
+            verify_checksums: self.verify_checksums,
+            fill_checksums: self.fill_checksums,
+            arp_cache: self.arp_cache,
+            arp_responder: ArpResponder::proxy(self.macaddr, self.ipaddr, ranges),
+        }
+    }
+}
+
+impl<const N: usize, const P: usize> Interface<N, P> {
+    /// Our MAC address
+    pub fn macaddr(&self) -> MacAddr {
+        self.macaddr
+    }
+
+    /// Our IP address, or [IpV4Addr::ANY] if we do not have one
+    pub fn ipaddr(&self) -> IpV4Addr {
+        self.ipaddr
+    }
+
+    /// Mask of the bits of our IP address that identify the subnet
+    pub fn subnet_mask(&self) -> IpV4Addr {
+        self.subnet_mask
+    }
+
+    /// Router for destinations outside the subnet, or [IpV4Addr::ANY] if there is none
+    pub fn gateway(&self) -> IpV4Addr {
+        self.gateway
+    }
+
+    /// Change our IP address, as when one is assigned
+    pub fn set_ipaddr(&mut self, ipaddr: IpV4Addr) {
+        self.ipaddr = ipaddr;
+        self.arp_responder.ipaddr = ipaddr;
+    }
+
+    /// Change the subnet mask
+    pub fn set_subnet_mask(&mut self, subnet_mask: IpV4Addr) {
+        self.subnet_mask = subnet_mask;
+    }
+
+    /// Change the router for destinations outside the subnet
+    pub fn set_gateway(&mut self, gateway: IpV4Addr) {
+        self.gateway = gateway;
+    }
+
+    /// Whether IP and UDP checksums of received frames are checked in software
+    pub fn verify_checksums(&self) -> bool {
+        self.verify_checksums
+    }
+
+    /// Enable or disable checking IP and UDP checksums of received frames in software.
+    /// Disable this if the hardware checks them and drops frames that fail.
+    pub fn set_verify_checksums(&mut self, verify_checksums: bool) {
+        self.verify_checksums = verify_checksums;
+    }
+
+    /// Whether UDP checksums of transmitted datagrams are calculated in software
+    pub fn fill_checksums(&self) -> bool {
+        self.fill_checksums
+    }
+
+    /// Enable or disable calculating UDP checksums of transmitted datagrams in software.
+    /// Disable this if the hardware fills them in. IP header checksums are always filled in.
+    pub fn set_fill_checksums(&mut self, fill_checksums: bool) {
+        self.fill_checksums = fill_checksums;
+    }
+
+    /// Match checksum handling to what the device does in hardware
+    pub fn apply_capabilities(&mut self, capabilities: &DeviceCapabilities) {
+        self.verify_checksums = !capabilities.rx_checksum_offload;
+        self.fill_checksums = !capabilities.tx_checksum_offload;
+    }
+
+    /// Broadcast address of our subnet
+    pub fn subnet_broadcast(&self) -> IpV4Addr {
+        let mut out = self.ipaddr;
+        for i in 0..4 {
+            out.0[i] |= !self.subnet_mask.0[i];
+        }
+        out
+    }
+
+    /// Whether an address is in our subnet
+    pub fn in_subnet(&self, ipaddr: IpV4Addr) -> bool {
+        (0..4).all(|i| (ipaddr.0[i] ^ self.ipaddr.0[i]) & self.subnet_mask.0[i] == 0)
+    }
+
+    /// Whether a datagram to this address is meant for us
+    fn accepts(&self, dst_ipaddr: IpV4Addr) -> bool {
+        self.ipaddr != IpV4Addr::ANY
+            && (dst_ipaddr == self.ipaddr
+                || dst_ipaddr == IpV4Addr::BROADCAST
+                || dst_ipaddr == self.subnet_broadcast())
+    }
+
+    /// Table of neighbors' hardware addresses
+    pub fn arp_cache(&self) -> &ArpCache<N> {
+        &self.arp_cache
+    }
+
+    /// Table of neighbors' hardware addresses, for adding or removing entries by hand
+    pub fn arp_cache_mut(&mut self) -> &mut ArpCache<N> {
+        &mut self.arp_cache
+    }
+
+    /// Responder answering ARP requests, with its proxy ranges and counts of requests answered
+    pub fn arp_responder(&self) -> &ArpResponder<P> {
+        &self.arp_responder
+    }
+
+    /// Address of the host a datagram is handed to on the way to its destination:
+    /// the destination itself if it is in the subnet, or the gateway if not
+    pub fn next_hop(&self, dst_ipaddr: IpV4Addr) -> IpV4Addr {
+        match self.in_subnet(dst_ipaddr) || self.gateway == IpV4Addr::ANY {
+            true => dst_ipaddr,
+            false => self.gateway,
+        }
+    }
+
+    /// Hardware address to send a datagram to, via the gateway if the destination is outside the subnet
+    pub fn resolve(&self, dst_ipaddr: IpV4Addr) -> Result<MacAddr, SendError> {
+        if dst_ipaddr == IpV4Addr::BROADCAST || dst_ipaddr == self.subnet_broadcast() {
+            return Ok(MacAddr::BROADCAST);
+        }
+        let next_hop = self.next_hop(dst_ipaddr);
+
+        self.arp_cache
+            .lookup(next_hop)
+            .ok_or(SendError::Unresolved(next_hop))
+    }
+
+    /// Process a received frame, writing any response to the start of the transmit buffer.
+    pub fn poll<'a>(
+        &mut self,
+        rx_frame: &'a [u8],
+        tx_buffer: &mut [u8],
+        now: u64,
+    ) -> InterfaceEvent<'a> {
+        self.arp_cache.age(now);
+
+        if let Ok(enet) = EthernetView::new(rx_frame) {
+            let dst_macaddr = enet.dst_macaddr();
+            if dst_macaddr != self.macaddr && dst_macaddr != MacAddr::BROADCAST {
+                return InterfaceEvent::Dropped(DropReason::NotForUs);
+            }
+        }
+
+        match Packet::classify(rx_frame, self.verify_checksums) {
+            Ok(Packet::Arp(frame)) => self.process_arp(&frame, tx_buffer, now),
+            Ok(Packet::Dhcp(udp)) => InterfaceEvent::Dhcp(udp),
+            Ok(Packet::Udp(udp)) => match self.accepts(udp.ip.dst_ipaddr) {
+                true => InterfaceEvent::Udp(udp),
+                false => InterfaceEvent::Dropped(DropReason::NotForUs),
+            },
+            Ok(_) => InterfaceEvent::Dropped(DropReason::Unsupported),
+            Err(x) => InterfaceEvent::Dropped(DropReason::Malformed(x)),
+        }
+    }
+
+    /// Receive a frame from the device, if there is one, and process it with [Interface::poll],
+    /// transmitting any response on the same device.
+    ///
+    /// Returns `None` if no frame was received. A response that was sent is reported as
+    /// [InterfaceEvent::None]; one that the device refused is reported as [DropReason::TransmitFailed].
+    /// An [InterfaceEvent::ArpMismatch] is always reported, without a length once its reply was sent.
+    pub fn poll_device<'a, D: Device>(
+        &mut self,
+        device: &mut D,
+        rx_buffer: &'a mut [u8],
+        tx_buffer: &mut [u8],
+        now: u64,
+    ) -> Option<InterfaceEvent<'a>> {
+        let len = device.receive(rx_buffer)?;
+        let rx_frame: &'a [u8] = rx_buffer.get(..len)?;
+
+        match self.poll(rx_frame, tx_buffer, now) {
+            InterfaceEvent::Transmit(len) => {
+                let frame = tx_buffer.get(..len)?;
+                match device.transmit(frame) {
+                    Ok(()) => Some(InterfaceEvent::None),
+                    Err(x) => Some(InterfaceEvent::Dropped(DropReason::TransmitFailed(x))),
+                }
+            }
+            InterfaceEvent::ArpMismatch(x, Some(len)) => {
+                let _ = device.transmit(tx_buffer.get(..len)?);
+                Some(InterfaceEvent::ArpMismatch(x, None))
+            }
+            x => Some(x),
+        }
+    }
+
+    /// Learn from ARP messages and answer requests for our address or proxied addresses,
+    /// unless they contradict a static entry
+    fn process_arp(
+        &mut self,
+        frame: &EthernetFrame<ArpPayload>,
+        tx_buffer: &mut [u8],
+        now: u64,
+    ) -> InterfaceEvent<'static> {
+        match self.arp_cache.process(&frame.data, self.ipaddr, now) {
+            Some(x) if x.pinned => InterfaceEvent::ArpMismatch(x, None),
+            Some(x) => match self.respond_arp(frame, tx_buffer) {
+                InterfaceEvent::Transmit(len) => InterfaceEvent::ArpMismatch(x, Some(len)),
+                _ => InterfaceEvent::ArpMismatch(x, None),
+            },
+            None => self.respond_arp(frame, tx_buffer),
+        }
+    }
+
+    /// Answer requests for our address or proxied addresses
+    fn respond_arp(
+        &mut self,
+        frame: &EthernetFrame<ArpPayload>,
+        tx_buffer: &mut [u8],
+    ) -> InterfaceEvent<'static> {
+        let msg = &frame.data;
+        if self.ipaddr == IpV4Addr::ANY {
+            return InterfaceEvent::Dropped(DropReason::NotForUs);
+        }
+
+        match self.arp_responder.respond(frame) {
+            Some(reply) => match write_arp(&reply, tx_buffer) {
+                Ok(len) => InterfaceEvent::Transmit(len),
+                Err(_) => InterfaceEvent::Dropped(DropReason::TransmitBufferTooSmall),
+            },
+            None if msg.dst_ipaddr != self.ipaddr => InterfaceEvent::Dropped(DropReason::NotForUs),
+            None => InterfaceEvent::None,
+        }
+    }
+
+    /// Write a broadcast ARP request for a neighbor's hardware address into the transmit buffer,
+    /// returning the number of bytes to send. The reply is learned by [Interface::poll].
+    pub fn write_arp_request(
+        &mut self,
+        ipaddr: IpV4Addr,
+        tx_buffer: &mut [u8],
+        now: u64,
+    ) -> Result<usize, BuildError> {
+        self.arp_cache.insert_incomplete(ipaddr, now);
+        let request = ArpPayload::new(
+            self.macaddr,
+            self.ipaddr,
+            MacAddr::ANY,
+            ipaddr,
+            ArpOperation::Request,
+        );
+        write_arp(&EthernetFrame::arp(MacAddr::BROADCAST, request), tx_buffer)
+    }
+
+    /// Write a broadcast ARP announcement of our address into the transmit buffer,
+    /// returning the number of bytes to send. Send this after boot or an address change.
+    pub fn write_arp_announce(&self, tx_buffer: &mut [u8]) -> Result<usize, BuildError> {
+        write_arp(
+            &EthernetFrame::arp_announce(self.macaddr, self.ipaddr),
+            tx_buffer,
+        )
+    }
+
+    /// Write a UDP datagram from us into the transmit buffer, returning the number of bytes to send
+    pub fn write_udp(
+        &self,
+        src_port: u16,
+        dst_ipaddr: IpV4Addr,
+        dst_port: u16,
+        payload: &[u8],
+        tx_buffer: &mut [u8],
+    ) -> Result<usize, SendError> {
+        let dst_macaddr = self.resolve(dst_ipaddr)?;
+        UdpBuilder::new(
+            self.macaddr,
+            self.ipaddr,
+            src_port,
+            dst_macaddr,
+            dst_ipaddr,
+            dst_port,
+        )
+        .with_udp_checksum(self.fill_checksums)
+        .write_bytes(payload, tx_buffer)
+        .map_err(SendError::Build)
+    }
+}
+
+/// Write an ARP frame into the transmit buffer without the frame check sequence,
+/// returning the number of bytes to send
+fn write_arp(frame: &EthernetFrame<ArpPayload>, tx_buffer: &mut [u8]) -> Result<usize, BuildError> {
+    let len = EthernetHeader::BYTE_LEN + ArpPayload::BYTE_LEN;
+    let buffer = tx_buffer.get_mut(..len).ok_or(BuildError::BufferTooSmall)?;
+    let (header_bytes, msg_bytes) = buffer.split_at_mut(EthernetHeader::BYTE_LEN);
+    frame.header.write_bytes(header_bytes);
+    frame.data.write_bytes(msg_bytes);
+
+    Ok(len)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const OUR_MAC: MacAddr = ByteArray([0x02, 0xAF, 0xFF, 0x1A, 0xE5, 0x3C]);
+    const OUR_IP: IpV4Addr = ByteArray([10, 0, 0, 120]);
+    const PEER_MAC: MacAddr = ByteArray([0x02, 0, 0, 0, 0, 1]);
+    const PEER_IP: IpV4Addr = ByteArray([10, 0, 0, 121]);
+    const GATEWAY_IP: IpV4Addr = ByteArray([10, 0, 0, 1]);
+
+    fn iface() -> Interface<2> {
+        Interface::new(
+            OUR_MAC,
+            OUR_IP,
+            IpV4Addr::new([255, 255, 255, 0]),
+            GATEWAY_IP,
+        )
+    }
+
+    fn arp_frame(msg: ArpPayload, dst_macaddr: MacAddr) -> [u8; 64] {
+        EthernetFrame {
+            header: EthernetHeader {
+                dst_macaddr,
+                src_macaddr: msg.src_mac,
+                ethertype: EtherType::Arp,
+            },
+            data: msg,
+            checksum: 0,
+        }
+        .to_be_bytes()
+    }
+
+    #[test]
+    fn test_arp() {
+        let mut iface = iface();
+        let mut tx = [0_u8; 128];
+
+        // Request for our address is answered
+        let request = ArpPayload::new(
+            PEER_MAC,
+            PEER_IP,
+            MacAddr::ANY,
+            OUR_IP,
+            ArpOperation::Request,
+        );
+        let len = match iface.poll(&arp_frame(request, MacAddr::BROADCAST), &mut tx, 0) {
+            InterfaceEvent::Transmit(len) => len,
+            x => panic!("{x:?}"),
+        };
+        let reply = match Packet::classify(&tx[..len], true) {
+            Ok(Packet::Arp(x)) => x,
+            x => panic!("{x:?}"),
+        };
+        assert_eq!(reply.header.dst_macaddr, PEER_MAC);
+        assert_eq!(reply.data.operation, ArpOperation::Response);
+        assert_eq!(reply.data.src_mac, OUR_MAC);
+        assert_eq!(reply.data.src_ipaddr, OUR_IP);
+        assert_eq!(reply.data.dst_ipaddr, PEER_IP);
+        assert_eq!(iface.arp_cache().lookup(PEER_IP), Some(PEER_MAC));
+
+        // Request for another address is not
+        let request = ArpPayload::new(
+            PEER_MAC,
+            PEER_IP,
+            MacAddr::ANY,
+            GATEWAY_IP,
+            ArpOperation::Request,
+        );
+        assert_eq!(
+            iface.poll(&arp_frame(request, MacAddr::BROADCAST), &mut tx, 0),
+            InterfaceEvent::Dropped(DropReason::NotForUs)
+        );
+
+        // Reply to our request is learned
+        let gateway_mac = MacAddr::new([0x02, 0, 0, 0, 0, 2]);
+        assert_eq!(
+            iface.write_udp(1234, IpV4Addr::new([1, 1, 1, 1]), 53, &[], &mut tx),
+            Err(SendError::Unresolved(GATEWAY_IP))
+        );
+        let reply = ArpPayload::new(
+            gateway_mac,
+            GATEWAY_IP,
+            OUR_MAC,
+            OUR_IP,
+            ArpOperation::Response,
+        );
+        assert_eq!(
+            iface.poll(&arp_frame(reply, OUR_MAC), &mut tx, 1),
+            InterfaceEvent::None
+        );
+        assert_eq!(iface.resolve(IpV4Addr::new([1, 1, 1, 1])), Ok(gateway_mac));
+
+        // Table is full; oldest entry is replaced
+        let other_ip = IpV4Addr::new([10, 0, 0, 122]);
+        let request = ArpPayload::new(
+            PEER_MAC,
+            other_ip,
+            MacAddr::ANY,
+            OUR_IP,
+            ArpOperation::Request,
+        );
+        iface.poll(&arp_frame(request, MacAddr::BROADCAST), &mut tx, 2);
+        assert_eq!(iface.arp_cache().lookup(PEER_IP), None);
+        assert_eq!(iface.arp_cache().lookup(GATEWAY_IP), Some(gateway_mac));
+        assert_eq!(iface.arp_cache().lookup(other_ip), Some(PEER_MAC));
+    }
+
+    #[test]
+    fn test_proxy_arp() {
+        let mut iface = iface().with_proxy_arp([IpV4Cidr::new(IpV4Addr::new([10, 0, 1, 0]), 24)]);
+        let mut tx = [0_u8; 128];
+        let proxied_ip = IpV4Addr::new([10, 0, 1, 7]);
+
+        // Request for a proxied address is answered with our MAC address
+        let request = ArpPayload::new(
+            PEER_MAC,
+            PEER_IP,
+            MacAddr::ANY,
+            proxied_ip,
+            ArpOperation::Request,
+        );
+        let len = match iface.poll(&arp_frame(request, MacAddr::BROADCAST), &mut tx, 0) {
+            InterfaceEvent::Transmit(len) => len,
+            x => panic!("{x:?}"),
+        };
+        match Packet::classify(&tx[..len], true) {
+            Ok(Packet::Arp(x)) => {
+                assert_eq!(x.data.src_mac, OUR_MAC);
+                assert_eq!(x.data.src_ipaddr, proxied_ip);
+                assert_eq!(x.data.dst_ipaddr, PEER_IP);
+            }
+            x => panic!("{x:?}"),
+        }
+
+        // Our own address is still answered, and others outside the ranges are not
+        let request = ArpPayload::new(
+            PEER_MAC,
+            PEER_IP,
+            MacAddr::ANY,
+            OUR_IP,
+            ArpOperation::Request,
+        );
+        assert!(matches!(
+            iface.poll(&arp_frame(request, MacAddr::BROADCAST), &mut tx, 1),
+            InterfaceEvent::Transmit(_)
+        ));
+        let request = ArpPayload::new(
+            PEER_MAC,
+            PEER_IP,
+            MacAddr::ANY,
+            IpV4Addr::new([10, 0, 2, 7]),
+            ArpOperation::Request,
+        );
+        assert_eq!(
+            iface.poll(&arp_frame(request, MacAddr::BROADCAST), &mut tx, 2),
+            InterfaceEvent::Dropped(DropReason::NotForUs)
+        );
+        assert_eq!(iface.arp_responder().answered(), 1);
+        assert_eq!(iface.arp_responder().proxied(), 1);
+
+        // A new address is answered for after it changes
+        let new_ip = IpV4Addr::new([10, 0, 0, 130]);
+        iface.set_ipaddr(new_ip);
+        let request = ArpPayload::new(
+            PEER_MAC,
+            PEER_IP,
+            MacAddr::ANY,
+            new_ip,
+            ArpOperation::Request,
+        );
+        assert!(matches!(
+            iface.poll(&arp_frame(request, MacAddr::BROADCAST), &mut tx, 3),
+            InterfaceEvent::Transmit(_)
+        ));
+        assert_eq!(iface.arp_responder().answered(), 2);
+    }
+
+    #[test]
+    fn test_arp_mismatch() {
+        let mut iface = iface();
+        let mut tx = [0_u8; 128];
+        let spoof_mac = MacAddr::new([0x02, 0, 0, 0, 0, 9]);
+        assert!(iface.arp_cache_mut().insert_static(GATEWAY_IP, PEER_MAC));
+
+        // Another host claiming to be the gateway is reported and not answered
+        let request = ArpPayload::new(
+            spoof_mac,
+            GATEWAY_IP,
+            MacAddr::ANY,
+            OUR_IP,
+            ArpOperation::Request,
+        );
+        assert_eq!(
+            iface.poll(&arp_frame(request, MacAddr::BROADCAST), &mut tx, 0),
+            InterfaceEvent::ArpMismatch(
+                ArpMismatch {
+                    ipaddr: GATEWAY_IP,
+                    known_macaddr: PEER_MAC,
+                    received_macaddr: spoof_mac,
+                    pinned: true,
+                },
+                None
+            )
+        );
+        assert_eq!(iface.resolve(IpV4Addr::new([1, 1, 1, 1])), Ok(PEER_MAC));
+
+        // A recently learned host that changed its hardware address is reported,
+        // but the new address is learned and the host is answered
+        let new_mac = MacAddr::new([0x02, 0, 0, 0, 0, 8]);
+        iface.arp_cache_mut().insert(PEER_IP, PEER_MAC, 0);
+        let request = ArpPayload::new(
+            new_mac,
+            PEER_IP,
+            MacAddr::ANY,
+            OUR_IP,
+            ArpOperation::Request,
+        );
+        match iface.poll(&arp_frame(request, MacAddr::BROADCAST), &mut tx, 1) {
+            InterfaceEvent::ArpMismatch(x, Some(len)) => {
+                assert_eq!(x.known_macaddr, PEER_MAC);
+                assert_eq!(x.received_macaddr, new_mac);
+                assert!(!x.pinned);
+                match Packet::classify(&tx[..len], true) {
+                    Ok(Packet::Arp(reply)) => {
+                        assert_eq!(reply.header.dst_macaddr, new_mac);
+                        assert_eq!(reply.data.operation, ArpOperation::Response);
+                    }
+                    x => panic!("{x:?}"),
+                }
+            }
+            x => panic!("{x:?}"),
+        }
+        assert_eq!(iface.resolve(PEER_IP), Ok(new_mac));
+    }
+
+    #[test]
+    fn test_udp() {
+        let mut iface = iface();
+        let mut rx = [0_u8; 128];
+        let mut tx = [0_u8; 128];
+        let peer = UdpBuilder::new(PEER_MAC, PEER_IP, 8123, OUR_MAC, OUR_IP, 8125);
+
+        // Addressed to us
+        let len = peer.write_bytes(&[1, 2, 3], &mut rx).unwrap();
+        match iface.poll(&rx[..len], &mut tx, 0) {
+            InterfaceEvent::Udp(udp) => {
+                assert_eq!(udp.ip.src_ipaddr, PEER_IP);
+                assert_eq!(udp.udp.dst_port, 8125);
+                assert_eq!(udp.payload, &[1, 2, 3]);
+            }
+            x => panic!("{x:?}"),
+        }
+
+        // Subnet broadcast
+        let mut broadcast = peer;
+        broadcast.dst_macaddr = MacAddr::BROADCAST;
+        broadcast.dst_ipaddr = IpV4Addr::new([10, 0, 0, 255]);
+        let len = broadcast.write_bytes(&[1, 2, 3], &mut rx).unwrap();
+        assert!(matches!(
+            iface.poll(&rx[..len], &mut tx, 0),
+            InterfaceEvent::Udp(_)
+        ));
+
+        // Another host's MAC or IP address
+        let mut other = peer;
+        other.dst_macaddr = MacAddr::new([0x02, 0, 0, 0, 0, 3]);
+        let len = other.write_bytes(&[1, 2, 3], &mut rx).unwrap();
+        assert_eq!(
+            iface.poll(&rx[..len], &mut tx, 0),
+            InterfaceEvent::Dropped(DropReason::NotForUs)
+        );
+        let mut other = peer;
+        other.dst_ipaddr = IpV4Addr::new([10, 0, 0, 3]);
+        let len = other.write_bytes(&[1, 2, 3], &mut rx).unwrap();
+        assert_eq!(
+            iface.poll(&rx[..len], &mut tx, 0),
+            InterfaceEvent::Dropped(DropReason::NotForUs)
+        );
+
+        // Corrupted
+        let len = peer.write_bytes(&[1, 2, 3], &mut rx).unwrap();
+        rx[len - 1] ^= 0xFF;
+        assert_eq!(
+            iface.poll(&rx[..len], &mut tx, 0),
+            InterfaceEvent::Dropped(DropReason::Malformed(ParseError::BadUdpChecksum))
+        );
+
+        // Sending to a broadcast address needs no resolution
+        let len = iface
+            .write_udp(8125, IpV4Addr::BROADCAST, 8123, &[4, 5], &mut tx)
+            .unwrap();
+        match Packet::classify(&tx[..len], true) {
+            Ok(Packet::Udp(udp)) => {
+                assert_eq!(udp.ethernet.dst_macaddr, MacAddr::BROADCAST);
+                assert_eq!(udp.ip.src_ipaddr, OUR_IP);
+                assert_eq!(udp.payload, &[4, 5]);
+                assert_ne!(udp.udp.checksum, 0);
+            }
+            x => panic!("{x:?}"),
+        }
+
+        // The UDP checksum is left for hardware that fills it in
+        iface.apply_capabilities(&DeviceCapabilities {
+            tx_checksum_offload: true,
+            ..DeviceCapabilities::default()
+        });
+        let len = iface
+            .write_udp(8125, IpV4Addr::BROADCAST, 8123, &[4, 5], &mut tx)
+            .unwrap();
+        match Packet::classify(&tx[..len], true) {
+            Ok(Packet::Udp(udp)) => assert_eq!(udp.udp.checksum, 0),
+            x => panic!("{x:?}"),
+        }
+    }
+
+    #[test]
+    fn test_poll_device() {
+        let mut iface = iface();
+        let mut device: QueueDevice<4, 1514> = QueueDevice::new();
+        let mut rx = [0_u8; 1514];
+        let mut tx = [0_u8; 1514];
+        iface.apply_capabilities(&device.capabilities());
+        assert!(iface.verify_checksums());
+        assert!(iface.fill_checksums());
+
+        assert_eq!(iface.poll_device(&mut device, &mut rx, &mut tx, 0), None);
+
+        // ARP reply goes back out on the device
+        let request = ArpPayload::new(
+            PEER_MAC,
+            PEER_IP,
+            MacAddr::ANY,
+            OUR_IP,
+            ArpOperation::Request,
+        );
+        device
+            .push_rx(&arp_frame(request, MacAddr::BROADCAST))
+            .unwrap();
+        assert_eq!(
+            iface.poll_device(&mut device, &mut rx, &mut tx, 0),
+            Some(InterfaceEvent::None)
+        );
+        let mut sent = [0_u8; 1514];
+        let len = device.pop_tx(&mut sent).unwrap();
+        assert!(matches!(
+            Packet::classify(&sent[..len], true),
+            Ok(Packet::Arp(x)) if x.data.operation == ArpOperation::Response
+        ));
+
+        // UDP data is handed to the application
+        let len = UdpBuilder::new(PEER_MAC, PEER_IP, 8123, OUR_MAC, OUR_IP, 8125)
+            .write_bytes(&[7; 10], &mut sent)
+            .unwrap();
+        device.push_rx(&sent[..len]).unwrap();
+        match iface.poll_device(&mut device, &mut rx, &mut tx, 0) {
+            Some(InterfaceEvent::Udp(udp)) => assert_eq!(udp.payload, &[7; 10]),
+            x => panic!("{x:?}"),
+        }
+    }
+}
@@ -1,9 +1,12 @@
 //! Internet layer: Internet Protocol message header construction
 
-use crate::{IpV4Addr, Protocol, DSCP};
+use crate::{
+    calc_ip_checksum, get_array, get_u16, set_array, set_u16, IpV4Addr, ParseError, Protocol,
+    TryByteStruct, DSCP,
+};
 
 use byte_struct::*;
-use modular_bitfield::prelude::*;
+use core::ops::Range;
 use static_assertions::const_assert;
 use ufmt::derive::uDebug;
 
@@ -43,6 +46,70 @@ impl IpV4Header {
         self.write_bytes(&mut bytes);
         bytes
     }
+
+    /// Check version, header length, and total length against each other and against the
+    /// length of the buffer holding the packet, which may be longer than the packet
+    /// if the ethernet frame was padded to its minimum size.
+    pub fn validate(&self, buffer_len: usize) -> Result<(), ParseError> {
+        self.check_fields()?;
+        self.data_range(buffer_len)?;
+
+        Ok(())
+    }
+
+    /// Check that the header checksum sums to zero.
+    ///
+    /// This only covers the fixed part of the header; if the packet has IP options,
+    /// use [IpV4View::verify_checksum] on the received bytes instead.
+    pub fn verify_checksum(&self) -> Result<(), ParseError> {
+        match calc_ip_checksum(&self.to_be_bytes()) {
+            0 => Ok(()),
+            _ => Err(ParseError::BadIpChecksum),
+        }
+    }
+
+    /// Check version, header length, and total length against each other
+    fn check_fields(&self) -> Result<(), ParseError> {
+        let version = self.version_and_header_length.version();
+        let header_length = self.version_and_header_length.header_length();
+        if version != 4 {
+            return Err(ParseError::BadIpVersion(version));
+        }
+        if (header_length as usize) < Self::BYTE_LEN / 4 {
+            return Err(ParseError::BadHeaderLength(header_length));
+        }
+        if (self.total_length as usize) < 4 * header_length as usize {
+            return Err(ParseError::BadLength(self.total_length));
+        }
+
+        Ok(())
+    }
+
+    /// Range of bytes holding the data that follows the header and any IP options,
+    /// checked against the length of the buffer holding the packet.
+    pub(crate) fn data_range(&self, buffer_len: usize) -> Result<Range<usize>, ParseError> {
+        let header_length = self.version_and_header_length.header_length();
+        let data_start = 4 * header_length as usize;
+        let data_end = self.total_length as usize;
+        if data_start > buffer_len {
+            return Err(ParseError::BadHeaderLength(header_length));
+        }
+        if data_end > buffer_len || data_end < data_start {
+            return Err(ParseError::BadLength(self.total_length));
+        }
+
+        Ok(data_start..data_end)
+    }
+}
+
+impl TryByteStruct for IpV4Header {
+    fn try_read_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        let bytes = bytes.get(..Self::BYTE_LEN).ok_or(ParseError::Truncated)?;
+        let header = Self::read_bytes(bytes);
+        header.check_fields()?;
+
+        Ok(header)
+    }
 }
 
 /// IPV4 frame with header and data.
@@ -82,6 +149,25 @@ where
     }
 }
 
+impl<T> TryByteStruct for IpV4Frame<T>
+where
+    T: TryByteStruct,
+{
+    /// Parse the header, then parse the data starting after any IP options
+    /// and ending at the total length indicated by the header.
+    fn try_read_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        let header = IpV4Header::try_read_bytes(bytes)?;
+        let data_bytes = bytes
+            .get(header.data_range(bytes.len())?)
+            .ok_or(ParseError::Truncated)?;
+
+        Ok(IpV4Frame::<T> {
+            header,
+            data: T::try_read_bytes(data_bytes)?,
+        })
+    }
+}
+
 impl<T> IpV4Frame<T>
 where
     T: ByteStruct,
@@ -94,17 +180,118 @@ where
     }
 }
 
-/// Fragmentation flags and offset info
-#[bitfield(bits = 16)]
+/// IPV4 packet with a typed header and data whose length is only known at run time,
+/// borrowed from the buffer it was parsed from.
+///
+/// This is the counterpart to [IpV4Frame] for receiving packets of any size;
+/// the data starts after any IP options and is bounded by the header's total length,
+/// so any ethernet padding at the end of the buffer is excluded.
+#[derive(Clone, Copy, uDebug, Debug, PartialEq, Eq)]
+pub struct IpV4Packet<'a> {
+    /// IP header
+    pub header: IpV4Header,
+    /// Data such as a UDP datagram
+    pub data: &'a [u8],
+}
+
+impl<'a> IpV4Packet<'a> {
+    /// Parse the header and borrow the data it describes
+    pub fn try_read_bytes(bytes: &'a [u8]) -> Result<Self, ParseError> {
+        let header = IpV4Header::try_read_bytes(bytes)?;
+        let data = bytes
+            .get(header.data_range(bytes.len())?)
+            .ok_or(ParseError::Truncated)?;
+
+        Ok(IpV4Packet { header, data })
+    }
+}
+
+/// Fragmentation flags and offset info, packed into two bytes in network order per IETF-RFC-791:
+/// a reserved bit, the do-not-fragment and more-fragments flags, then a 13-bit offset.
+///
+/// Values too wide for a field are truncated to the field's width.
 #[derive(Clone, Copy, uDebug, Debug, Default, PartialEq, Eq)]
-pub struct Fragmentation {
-    unused: B1,
+pub struct Fragmentation(u16);
+
+impl Fragmentation {
+    const DO_NOT_FRAGMENT: u16 = 1 << 14;
+    const MORE_FRAGMENTS: u16 = 1 << 13;
+    const OFFSET: u16 = 0x1FFF;
+
+    /// No flags set and zero offset
+    pub const fn new() -> Self {
+        Fragmentation(0)
+    }
+
+    /// Unpack from bytes
+    pub const fn from_bytes(bytes: [u8; 2]) -> Self {
+        Fragmentation(u16::from_be_bytes(bytes))
+    }
+
+    /// Pack into bytes
+    pub const fn into_bytes(self) -> [u8; 2] {
+        self.0.to_be_bytes()
+    }
+
     /// Flag for routers to drop packets instead of fragmenting
-    pub do_not_fragment: B1,
+    pub const fn do_not_fragment(&self) -> u8 {
+        (self.0 & Self::DO_NOT_FRAGMENT != 0) as u8
+    }
+
     /// Flag that there are more fragments coming
-    pub more_fragments: B1,
+    pub const fn more_fragments(&self) -> u8 {
+        (self.0 & Self::MORE_FRAGMENTS != 0) as u8
+    }
+
     /// Where we are in a set of fragments
-    pub offset: B13,
+    pub const fn offset(&self) -> u16 {
+        self.0 & Self::OFFSET
+    }
+
+    /// Whether this is one piece of a fragmented datagram rather than a whole one
+    pub const fn is_fragment(&self) -> bool {
+        self.more_fragments() != 0 || self.offset() != 0
+    }
+
+    /// Set flag for routers to drop packets instead of fragmenting
+    pub fn set_do_not_fragment(&mut self, value: u8) {
+        self.set_flag(Self::DO_NOT_FRAGMENT, value);
+    }
+
+    /// Set flag that there are more fragments coming
+    pub fn set_more_fragments(&mut self, value: u8) {
+        self.set_flag(Self::MORE_FRAGMENTS, value);
+    }
+
+    /// Set where we are in a set of fragments
+    pub fn set_offset(&mut self, value: u16) {
+        self.0 = (self.0 & !Self::OFFSET) | (value & Self::OFFSET);
+    }
+
+    /// With flag for routers to drop packets instead of fragmenting
+    pub fn with_do_not_fragment(mut self, value: u8) -> Self {
+        self.set_do_not_fragment(value);
+        self
+    }
+
+    /// With flag that there are more fragments coming
+    pub fn with_more_fragments(mut self, value: u8) -> Self {
+        self.set_more_fragments(value);
+        self
+    }
+
+    /// With where we are in a set of fragments
+    pub fn with_offset(mut self, value: u16) -> Self {
+        self.set_offset(value);
+        self
+    }
+
+    fn set_flag(&mut self, flag: u16, value: u8) {
+        match value & 1 {
+            0 => self.0 &= !flag,
+            _ => self.0 |= flag,
+        }
+    }
 }
 
 impl ByteStructLen for Fragmentation {
@@ -126,14 +313,61 @@ impl ByteStruct for Fragmentation {
     }
 }
 
+impl TryByteStruct for Fragmentation {}
+
 /// Combined IP version and header length in a single byte.
-#[bitfield(bits = 8)]
+///
+/// Values too wide for a field are truncated to the field's width.
 #[derive(Clone, Copy, uDebug, Debug, Default, PartialEq, Eq)]
-pub struct VersionAndHeaderLength {
+pub struct VersionAndHeaderLength(u8);
+
+impl VersionAndHeaderLength {
+    /// Zero version and header length
+    pub const fn new() -> Self {
+        VersionAndHeaderLength(0)
+    }
+
+    /// Unpack from bytes
+    pub const fn from_bytes(bytes: [u8; 1]) -> Self {
+        VersionAndHeaderLength(bytes[0])
+    }
+
+    /// Pack into bytes
+    pub const fn into_bytes(self) -> [u8; 1] {
+        [self.0]
+    }
+
     /// Length of IP header in 32-bit words (usually 5 words, or 20 bytes)
-    pub header_length: B4,
+    pub const fn header_length(&self) -> u8 {
+        self.0 & 0x0F
+    }
+
     /// IP version number
-    pub version: B4,
+    pub const fn version(&self) -> u8 {
+        self.0 >> 4
+    }
+
+    /// Set length of IP header in 32-bit words
+    pub fn set_header_length(&mut self, value: u8) {
+        self.0 = (self.0 & 0xF0) | (value & 0x0F);
+    }
+
+    /// Set IP version number
+    pub fn set_version(&mut self, value: u8) {
+        self.0 = (self.0 & 0x0F) | (value << 4);
+    }
+
+    /// With length of IP header in 32-bit words
+    pub fn with_header_length(mut self, value: u8) -> Self {
+        self.set_header_length(value);
+        self
+    }
+
+    /// With IP version number
+    pub fn with_version(mut self, value: u8) -> Self {
+        self.set_version(value);
+        self
+    }
 }
 
 impl ByteStructLen for VersionAndHeaderLength {
@@ -150,3 +384,197 @@ impl ByteStruct for VersionAndHeaderLength {
         bytes[0] = self.into_bytes()[0];
     }
 }
+
+impl TryByteStruct for VersionAndHeaderLength {}
+
+/// Borrowed view of an IPV4 packet in a buffer, with typed access to header fields in place.
+///
+/// The header length, total length, and version are checked when the view is constructed with
+/// [IpV4View::new], so the payload is always the data after any IP options and before any ethernet
+/// padding. To assemble a packet in a blank transmit buffer, use [IpV4View::new_unchecked] and write
+/// the header first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IpV4View<T: AsRef<[u8]>> {
+    buffer: T,
+}
+
+impl<T: AsRef<[u8]>> IpV4View<T> {
+    /// Wrap a buffer, checking that the header is valid and that the lengths it reports fit in the buffer
+    pub fn new(buffer: T) -> Result<Self, ParseError> {
+        let bytes = buffer.as_ref();
+        IpV4Header::try_read_bytes(bytes)?.data_range(bytes.len())?;
+
+        Ok(IpV4View { buffer })
+    }
+
+    /// Wrap a buffer without checking the header, such as a transmit buffer whose header is
+    /// yet to be written. Accessors stay within the buffer regardless, and the payload is empty
+    /// until the header and total length describe it.
+    pub fn new_unchecked(buffer: T) -> Self {
+        IpV4View { buffer }
+    }
+
+    /// Release the underlying buffer
+    pub fn into_inner(self) -> T {
+        self.buffer
+    }
+
+    /// Combined version and header length
+    pub fn version_and_header_length(&self) -> VersionAndHeaderLength {
+        VersionAndHeaderLength::from_bytes(get_array(self.buffer.as_ref(), 0))
+    }
+
+    /// Length of header including options, in bytes
+    pub fn header_len(&self) -> usize {
+        4 * self.version_and_header_length().header_length() as usize
+    }
+
+    /// Type of Service / Differentiated-Service
+    pub fn dscp(&self) -> DSCP {
+        DSCP::read_bytes(&get_array::<1>(self.buffer.as_ref(), 1))
+    }
+
+    /// Total length including header and data
+    pub fn total_length(&self) -> u16 {
+        get_u16(self.buffer.as_ref(), 2)
+    }
+
+    /// Mostly-legacy id field
+    pub fn identification(&self) -> u16 {
+        get_u16(self.buffer.as_ref(), 4)
+    }
+
+    /// Mostly-legacy packet fragmentation info
+    pub fn fragmentation(&self) -> Fragmentation {
+        Fragmentation::from_bytes(get_array(self.buffer.as_ref(), 6))
+    }
+
+    /// TTL counter
+    pub fn time_to_live(&self) -> u8 {
+        self.buffer.as_ref().get(8).copied().unwrap_or(0)
+    }
+
+    /// Transport-layer protocol
+    pub fn protocol(&self) -> Protocol {
+        Protocol::read_bytes(&get_array::<1>(self.buffer.as_ref(), 9))
+    }
+
+    /// Header checksum
+    pub fn checksum(&self) -> u16 {
+        get_u16(self.buffer.as_ref(), 10)
+    }
+
+    /// Source IP address
+    pub fn src_ipaddr(&self) -> IpV4Addr {
+        IpV4Addr::new(get_array(self.buffer.as_ref(), 12))
+    }
+
+    /// Destination IP address
+    pub fn dst_ipaddr(&self) -> IpV4Addr {
+        IpV4Addr::new(get_array(self.buffer.as_ref(), 16))
+    }
+
+    /// Copy out the fixed part of the header
+    pub fn header(&self) -> IpV4Header {
+        IpV4Header::read_bytes(&get_array::<{ IpV4Header::BYTE_LEN }>(
+            self.buffer.as_ref(),
+            0,
+        ))
+    }
+
+    /// IP options between the fixed part of the header and the data, if any
+    pub fn options(&self) -> &[u8] {
+        self.buffer
+            .as_ref()
+            .get(IpV4Header::BYTE_LEN..self.header_len())
+            .unwrap_or(&[])
+    }
+
+    /// Check that the header checksum, including any options, sums to zero
+    pub fn verify_checksum(&self) -> Result<(), ParseError> {
+        let header_bytes = self.buffer.as_ref().get(..self.header_len()).unwrap_or(&[]);
+        match calc_ip_checksum(header_bytes) {
+            0 => Ok(()),
+            _ => Err(ParseError::BadIpChecksum),
+        }
+    }
+
+    /// Data after the header, up to the total length
+    pub fn payload(&self) -> &[u8] {
+        self.buffer
+            .as_ref()
+            .get(self.header_len()..self.total_length() as usize)
+            .unwrap_or(&[])
+    }
+}
+
+impl<T: AsRef<[u8]> + AsMut<[u8]>> IpV4View<T> {
+    /// Set combined version and header length
+    pub fn set_version_and_header_length(&mut self, value: VersionAndHeaderLength) {
+        set_array(self.buffer.as_mut(), 0, &value.into_bytes());
+    }
+
+    /// Set Type of Service / Differentiated-Service
+    pub fn set_dscp(&mut self, value: DSCP) {
+        set_array(self.buffer.as_mut(), 1, &[value as u8]);
+    }
+
+    /// Set total length including header and data, which moves the end of the payload
+    pub fn set_total_length(&mut self, value: u16) {
+        set_u16(self.buffer.as_mut(), 2, value);
+    }
+
+    /// Set mostly-legacy id field
+    pub fn set_identification(&mut self, value: u16) {
+        set_u16(self.buffer.as_mut(), 4, value);
+    }
+
+    /// Set mostly-legacy packet fragmentation info
+    pub fn set_fragmentation(&mut self, value: Fragmentation) {
+        set_array(self.buffer.as_mut(), 6, &value.into_bytes());
+    }
+
+    /// Set TTL counter
+    pub fn set_time_to_live(&mut self, value: u8) {
+        set_array(self.buffer.as_mut(), 8, &[value]);
+    }
+
+    /// Set transport-layer protocol
+    pub fn set_protocol(&mut self, value: Protocol) {
+        set_array(self.buffer.as_mut(), 9, &[value as u8]);
+    }
+
+    /// Set header checksum
+    pub fn set_checksum(&mut self, value: u16) {
+        set_u16(self.buffer.as_mut(), 10, value);
+    }
+
+    /// Set source IP address
+    pub fn set_src_ipaddr(&mut self, value: IpV4Addr) {
+        set_array(self.buffer.as_mut(), 12, &value.0);
+    }
+
+    /// Set destination IP address
+    pub fn set_dst_ipaddr(&mut self, value: IpV4Addr) {
+        set_array(self.buffer.as_mut(), 16, &value.0);
+    }
+
+    /// Overwrite the fixed part of the header
+    pub fn set_header(&mut self, value: &IpV4Header) {
+        set_array(self.buffer.as_mut(), 0, &value.to_be_bytes());
+    }
+
+    /// Recalculate the header checksum after modifying header fields
+    pub fn fill_checksum(&mut self) {
+        self.set_checksum(0);
+        let header_len = self.header_len();
+        let checksum = calc_ip_checksum(self.buffer.as_ref().get(..header_len).unwrap_or(&[]));
+        self.set_checksum(checksum);
+    }
+
+    /// Mutable access to the data after the header, up to the total length
+    pub fn payload_mut(&mut self) -> &mut [u8] {
+        let range = self.header_len()..self.total_length() as usize;
+        self.buffer.as_mut().get_mut(range).unwrap_or(&mut [])
+    }
+}
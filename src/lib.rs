@@ -11,6 +11,8 @@
 //! be implemented and I'm sure some bugs are yet to be found.
 //!
 //! ```rust
+//! # #![allow(incomplete_features)]
+//! # #![feature(generic_const_exprs)]
 //! use catnip::*;
 //!
 //! // Some made-up data with two 32-bit words' worth of bytes and some arbitrary addresses
@@ -59,10 +61,19 @@
 //! // Parse from bytes
 //! let frame_parsed = EthernetFrame::<IpV4Frame<UdpFrame<ByteArray<8>>>>::read_bytes(&bytes);
 //! assert_eq!(frame_parsed, frame);
+//!
+//! // Parse from bytes that may be truncated or corrupted, as when receiving from the network
+//! let frame_parsed = EthernetFrame::<IpV4Frame<UdpFrame<ByteArray<8>>>>::try_read_bytes(&bytes);
+//! assert_eq!(frame_parsed, Ok(frame));
+//! assert_eq!(
+//!     EthernetFrame::<IpV4Frame<UdpFrame<ByteArray<8>>>>::try_read_bytes(&bytes[..20]),
+//!     Err(ParseError::Truncated)
+//! );
 //! ```
 
 #![no_std]
 #![allow(dead_code)]
+#![allow(incomplete_features)]
 #![deny(missing_docs)]
 #![feature(generic_const_exprs)]
 
@@ -80,10 +91,35 @@ pub mod udp; // Transport layer
 pub mod arp; // Address Resolution Protocol - not a distinct layer (between link and transport), but required for IP and UDP to function on most networks.
 pub mod dhcp; // Dynamic Host Configuration Protocol - for negotiating an IP address from a router/switch. Uses UDP.
 
+pub mod acd; // IPV4 address conflict detection
+pub mod arp_cache; // Table of neighbors' hardware addresses learned from ARP
+pub mod arp_scan; // Discovery of hosts on a subnet by ARP
+pub mod asynch; // Executor-agnostic async send and receive
+pub mod device; // Ethernet MAC driver abstraction, with loopback and in-memory devices
+pub mod dhcp_client; // DHCP client state machine for acquiring and renewing a lease
+pub mod iface; // Network interface holding our addresses and ARP cache
+pub mod link_local; // IPV4 link-local address autoconfiguration
+pub mod packet; // Classification of received frames by ethertype, protocol, and port
+pub mod pending; // Outgoing frames waiting for ARP resolution
+pub mod rarp; // Reverse ARP, for hosts that learn their IP address from a table keyed by MAC address
+pub mod socket; // UDP pseudo-sockets
+
+pub use acd::*;
 pub use arp::*;
+pub use arp_cache::*;
+pub use arp_scan::*;
+pub use asynch::*;
+pub use device::*;
 pub use dhcp::*;
+pub use dhcp_client::*;
 pub use enet::*;
+pub use iface::*;
 pub use ip::*;
+pub use link_local::*;
+pub use packet::*;
+pub use pending::*;
+pub use rarp::*;
+pub use socket::*;
 pub use udp::*;
 
 /// Standard 6-byte MAC address.
@@ -123,6 +159,37 @@ impl IpV4Addr {
     pub const ANY: IpV4Addr = ByteArray([0x0_u8; 4]);
 }
 
+/// Block of IPV4 addresses sharing the first `prefix_len` bits, like 10.0.0.0/24
+#[derive(Clone, Copy, uDebug, Debug, PartialEq, Eq)]
+pub struct IpV4Cidr {
+    /// Any address in the block
+    pub network: IpV4Addr,
+    /// Number of leading bits shared by every address in the block, up to 32
+    pub prefix_len: u8,
+}
+
+impl IpV4Cidr {
+    /// New from an address in the block and a prefix length
+    pub fn new(network: IpV4Addr, prefix_len: u8) -> Self {
+        IpV4Cidr {
+            network,
+            prefix_len,
+        }
+    }
+
+    /// Block of just one address
+    pub fn single(ipaddr: IpV4Addr) -> Self {
+        Self::new(ipaddr, 32)
+    }
+
+    /// Whether an address is in the block
+    pub fn contains(&self, ipaddr: IpV4Addr) -> bool {
+        let shift = 32 - self.prefix_len.min(32) as u32;
+        let mask = u32::MAX.checked_shl(shift).unwrap_or(0);
+        (u32::from_be_bytes(self.network.0) ^ u32::from_be_bytes(ipaddr.0)) & mask == 0
+    }
+}
+
 /// Common choices of transport-layer protocols and their IP header values.
 /// There are many more protocols not listed here.
 /// See <https://en.wikipedia.org/wiki/List_of_IP_protocol_numbers>.
@@ -143,11 +210,11 @@ impl ByteStructLen for Protocol {
 
 impl ByteStruct for Protocol {
     fn read_bytes(bytes: &[u8]) -> Self {
-        return match bytes[0] {
+        match bytes[0] {
             x if x == (Protocol::Tcp as u8) => Protocol::Tcp,
             x if x == (Protocol::Udp as u8) => Protocol::Udp,
             _ => Protocol::Unimplemented,
-        };
+        }
     }
 
     fn write_bytes(&self, bytes: &mut [u8]) {
@@ -156,8 +223,8 @@ impl ByteStruct for Protocol {
 }
 
 impl Protocol {
-    fn to_be_bytes(&self) -> [u8; Self::BYTE_LEN] {
-        (*self as u8).to_be_bytes()
+    fn to_be_bytes(self) -> [u8; Self::BYTE_LEN] {
+        (self as u8).to_be_bytes()
     }
 }
 
@@ -180,11 +247,11 @@ impl ByteStructLen for DSCP {
 
 impl ByteStruct for DSCP {
     fn read_bytes(bytes: &[u8]) -> Self {
-        return match bytes[0] {
+        match bytes[0] {
             x if x == (DSCP::Standard as u8) => DSCP::Standard,
             x if x == (DSCP::Realtime as u8) => DSCP::Realtime,
             _ => DSCP::Unimplemented,
-        };
+        }
     }
 
     fn write_bytes(&self, bytes: &mut [u8]) {
@@ -193,8 +260,8 @@ impl ByteStruct for DSCP {
 }
 
 impl DSCP {
-    fn to_be_bytes(&self) -> [u8; Self::BYTE_LEN] {
-        (*self as u8).to_be_bytes()
+    fn to_be_bytes(self) -> [u8; Self::BYTE_LEN] {
+        (self as u8).to_be_bytes()
     }
 }
 
@@ -215,9 +282,7 @@ impl<const N: usize> ByteStruct for ByteArray<N> {
     }
 
     fn write_bytes(&self, bytes: &mut [u8]) {
-        for i in 0..N {
-            bytes[i] = self.0[i];
-        }
+        bytes[..N].copy_from_slice(&self.0);
     }
 }
 
@@ -293,6 +358,148 @@ macro_rules! enum_with_unknown {
     }
 }
 
+/// Errors produced while parsing untrusted bytes, such as a frame received from the network.
+#[derive(Clone, Copy, uDebug, Debug, PartialEq, Eq)]
+pub enum ParseError {
+    /// Input is shorter than the structure being parsed
+    Truncated,
+    /// IP version field is not 4
+    BadIpVersion(u8),
+    /// IP header length (IHL) in 32-bit words is less than the minimum of 5 or runs past the end of the input
+    BadHeaderLength(u8),
+    /// A length field is smaller than its own header or larger than the input
+    BadLength(u16),
+    /// EtherType (or ARP protocol type) is not one of the values handled here
+    UnknownEtherType(u16),
+    /// ARP address lengths do not match the hardware or protocol type
+    BadArpFormat,
+    /// ARP hardware or protocol type is valid, but not the ethernet and IPV4 handled here
+    UnsupportedArp {
+        /// Hardware type
+        htype: u16,
+        /// Protocol type
+        ptype: u16,
+        /// Hardware address length
+        hlen: u8,
+        /// Protocol address length
+        plen: u8,
+    },
+    /// DHCP "magic cookie" does not match; this is probably not a DHCP message
+    BadDhcpCookie(u32),
+    /// DHCP option with this code is cut off, its length runs past the end of the options section,
+    /// or its length is wrong for its contents
+    BadDhcpOption(u8),
+    /// DHCP option with this code is required in this message but is not present
+    MissingDhcpOption(u8),
+    /// IP header checksum does not match the header's contents
+    BadIpChecksum,
+    /// UDP checksum is present and does not match the datagram's contents
+    BadUdpChecksum,
+}
+
+/// Errors produced while serializing into a caller-provided buffer.
+#[derive(Clone, Copy, uDebug, Debug, PartialEq, Eq)]
+pub enum BuildError {
+    /// Buffer is too short to hold the output
+    BufferTooSmall,
+    /// Data is too long to be described by the protocol's length fields
+    PayloadTooLong,
+}
+
+/// Fallible counterpart to [ByteStruct::read_bytes] for parsing input that may be short, truncated, or corrupted.
+///
+/// The default implementation only checks that the input is long enough;
+/// types with fields that constrain each other override it to check those as well.
+pub trait TryByteStruct: ByteStruct + Sized {
+    /// Parse from big-endian (network) bytes without panicking on bad input
+    fn try_read_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        match bytes.get(..Self::BYTE_LEN) {
+            Some(x) => Ok(Self::read_bytes(x)),
+            None => Err(ParseError::Truncated),
+        }
+    }
+}
+
+impl TryByteStruct for Protocol {}
+
+impl TryByteStruct for DSCP {}
+
+impl<const N: usize> TryByteStruct for ByteArray<N> {}
+
+// Accessors for the borrowed packet views.
+// Each view checks the buffer length when it is constructed, so the out-of-bounds
+// fallbacks here are never taken; they exist so that field access has no panic branches.
+
+/// Read a big-endian u16 at some offset, or 0 if out of bounds
+pub(crate) fn get_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes(get_array(bytes, offset))
+}
+
+/// Read a big-endian u32 at some offset, or 0 if out of bounds
+pub(crate) fn get_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes(get_array(bytes, offset))
+}
+
+/// Copy a fixed number of bytes from some offset, or zeroes if out of bounds
+pub(crate) fn get_array<const N: usize>(bytes: &[u8], offset: usize) -> [u8; N] {
+    let mut out = [0_u8; N];
+    let end = offset.checked_add(N);
+    if let Some(x) = end.and_then(|end| bytes.get(offset..end)) {
+        out.copy_from_slice(x);
+    }
+    out
+}
+
+/// Write a big-endian u16 at some offset, or do nothing if out of bounds
+pub(crate) fn set_u16(bytes: &mut [u8], offset: usize, value: u16) {
+    set_array(bytes, offset, &value.to_be_bytes());
+}
+
+/// Write a big-endian u32 at some offset, or do nothing if out of bounds
+pub(crate) fn set_u32(bytes: &mut [u8], offset: usize, value: u32) {
+    set_array(bytes, offset, &value.to_be_bytes());
+}
+
+/// Copy bytes to some offset, or do nothing if out of bounds
+pub(crate) fn set_array(bytes: &mut [u8], offset: usize, value: &[u8]) {
+    let end = offset.checked_add(value.len());
+    if let Some(x) = end.and_then(|end| bytes.get_mut(offset..end)) {
+        x.copy_from_slice(value);
+    }
+}
+
+/// Small xorshift pseudorandom number generator for protocol timing jitter and address selection.
+/// Not suitable for anything security-related; the application provides the seed,
+/// which should differ between hosts (for example, derived from the MAC address or a hardware RNG).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct XorShift32(u32);
+
+impl XorShift32 {
+    /// New generator; a zero seed is replaced, since it would produce only zeroes
+    pub(crate) const fn new(seed: u32) -> Self {
+        match seed {
+            0 => XorShift32(0x9E37_79B9),
+            x => XorShift32(x),
+        }
+    }
+
+    /// Next value in the sequence
+    pub(crate) fn next_u32(&mut self) -> u32 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.0 = x;
+        x
+    }
+
+    /// Value in `[lo, hi]`
+    pub(crate) fn range(&mut self, lo: u64, hi: u64) -> u64 {
+        let span = hi.saturating_sub(lo).saturating_add(1);
+        lo.saturating_add(self.next_u32() as u64 % span)
+    }
+}
+
 /// Calculate IP checksum per IETF-RFC-768
 /// following implementation guide in IETF-RFC-1071 section 4.1 .
 /// See <https://datatracker.ietf.org/doc/html/rfc1071#section-4> .
@@ -301,9 +508,7 @@ pub fn calc_ip_checksum(data: &[u8]) -> u16 {
     // Partial calc
     let sum = calc_ip_checksum_incomplete(data);
     // Fold and flip
-    let checksum = calc_ip_checksum_finalize(sum);
-
-    checksum
+    calc_ip_checksum_finalize(sum)
 }
 
 /// Finalize an IP checksum by folding the accumulator from an [i32]
@@ -322,9 +527,7 @@ pub fn calc_ip_checksum_finalize(sum: u32) -> u16 {
     sum = (sum & 0xffff).wrapping_add(sum >> 16);
 
     // Convert to u16 and take bitwise complement
-    let checksum = !(sum as u16);
-
-    checksum
+    !(sum as u16)
 }
 
 /// Calculate an IP checksum on incomplete data
@@ -335,16 +538,13 @@ pub fn calc_ip_checksum_finalize(sum: u32) -> u16 {
 /// but this method avoids generating panic branches in slice operations.
 pub fn calc_ip_checksum_incomplete(data: &[u8]) -> u32 {
     let mut sum: u32 = 0;
-    let mut i: usize = 0;
 
-    for x in data {
+    for (i, x) in data.iter().enumerate() {
         if i % 2 == 0 {
             sum += (*x as u32) << 8;
         } else {
             sum += *x as u32;
         };
-
-        i += 1;
     }
 
     sum
@@ -358,7 +558,7 @@ mod test {
     use std::*;
 
     #[test]
-    fn test_calc_ip_checksum() -> () {
+    fn test_calc_ip_checksum() {
         let src_ipaddr: IpV4Addr = IpV4Addr::new([10, 0, 0, 1]);
         let dst_ipaddr: IpV4Addr = IpV4Addr::new([10, 0, 0, 2]);
         let mut sample_ipv4_header = IpV4Header {
@@ -372,8 +572,8 @@ mod test {
             time_to_live: 10,
             protocol: Protocol::Udp,
             checksum: 0,
-            src_ipaddr: src_ipaddr,
-            dst_ipaddr: dst_ipaddr,
+            src_ipaddr,
+            dst_ipaddr,
         };
         let checksum_pre = calc_ip_checksum(&sample_ipv4_header.to_be_bytes());
         sample_ipv4_header.checksum = checksum_pre;
@@ -381,4 +581,271 @@ mod test {
 
         assert!(checksum_post == 0)
     }
+
+    #[test]
+    fn test_byte_helpers() {
+        let mut bytes = [0_u8; 8];
+        set_u32(&mut bytes, 2, 0x0102_0304);
+        assert_eq!(get_u32(&bytes, 2), 0x0102_0304);
+        assert_eq!(get_u16(&bytes, 4), 0x0304);
+
+        // Partly or entirely out of bounds reads give zeroes and writes do nothing
+        set_u32(&mut bytes, 6, 0xFFFF_FFFF);
+        assert_eq!(bytes, [0, 0, 1, 2, 3, 4, 0, 0]);
+        assert_eq!(get_u32(&bytes, 6), 0);
+
+        // Offsets near the end of the address space are out of bounds rather than wrapping
+        set_array(&mut bytes, usize::MAX - 1, &[0xFF; 4]);
+        set_u16(&mut bytes, usize::MAX, 0xFFFF);
+        assert_eq!(bytes, [0, 0, 1, 2, 3, 4, 0, 0]);
+        assert_eq!(get_array::<4>(&bytes, usize::MAX - 1), [0; 4]);
+        assert_eq!(get_u16(&bytes, usize::MAX), 0);
+    }
+
+    fn sample_frame() -> EthernetFrame<IpV4Frame<UdpFrame<ByteArray<8>>>> {
+        let mut frame = EthernetFrame::<IpV4Frame<UdpFrame<ByteArray<8>>>> {
+            header: EthernetHeader {
+                dst_macaddr: MacAddr::BROADCAST,
+                src_macaddr: MacAddr::new([0x02, 0xAF, 0xFF, 0x1A, 0xE5, 0x3C]),
+                ethertype: EtherType::IpV4,
+            },
+            data: IpV4Frame::<UdpFrame<ByteArray<8>>> {
+                header: IpV4Header {
+                    version_and_header_length: VersionAndHeaderLength::new()
+                        .with_version(4)
+                        .with_header_length((IpV4Header::BYTE_LEN / 4) as u8),
+                    dscp: DSCP::Standard,
+                    total_length: IpV4Frame::<UdpFrame<ByteArray<8>>>::BYTE_LEN as u16,
+                    identification: 0,
+                    fragmentation: Fragmentation::default(),
+                    time_to_live: 10,
+                    protocol: Protocol::Udp,
+                    checksum: 0,
+                    src_ipaddr: IpV4Addr::new([10, 0, 0, 1]),
+                    dst_ipaddr: IpV4Addr::new([10, 0, 0, 2]),
+                },
+                data: UdpFrame::<ByteArray<8>> {
+                    header: UdpHeader {
+                        src_port: 8123,
+                        dst_port: 8125,
+                        length: UdpFrame::<ByteArray<8>>::BYTE_LEN as u16,
+                        checksum: 0,
+                    },
+                    data: ByteArray([0, 1, 2, 3, 4, 5, 6, 7]),
+                },
+            },
+            checksum: 0,
+        };
+        frame.data.header.checksum = calc_ip_checksum(&frame.data.header.to_be_bytes());
+
+        frame
+    }
+
+    #[test]
+    fn test_try_read_bytes() {
+        type Frame = EthernetFrame<IpV4Frame<UdpFrame<ByteArray<8>>>>;
+        let frame = sample_frame();
+        let bytes = frame.to_be_bytes();
+
+        // Round trip
+        assert_eq!(Frame::try_read_bytes(&bytes), Ok(frame));
+
+        // Every truncation is an error instead of a panic
+        for i in 0..bytes.len() {
+            assert!(Frame::try_read_bytes(&bytes[..i]).is_err());
+        }
+
+        // Unknown ethertype
+        let mut bad = bytes;
+        bad[12..14].copy_from_slice(&0x1234_u16.to_be_bytes());
+        assert_eq!(
+            Frame::try_read_bytes(&bad),
+            Err(ParseError::UnknownEtherType(0x1234))
+        );
+
+        // Bad IP version
+        let mut bad = bytes;
+        bad[14] = (6 << 4) | 5;
+        assert_eq!(
+            Frame::try_read_bytes(&bad),
+            Err(ParseError::BadIpVersion(6))
+        );
+
+        // IHL below minimum
+        let mut bad = bytes;
+        bad[14] = (4 << 4) | 4;
+        assert_eq!(
+            Frame::try_read_bytes(&bad),
+            Err(ParseError::BadHeaderLength(4))
+        );
+
+        // IP total length longer than the frame
+        let mut bad = bytes;
+        bad[16..18].copy_from_slice(&1000_u16.to_be_bytes());
+        assert_eq!(
+            Frame::try_read_bytes(&bad),
+            Err(ParseError::BadLength(1000))
+        );
+
+        // UDP length shorter than its own header
+        let mut bad = bytes;
+        bad[38..40].copy_from_slice(&4_u16.to_be_bytes());
+        assert_eq!(Frame::try_read_bytes(&bad), Err(ParseError::BadLength(4)));
+    }
+
+    #[test]
+    fn test_views() {
+        let frame = sample_frame();
+        let mut bytes = frame.to_be_bytes();
+
+        // Read in place
+        let enet = EthernetView::new(&bytes[..]).unwrap();
+        assert_eq!(enet.header(), frame.header);
+        let ip = IpV4View::new(enet.payload()).unwrap();
+        assert_eq!(ip.header(), frame.data.header);
+        assert_eq!(calc_ip_checksum(&ip.header().to_be_bytes()), 0);
+        let udp = UdpView::new(ip.payload()).unwrap();
+        assert_eq!(udp.header(), frame.data.data.header);
+        assert_eq!(&udp.payload()[..8], &frame.data.data.data.0);
+
+        // Modify in place
+        let mut enet = EthernetView::new(&mut bytes[..]).unwrap();
+        enet.set_dst_macaddr(MacAddr::new([1, 2, 3, 4, 5, 6]));
+        let mut ip = IpV4View::new(enet.payload_mut()).unwrap();
+        ip.set_dst_ipaddr(IpV4Addr::new([10, 0, 0, 3]));
+        ip.fill_checksum();
+        let mut udp = UdpView::new(ip.payload_mut()).unwrap();
+        udp.set_dst_port(9000);
+        udp.payload_mut()[0] = 0xAA;
+
+        let parsed = EthernetFrame::<IpV4Frame<UdpFrame<ByteArray<8>>>>::read_bytes(&bytes);
+        assert_eq!(parsed.header.dst_macaddr, MacAddr::new([1, 2, 3, 4, 5, 6]));
+        assert_eq!(parsed.data.header.dst_ipaddr, IpV4Addr::new([10, 0, 0, 3]));
+        assert_eq!(calc_ip_checksum(&parsed.data.header.to_be_bytes()), 0);
+        assert_eq!(parsed.data.data.header.dst_port, 9000);
+        assert_eq!(parsed.data.data.data.0[0], 0xAA);
+
+        // Short or inconsistent buffers are rejected at construction
+        assert_eq!(EthernetView::new(&bytes[..10]), Err(ParseError::Truncated));
+        assert_eq!(
+            IpV4View::new(&bytes[14..40]),
+            Err(ParseError::BadLength(frame.data.header.total_length))
+        );
+        assert_eq!(UdpView::new(&bytes[34..38]), Err(ParseError::Truncated));
+
+        // Assemble a frame in a blank buffer
+        let mut tx = [0_u8; 64];
+        let mut enet = EthernetView::new(&mut tx[..]).unwrap();
+        enet.set_header(&frame.header);
+        let mut ip = IpV4View::new_unchecked(enet.payload_mut());
+        assert!(ip.payload().is_empty());
+        ip.set_header(&frame.data.header);
+        let mut udp = UdpView::new_unchecked(ip.payload_mut());
+        udp.set_header(&frame.data.data.header);
+        udp.payload_mut().copy_from_slice(&frame.data.data.data.0);
+        let len = EthernetHeader::BYTE_LEN + frame.data.header.total_length as usize;
+        assert_eq!(&tx[..len], &frame.to_be_bytes()[..len]);
+
+        // Shrink the datagram in place to 3 bytes of data
+        let mut ip = IpV4View::new(&mut tx[EthernetHeader::BYTE_LEN..]).unwrap();
+        UdpView::new(ip.payload_mut()).unwrap().set_length(8 + 3);
+        ip.set_total_length(20 + 8 + 3);
+        ip.fill_checksum();
+        let ip = IpV4View::new(&tx[EthernetHeader::BYTE_LEN..]).unwrap();
+        assert_eq!(ip.verify_checksum(), Ok(()));
+        assert_eq!(UdpView::new(ip.payload()).unwrap().payload(), &[0, 1, 2]);
+
+        // Version and header length
+        let mut ip = IpV4View::new_unchecked(&mut tx[EthernetHeader::BYTE_LEN..]);
+        ip.set_version_and_header_length(VersionAndHeaderLength::new().with_version(6));
+        assert_eq!(
+            IpV4View::new(&tx[EthernetHeader::BYTE_LEN..]),
+            Err(ParseError::BadIpVersion(6))
+        );
+    }
+
+    #[test]
+    fn test_runtime_length() {
+        let frame = sample_frame();
+        let mut bytes = frame.to_be_bytes();
+
+        // Shrink the datagram to 3 bytes of data; the rest of the buffer is now padding
+        bytes[16..18].copy_from_slice(&(20_u16 + 8 + 3).to_be_bytes());
+        bytes[38..40].copy_from_slice(&(8_u16 + 3).to_be_bytes());
+
+        let packet = IpV4Packet::try_read_bytes(&bytes[EthernetHeader::BYTE_LEN..]).unwrap();
+        assert_eq!(packet.header.protocol, Protocol::Udp);
+        assert_eq!(packet.data.len(), 8 + 3);
+        let datagram = UdpDatagram::try_read_bytes(packet.data).unwrap();
+        assert_eq!(datagram.header.dst_port, 8125);
+        assert_eq!(datagram.data, &[0, 1, 2]);
+
+        // UDP length that runs past the end of the IP packet
+        bytes[38..40].copy_from_slice(&(8_u16 + 4).to_be_bytes());
+        let packet = IpV4Packet::try_read_bytes(&bytes[EthernetHeader::BYTE_LEN..]).unwrap();
+        assert_eq!(
+            UdpDatagram::try_read_bytes(packet.data),
+            Err(ParseError::BadLength(12))
+        );
+    }
+
+    #[test]
+    fn test_validation() {
+        let src_ipaddr = IpV4Addr::new([10, 0, 0, 1]);
+        let dst_ipaddr = IpV4Addr::new([10, 0, 0, 2]);
+        let builder = UdpBuilder::new(
+            MacAddr::new([0x02, 0xAF, 0xFF, 0x1A, 0xE5, 0x3C]),
+            src_ipaddr,
+            8123,
+            MacAddr::BROADCAST,
+            dst_ipaddr,
+            8125,
+        );
+        let frame = builder.build_frame(ByteArray([0, 1, 2, 3, 4, 5, 6, 7]));
+        assert_eq!(frame.data.validate(), Ok(()));
+
+        let mut bytes = [0_u8; 64];
+        let len = builder.write_bytes(&[1, 2, 3], &mut bytes).unwrap();
+        let check = |bytes: &[u8]| -> Result<(), ParseError> {
+            let ip = IpV4View::new(&bytes[14..])?;
+            ip.header().validate(bytes.len() - 14)?;
+            ip.verify_checksum()?;
+            let packet = IpV4Packet::try_read_bytes(&bytes[14..])?;
+            let datagram = UdpDatagram::try_read_bytes(packet.data)?;
+            datagram.header.validate(&packet.header)?;
+            datagram.verify_checksum(packet.header.src_ipaddr, packet.header.dst_ipaddr)?;
+            UdpView::new(ip.payload())?.verify_checksum(ip.src_ipaddr(), ip.dst_ipaddr())
+        };
+        assert_eq!(check(&bytes[..len]), Ok(()));
+
+        // Corrupted IP header
+        let mut bad = bytes;
+        bad[22] ^= 0x01;
+        assert_eq!(check(&bad[..len]), Err(ParseError::BadIpChecksum));
+
+        // Corrupted UDP data
+        let mut bad = bytes;
+        bad[len - 1] ^= 0x01;
+        assert_eq!(check(&bad[..len]), Err(ParseError::BadUdpChecksum));
+
+        // Corrupted data with checksum not present
+        bad[40..42].copy_from_slice(&[0, 0]);
+        assert_eq!(check(&bad[..len]), Ok(()));
+
+        // UDP length longer than IP data
+        let mut udp_header = UdpHeader::read_bytes(&bytes[34..42]);
+        let ip_header = IpV4Header::read_bytes(&bytes[14..34]);
+        assert_eq!(udp_header.validate(&ip_header), Ok(()));
+        udp_header.length += 1;
+        assert_eq!(
+            udp_header.validate(&ip_header),
+            Err(ParseError::BadLength(12))
+        );
+
+        // IP total length longer than the buffer
+        assert_eq!(
+            ip_header.validate(len - 15),
+            Err(ParseError::BadLength(ip_header.total_length))
+        );
+    }
 }
@@ -0,0 +1,178 @@
+//! IPV4 link-local address autoconfiguration per RFC 3927.
+//!
+//! With no DHCP server or static configuration, as when plugged directly into a laptop,
+//! a host can pick an address in 169.254.0.0/16 and claim it using [AddressConflictDetector].
+//! Candidates are drawn pseudorandomly from a seed mixed with our MAC address, so that the same host
+//! tends to get the same address each time while different hosts tend to get different ones.
+//! On a conflict, a new candidate is chosen automatically.
+//!
+//! Start this when [DhcpClient] reports [DhcpEvent::Failed] and, once the address is claimed,
+//! apply it with [LinkLocal::configure].
+//!
+//! ```rust
+//! use catnip::*;
+//!
+//! let macaddr = MacAddr::new([0x02, 0xAF, 0xFF, 0x1A, 0xE5, 0x3C]);
+//! let mut iface: Interface = Interface::new(macaddr, IpV4Addr::ANY, IpV4Addr::ANY, IpV4Addr::ANY);
+//! let mut link_local = LinkLocal::new(macaddr, 0, 0);
+//! assert!(is_link_local(link_local.ipaddr()));
+//!
+//! // Probe until the address is claimed
+//! let mut now = 0;
+//! while !link_local.is_claimed() {
+//!     // Send any frames, and pass any received ARP messages to `link_local.process`
+//!     let _event = link_local.poll(now);
+//!     now += 10;
+//! }
+//!
+//! link_local.configure(&mut iface);
+//! assert_eq!(iface.ipaddr(), link_local.ipaddr());
+//! assert_eq!(iface.subnet_mask(), LINK_LOCAL_SUBNET_MASK);
+//! ```
+
+use crate::*;
+
+/// Subnet mask for link-local addresses
+pub const LINK_LOCAL_SUBNET_MASK: IpV4Addr = ByteArray([255, 255, 0, 0]);
+
+/// Whether an address is in 169.254.0.0/16
+pub fn is_link_local(ipaddr: IpV4Addr) -> bool {
+    ipaddr.0[0] == 169 && ipaddr.0[1] == 254
+}
+
+/// Link-local address selection and claiming
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LinkLocal {
+    acd: AddressConflictDetector,
+    rng: XorShift32,
+}
+
+impl LinkLocal {
+    /// Choose a candidate address and start claiming it.
+    ///
+    /// The seed is mixed with the MAC address to choose candidates and randomize probe timing.
+    /// A fixed seed gives the same sequence of candidates for a given MAC address each time.
+    pub fn new(macaddr: MacAddr, seed: u32, now: u64) -> Self {
+        // FNV-1a hash of the MAC address
+        let hash = macaddr.0.iter().fold(0x811C_9DC5_u32, |h, &b| {
+            (h ^ b as u32).wrapping_mul(0x0100_0193)
+        });
+        let mut rng = XorShift32::new(seed ^ hash);
+        let ipaddr = candidate(&mut rng);
+        let acd = AddressConflictDetector::new(macaddr, ipaddr, rng.next_u32(), now);
+
+        LinkLocal { acd, rng }
+    }
+
+    /// Candidate address, which may only be used once claimed
+    pub fn ipaddr(&self) -> IpV4Addr {
+        self.acd.ipaddr()
+    }
+
+    /// Whether the candidate address has been claimed and may be used
+    pub fn is_claimed(&self) -> bool {
+        self.acd.is_claimed()
+    }
+
+    /// Progress of claiming the candidate address
+    pub fn state(&self) -> AcdState {
+        self.acd.state()
+    }
+
+    /// Time at which [LinkLocal::poll] next has something to do, if any
+    pub fn next_at(&self) -> Option<u64> {
+        self.acd.next_at()
+    }
+
+    /// Send probes and announcements when they are due. See [AddressConflictDetector::poll].
+    pub fn poll(&mut self, now: u64) -> AcdEvent {
+        self.acd.poll(now)
+    }
+
+    /// Check a received ARP message for conflicts. See [AddressConflictDetector::process].
+    ///
+    /// On [AcdEvent::Conflict], the application must stop using the old address, if it was claimed;
+    /// a new candidate is chosen and probing restarts.
+    pub fn process(&mut self, msg: &ArpPayload, now: u64) -> AcdEvent {
+        let event = self.acd.process(msg, now);
+        if let AcdEvent::Conflict(_) = event {
+            let ipaddr = candidate(&mut self.rng);
+            self.acd.restart(ipaddr, now);
+        }
+        event
+    }
+
+    /// Set the interface's address to the claimed link-local address, with no gateway.
+    /// Does nothing if the address has not been claimed.
+    pub fn configure<const N: usize, const P: usize>(&self, iface: &mut Interface<N, P>) {
+        if self.is_claimed() {
+            iface.set_ipaddr(self.ipaddr());
+            iface.set_subnet_mask(LINK_LOCAL_SUBNET_MASK);
+            iface.set_gateway(IpV4Addr::ANY);
+        }
+    }
+}
+
+/// Random address in 169.254.1.0 through 169.254.254.255; the first and last 256 addresses are reserved
+fn candidate(rng: &mut XorShift32) -> IpV4Addr {
+    let host = rng.range(0x0100, 0xFEFF) as u16;
+    let [hi, lo] = host.to_be_bytes();
+    IpV4Addr::new([169, 254, hi, lo])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_candidates() {
+        let mac = MacAddr::new([0x02, 0, 0, 0, 0, 1]);
+
+        // Same MAC and seed give the same address; different MACs give different addresses
+        assert_eq!(
+            LinkLocal::new(mac, 0, 0).ipaddr(),
+            LinkLocal::new(mac, 0, 0).ipaddr()
+        );
+        assert_ne!(
+            LinkLocal::new(mac, 0, 0).ipaddr(),
+            LinkLocal::new(MacAddr::new([0x02, 0, 0, 0, 0, 2]), 0, 0).ipaddr()
+        );
+
+        // Always in the usable range
+        let mut rng = XorShift32::new(1);
+        for _ in 0..1000 {
+            let ipaddr = candidate(&mut rng);
+            assert!(is_link_local(ipaddr));
+            assert!((1..=254).contains(&ipaddr.0[2]));
+        }
+    }
+
+    #[test]
+    fn test_conflict() {
+        let mac = MacAddr::new([0x02, 0, 0, 0, 0, 1]);
+        let other_mac = MacAddr::new([0x02, 0, 0, 0, 0, 2]);
+        let mut link_local = LinkLocal::new(mac, 0, 0);
+        let mut iface: Interface = Interface::new(mac, IpV4Addr::ANY, IpV4Addr::ANY, IpV4Addr::ANY);
+
+        // Not configured until claimed
+        link_local.configure(&mut iface);
+        assert_eq!(iface.ipaddr(), IpV4Addr::ANY);
+
+        // Conflict moves on to a new candidate
+        let first = link_local.ipaddr();
+        let msg = ArpPayload::announce(other_mac, first);
+        assert_eq!(link_local.process(&msg, 0), AcdEvent::Conflict(other_mac));
+        assert_ne!(link_local.ipaddr(), first);
+        assert!(is_link_local(link_local.ipaddr()));
+        assert_eq!(link_local.state(), AcdState::Probing(0));
+
+        let mut now = 0;
+        while !link_local.is_claimed() {
+            link_local.poll(now);
+            now += 10;
+        }
+        link_local.configure(&mut iface);
+        assert_eq!(iface.ipaddr(), link_local.ipaddr());
+        assert_eq!(iface.gateway(), IpV4Addr::ANY);
+    }
+}
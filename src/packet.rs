@@ -0,0 +1,246 @@
+//! Classification of received frames.
+//!
+//! Sorts a raw ethernet frame by ethertype, then by IP protocol, then by UDP port,
+//! checking each layer on the way down so that the application only has to handle
+//! the cases it cares about.
+//!
+//! ```rust
+//! use catnip::*;
+//!
+//! let builder = UdpBuilder::new(
+//!     MacAddr::new([0x02, 0xAF, 0xFF, 0x1A, 0xE5, 0x3C]),
+//!     IpV4Addr::new([10, 0, 0, 120]),
+//!     8123,
+//!     MacAddr::BROADCAST,
+//!     IpV4Addr::new([10, 0, 0, 121]),
+//!     8125,
+//! );
+//! let mut frame = [0_u8; 64];
+//! let len = builder.write_bytes(&[1, 2, 3, 4], &mut frame).unwrap();
+//!
+//! match Packet::classify(&frame[..len], true) {
+//!     Ok(Packet::Udp(udp)) => {
+//!         assert_eq!(udp.udp.dst_port, 8125);
+//!         assert_eq!(udp.payload, &[1, 2, 3, 4]);
+//!     }
+//!     _ => panic!("Expected a UDP datagram"),
+//! }
+//! ```
+
+use crate::*;
+
+/// A received frame sorted by the protocols it contains
+#[derive(Clone, Copy, uDebug, Debug, PartialEq, Eq)]
+pub enum Packet<'a> {
+    /// Address resolution request or response
+    Arp(EthernetFrame<ArpPayload>),
+    /// Reverse address resolution request or reply
+    Rarp(EthernetFrame<ArpPayload>),
+    /// UDP datagram addressed to the DHCP client port
+    Dhcp(UdpPacket<'a>),
+    /// Any other UDP datagram
+    Udp(UdpPacket<'a>),
+    /// IPV4 packet with a transport protocol other than UDP, with its data left unparsed
+    IpV4(EthernetHeader, IpV4Packet<'a>),
+    /// One piece of a fragmented IPV4 datagram, with its data left unparsed.
+    /// Fragments are not reassembled here, and their transport headers and checksums
+    /// can't be checked until they are.
+    Fragment(EthernetHeader, IpV4Packet<'a>),
+    /// Frame with an ethertype that is not handled here, with its raw ethertype value
+    Unsupported(u16),
+}
+
+impl<'a> Packet<'a> {
+    /// Sort a received ethernet frame by ethertype, IP protocol, and UDP destination port.
+    ///
+    /// Lengths are always checked against each other and against the size of the frame.
+    /// IP and UDP checksums are verified in software if `verify_checksums` is set;
+    /// this can be skipped if the hardware has already checked them.
+    pub fn classify(frame: &'a [u8], verify_checksums: bool) -> Result<Self, ParseError> {
+        let ethernet = EthernetView::new(frame)?.header();
+        let payload = frame.get(EthernetHeader::BYTE_LEN..).unwrap_or(&[]);
+
+        match ethernet.ethertype {
+            EtherType::Arp => Ok(Packet::Arp(EthernetFrame {
+                header: ethernet,
+                data: ArpPayload::try_read_bytes(payload)?,
+                checksum: 0,
+            })),
+            EtherType::Rarp => Ok(Packet::Rarp(EthernetFrame {
+                header: ethernet,
+                data: ArpPayload::try_read_bytes(payload)?,
+                checksum: 0,
+            })),
+            EtherType::IpV4 => {
+                let ip = IpV4View::new(payload)?;
+                if verify_checksums {
+                    ip.verify_checksum()?;
+                }
+                let packet = IpV4Packet::try_read_bytes(payload)?;
+                if packet.header.fragmentation.is_fragment() {
+                    return Ok(Packet::Fragment(ethernet, packet));
+                }
+                if packet.header.protocol != Protocol::Udp {
+                    return Ok(Packet::IpV4(ethernet, packet));
+                }
+
+                let datagram = UdpDatagram::try_read_bytes(packet.data)?;
+                if verify_checksums {
+                    datagram.verify_checksum(packet.header.src_ipaddr, packet.header.dst_ipaddr)?;
+                }
+                let udp = UdpPacket {
+                    ethernet,
+                    ip: packet.header,
+                    udp: datagram.header,
+                    payload: datagram.data,
+                };
+
+                match udp.udp.dst_port {
+                    DHCP_CLIENT_PORT => Ok(Packet::Dhcp(udp)),
+                    _ => Ok(Packet::Udp(udp)),
+                }
+            }
+            _ => Ok(Packet::Unsupported(get_u16(frame, 12))),
+        }
+    }
+}
+
+/// A received UDP datagram along with the headers of the layers that carried it
+#[derive(Clone, Copy, uDebug, Debug, PartialEq, Eq)]
+pub struct UdpPacket<'a> {
+    /// Ethernet header, including the sender's MAC address
+    pub ethernet: EthernetHeader,
+    /// IP header, including the sender's IP address
+    pub ip: IpV4Header,
+    /// UDP header, including the ports
+    pub udp: UdpHeader,
+    /// Data after the UDP header, bounded by the UDP length
+    pub payload: &'a [u8],
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn builder() -> UdpBuilder {
+        UdpBuilder::new(
+            MacAddr::new([0x02, 0xAF, 0xFF, 0x1A, 0xE5, 0x3C]),
+            IpV4Addr::new([10, 0, 0, 1]),
+            8123,
+            MacAddr::BROADCAST,
+            IpV4Addr::new([10, 0, 0, 2]),
+            8125,
+        )
+    }
+
+    #[test]
+    fn test_classify() {
+        let mut frame = [0_u8; 128];
+
+        // UDP
+        let len = builder().write_bytes(&[1, 2, 3], &mut frame).unwrap();
+        match Packet::classify(&frame[..len], true) {
+            Ok(Packet::Udp(udp)) => {
+                assert_eq!(udp.ethernet.src_macaddr, builder().src_macaddr);
+                assert_eq!(udp.ip.src_ipaddr, builder().src_ipaddr);
+                assert_eq!(udp.udp.src_port, 8123);
+                assert_eq!(udp.payload, &[1, 2, 3]);
+            }
+            x => panic!("{x:?}"),
+        }
+
+        // Corruption is reported instead of classified
+        frame[len - 1] ^= 0xFF;
+        assert_eq!(
+            Packet::classify(&frame[..len], true),
+            Err(ParseError::BadUdpChecksum)
+        );
+        assert!(matches!(
+            Packet::classify(&frame[..len], false),
+            Ok(Packet::Udp(_))
+        ));
+
+        // DHCP by port
+        let mut dhcp = builder();
+        dhcp.dst_port = DHCP_CLIENT_PORT;
+        let len = dhcp.write_bytes(&[0; 8], &mut frame).unwrap();
+        assert!(matches!(
+            Packet::classify(&frame[..len], true),
+            Ok(Packet::Dhcp(_))
+        ));
+
+        // Other IP protocols
+        frame[14 + 9] = Protocol::Tcp as u8;
+        IpV4View::new(&mut frame[14..len]).unwrap().fill_checksum();
+        match Packet::classify(&frame[..len], true) {
+            Ok(Packet::IpV4(_, packet)) => assert_eq!(packet.header.protocol, Protocol::Tcp),
+            x => panic!("{x:?}"),
+        }
+
+        // ARP
+        let arp = EthernetFrame {
+            header: EthernetHeader {
+                dst_macaddr: MacAddr::BROADCAST,
+                src_macaddr: builder().src_macaddr,
+                ethertype: EtherType::Arp,
+            },
+            data: ArpPayload::new(
+                builder().src_macaddr,
+                builder().src_ipaddr,
+                MacAddr::ANY,
+                builder().dst_ipaddr,
+                ArpOperation::Request,
+            ),
+            checksum: 0,
+        };
+        assert_eq!(
+            Packet::classify(&arp.to_be_bytes(), true),
+            Ok(Packet::Arp(arp))
+        );
+
+        // RARP
+        let rarp = EthernetFrame::rarp_request(builder().src_macaddr);
+        assert_eq!(
+            Packet::classify(&rarp.to_be_bytes(), true),
+            Ok(Packet::Rarp(rarp))
+        );
+
+        // Fragments are not parsed as whole datagrams, whether more are coming or this is the last
+        let len = builder().write_bytes(&[1, 2, 3], &mut frame).unwrap();
+        for (flags, offset) in [(0x20, 0), (0x00, 0x10)] {
+            let mut fragment = frame;
+            fragment[14 + 6..14 + 8].copy_from_slice(&[flags, offset]);
+            IpV4View::new(&mut fragment[14..len])
+                .unwrap()
+                .fill_checksum();
+            match Packet::classify(&fragment[..len], true) {
+                Ok(Packet::Fragment(_, packet)) => {
+                    let fragmentation = packet.header.fragmentation;
+                    assert_eq!(fragmentation.more_fragments(), (flags != 0) as u8);
+                    assert_eq!(fragmentation.offset(), offset as u16);
+                }
+                x => panic!("{x:?}"),
+            }
+        }
+
+        // Don't-fragment alone is a whole datagram
+        frame[14 + 6] = 0x40;
+        IpV4View::new(&mut frame[14..len]).unwrap().fill_checksum();
+        assert!(matches!(
+            Packet::classify(&frame[..len], true),
+            Ok(Packet::Udp(udp)) if udp.ip.fragmentation.do_not_fragment() == 1
+        ));
+
+        // Unsupported ethertypes keep their value
+        frame[12..14].copy_from_slice(&(EtherType::IpV6 as u16).to_be_bytes());
+        assert_eq!(
+            Packet::classify(&frame[..len], true),
+            Ok(Packet::Unsupported(0x86DD))
+        );
+        frame[12..14].copy_from_slice(&0x88B5_u16.to_be_bytes());
+        assert_eq!(
+            Packet::classify(&frame[..len], true),
+            Ok(Packet::Unsupported(0x88B5))
+        );
+    }
+}
@@ -0,0 +1,482 @@
+//! Outgoing frames waiting for ARP resolution of their next hop.
+//!
+//! When the application sends to an address whose hardware address is not known yet, the frame is
+//! held in a [PendingQueue] while ARP requests are sent, with the delay between requests doubling
+//! each time. Once a reply arrives and is learned by the [Interface], the frames are released in
+//! order; if no reply arrives after the configured number of requests, the frames are discarded
+//! and the destination is reported as unreachable.
+//!
+//! ```rust
+//! use catnip::*;
+//!
+//! let mut iface: Interface = Interface::new(
+//!     MacAddr::new([0x02, 0xAF, 0xFF, 0x1A, 0xE5, 0x3C]),
+//!     IpV4Addr::new([10, 0, 0, 120]),
+//!     IpV4Addr::new([255, 255, 255, 0]),
+//!     IpV4Addr::ANY,
+//! );
+//! let mut pending: PendingQueue<4, 1514> = PendingQueue::default();
+//! let mut tx_buffer = [0_u8; 1514];
+//! let dst_ipaddr = IpV4Addr::new([10, 0, 0, 121]);
+//!
+//! // The destination is unknown, so the datagram is held and an ARP request goes out instead
+//! assert_eq!(pending.send_udp(&iface, 8123, dst_ipaddr, 8125, &[1, 2, 3], &mut tx_buffer, 0), Ok(None));
+//! assert_eq!(pending.poll(&mut iface, &mut tx_buffer, 0), PendingEvent::Transmit(60));
+//!
+//! // Nobody answers
+//! let mut now = 0;
+//! loop {
+//!     match pending.poll(&mut iface, &mut tx_buffer, now) {
+//!         PendingEvent::HostUnreachable(x) => {
+//!             assert_eq!(x, dst_ipaddr);
+//!             break;
+//!         }
+//!         _ => now += 100,
+//!     }
+//! }
+//! assert!(pending.is_empty());
+//! ```
+
+use crate::*;
+
+/// Default delay in milliseconds before repeating the first ARP request
+pub const ARP_RETRY_INTERVAL_MS: u64 = 1_000;
+/// Default number of ARP requests to send before giving up on a destination
+pub const ARP_MAX_REQUESTS: u8 = 3;
+/// Default number of frames held for each destination
+pub const PENDING_PER_DESTINATION: usize = 4;
+
+/// Why a frame could not be held
+#[derive(Clone, Copy, uDebug, Debug, PartialEq, Eq)]
+pub enum PendingError {
+    /// No room in the queue, or the limit of frames for this destination has been reached
+    QueueFull,
+    /// The frame is larger than the queue's frame capacity
+    FrameTooLong,
+    /// The datagram could not be written as a frame
+    Send(SendError),
+}
+
+/// Something the application should act on after polling the queue
+#[derive(Clone, Copy, uDebug, Debug, PartialEq, Eq)]
+pub enum PendingEvent {
+    /// Nothing to do right now
+    None,
+    /// A released frame or an ARP request was written to the start of the transmit buffer;
+    /// send this many bytes
+    Transmit(usize),
+    /// No ARP reply came from this next hop, and the frames for it were discarded
+    HostUnreachable(IpV4Addr),
+    /// A frame for this next hop was released but did not fit in the transmit buffer,
+    /// and was discarded
+    Dropped(IpV4Addr),
+}
+
+/// A frame waiting for its next hop's hardware address
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct PendingFrame<const M: usize> {
+    next_hop: IpV4Addr,
+    /// Order of arrival, so that frames are released in the order they were sent
+    seq: u32,
+    len: usize,
+    data: [u8; M],
+}
+
+/// Progress of resolving one next hop
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Resolution {
+    next_hop: IpV4Addr,
+    requests: u8,
+    next_at: u64,
+}
+
+/// Up to `N` frames of up to `M` bytes each, waiting for ARP resolution
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PendingQueue<const N: usize, const M: usize> {
+    frames: [Option<PendingFrame<M>>; N],
+    resolutions: [Option<Resolution>; N],
+    seq: u32,
+    per_destination: usize,
+    max_requests: u8,
+    retry_interval: u64,
+}
+
+impl<const N: usize, const M: usize> PendingQueue<N, M> {
+    /// New empty queue holding at most `per_destination` frames for each next hop, sending at most
+    /// `max_requests` ARP requests for each, starting `retry_interval` milliseconds apart and doubling
+    pub const fn new(per_destination: usize, max_requests: u8, retry_interval: u64) -> Self {
+        PendingQueue {
+            frames: [None; N],
+            resolutions: [None; N],
+            seq: 0,
+            per_destination,
+            max_requests,
+            retry_interval,
+        }
+    }
+
+    /// Number of frames held
+    pub fn len(&self) -> usize {
+        self.frames.iter().flatten().count()
+    }
+
+    /// Whether no frames are held
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Time at which the next ARP request or timeout is due, if any
+    pub fn next_at(&self) -> Option<u64> {
+        self.resolutions.iter().flatten().map(|x| x.next_at).min()
+    }
+
+    /// Hold a complete ethernet frame until the hardware address of its next hop is known.
+    /// The destination MAC address in the frame is overwritten when it is released.
+    pub fn enqueue(
+        &mut self,
+        next_hop: IpV4Addr,
+        frame: &[u8],
+        now: u64,
+    ) -> Result<(), PendingError> {
+        if frame.len() > M {
+            return Err(PendingError::FrameTooLong);
+        }
+        let held = self
+            .frames
+            .iter()
+            .flatten()
+            .filter(|x| x.next_hop == next_hop)
+            .count();
+        if held >= self.per_destination {
+            return Err(PendingError::QueueFull);
+        }
+        let slot = self
+            .frames
+            .iter_mut()
+            .find(|x| x.is_none())
+            .ok_or(PendingError::QueueFull)?;
+
+        let mut data = [0_u8; M];
+        set_array(&mut data, 0, frame);
+        *slot = Some(PendingFrame {
+            next_hop,
+            seq: self.seq,
+            len: frame.len(),
+            data,
+        });
+        self.seq = self.seq.wrapping_add(1);
+
+        // Start resolving if we are not already; there is always room for one per frame
+        if !self
+            .resolutions
+            .iter()
+            .flatten()
+            .any(|x| x.next_hop == next_hop)
+        {
+            if let Some(x) = self.resolutions.iter_mut().find(|x| x.is_none()) {
+                *x = Some(Resolution {
+                    next_hop,
+                    requests: 0,
+                    next_at: now,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write a UDP datagram from us into the transmit buffer if the next hop's hardware address is known,
+    /// returning the number of bytes to send; otherwise, hold it until the address is resolved.
+    #[allow(clippy::too_many_arguments)]
+    pub fn send_udp<const K: usize, const P: usize>(
+        &mut self,
+        iface: &Interface<K, P>,
+        src_port: u16,
+        dst_ipaddr: IpV4Addr,
+        dst_port: u16,
+        payload: &[u8],
+        tx_buffer: &mut [u8],
+        now: u64,
+    ) -> Result<Option<usize>, PendingError> {
+        match iface.write_udp(src_port, dst_ipaddr, dst_port, payload, tx_buffer) {
+            Ok(len) => Ok(Some(len)),
+            Err(SendError::Unresolved(next_hop)) => {
+                let mut frame = [0_u8; M];
+                let len = UdpBuilder::new(
+                    iface.macaddr(),
+                    iface.ipaddr(),
+                    src_port,
+                    MacAddr::ANY,
+                    dst_ipaddr,
+                    dst_port,
+                )
+                .write_bytes(payload, &mut frame)
+                .map_err(|_| PendingError::FrameTooLong)?;
+                self.enqueue(next_hop, frame.get(..len).unwrap_or(&[]), now)?;
+                Ok(None)
+            }
+            Err(x) => Err(PendingError::Send(x)),
+        }
+    }
+
+    /// Release a frame whose next hop has been resolved, or send an ARP request or give up on a
+    /// next hop if one is due, writing any frame to send into the start of the transmit buffer.
+    /// Call repeatedly until it returns [PendingEvent::None].
+    pub fn poll<const K: usize, const P: usize>(
+        &mut self,
+        iface: &mut Interface<K, P>,
+        tx_buffer: &mut [u8],
+        now: u64,
+    ) -> PendingEvent {
+        // Release frames for resolved next hops, oldest first
+        for i in 0..N {
+            let next_hop = match self.resolutions.get(i) {
+                Some(Some(x)) => x.next_hop,
+                _ => continue,
+            };
+            let macaddr = match iface.arp_cache().lookup(next_hop) {
+                Some(x) => x,
+                None => continue,
+            };
+            match self.take_oldest(next_hop) {
+                Some(frame) => {
+                    let frame_bytes = frame.data.get(..frame.len).unwrap_or(&[]);
+                    match tx_buffer.get_mut(..frame_bytes.len()) {
+                        Some(dst) => {
+                            dst.copy_from_slice(frame_bytes);
+                            set_array(dst, 0, &macaddr.0);
+                            return PendingEvent::Transmit(frame_bytes.len());
+                        }
+                        None => return PendingEvent::Dropped(next_hop),
+                    }
+                }
+                None => self.remove_resolution(next_hop),
+            }
+        }
+
+        // Send a due ARP request, or give up. A request that could not be written still counts,
+        // so that it backs off and does not hold up the others.
+        while let Some(x) = self
+            .resolutions
+            .iter()
+            .flatten()
+            .filter(|x| x.next_at <= now)
+            .min_by_key(|x| x.next_at)
+            .copied()
+        {
+            if x.requests >= self.max_requests {
+                self.remove_resolution(x.next_hop);
+                for slot in self.frames.iter_mut() {
+                    if matches!(slot, Some(f) if f.next_hop == x.next_hop) {
+                        *slot = None;
+                    }
+                }
+                return PendingEvent::HostUnreachable(x.next_hop);
+            }
+
+            let backoff = self.retry_interval.saturating_mul(1 << x.requests.min(16));
+            if let Some(r) = self
+                .resolutions
+                .iter_mut()
+                .flatten()
+                .find(|r| r.next_hop == x.next_hop)
+            {
+                r.requests = r.requests.saturating_add(1);
+                r.next_at = now.saturating_add(backoff);
+            }
+            if let Ok(len) = iface.write_arp_request(x.next_hop, tx_buffer, now) {
+                return PendingEvent::Transmit(len);
+            }
+        }
+
+        PendingEvent::None
+    }
+
+    /// Remove and return the oldest frame for a next hop
+    fn take_oldest(&mut self, next_hop: IpV4Addr) -> Option<PendingFrame<M>> {
+        let seq = self.seq;
+        self.frames
+            .iter_mut()
+            .filter(|x| matches!(x, Some(f) if f.next_hop == next_hop))
+            // Age relative to the next sequence number, so that wrapping does not reorder frames
+            .max_by_key(|x| x.map(|f| seq.wrapping_sub(f.seq)))
+            .and_then(|x| x.take())
+    }
+
+    fn remove_resolution(&mut self, next_hop: IpV4Addr) {
+        for slot in self.resolutions.iter_mut() {
+            if matches!(slot, Some(x) if x.next_hop == next_hop) {
+                *slot = None;
+            }
+        }
+    }
+}
+
+impl<const N: usize, const M: usize> Default for PendingQueue<N, M> {
+    fn default() -> Self {
+        Self::new(
+            PENDING_PER_DESTINATION,
+            ARP_MAX_REQUESTS,
+            ARP_RETRY_INTERVAL_MS,
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const OUR_MAC: MacAddr = ByteArray([0x02, 0, 0, 0, 0, 1]);
+    const OUR_IP: IpV4Addr = ByteArray([10, 0, 0, 1]);
+    const PEER_MAC: MacAddr = ByteArray([0x02, 0, 0, 0, 0, 2]);
+    const PEER_IP: IpV4Addr = ByteArray([10, 0, 0, 2]);
+
+    fn iface() -> Interface {
+        Interface::new(
+            OUR_MAC,
+            OUR_IP,
+            IpV4Addr::new([255, 255, 255, 0]),
+            IpV4Addr::ANY,
+        )
+    }
+
+    #[test]
+    fn test_release() {
+        let mut iface = iface();
+        let mut pending: PendingQueue<4, 128> = PendingQueue::new(2, 3, 100);
+        let mut tx = [0_u8; 128];
+
+        // Bounded per destination
+        for i in 0..2 {
+            assert_eq!(
+                pending.send_udp(&iface, 1, PEER_IP, 2, &[i], &mut tx, 0),
+                Ok(None)
+            );
+        }
+        assert_eq!(
+            pending.send_udp(&iface, 1, PEER_IP, 2, &[2], &mut tx, 0),
+            Err(PendingError::QueueFull)
+        );
+
+        // One ARP request, then nothing until the retry is due
+        let len = match pending.poll(&mut iface, &mut tx, 0) {
+            PendingEvent::Transmit(len) => len,
+            x => panic!("{x:?}"),
+        };
+        match Packet::classify(&tx[..len], true) {
+            Ok(Packet::Arp(x)) => assert_eq!(
+                x.data,
+                ArpPayload::new(
+                    OUR_MAC,
+                    OUR_IP,
+                    MacAddr::ANY,
+                    PEER_IP,
+                    ArpOperation::Request
+                )
+            ),
+            x => panic!("{x:?}"),
+        }
+        assert_eq!(pending.poll(&mut iface, &mut tx, 50), PendingEvent::None);
+        assert_eq!(pending.next_at(), Some(100));
+
+        // Reply arrives; frames are released in order with the resolved address
+        let reply = EthernetFrame::arp(
+            OUR_MAC,
+            ArpPayload::new(PEER_MAC, PEER_IP, OUR_MAC, OUR_IP, ArpOperation::Response),
+        );
+        iface.poll(&reply.to_be_bytes(), &mut tx, 60);
+        for i in 0..2 {
+            let len = match pending.poll(&mut iface, &mut tx, 60) {
+                PendingEvent::Transmit(len) => len,
+                x => panic!("{x:?}"),
+            };
+            match Packet::classify(&tx[..len], true) {
+                Ok(Packet::Udp(x)) => {
+                    assert_eq!(x.ethernet.dst_macaddr, PEER_MAC);
+                    assert_eq!(x.payload, &[i]);
+                }
+                x => panic!("{x:?}"),
+            }
+        }
+        assert_eq!(pending.poll(&mut iface, &mut tx, 60), PendingEvent::None);
+        assert!(pending.is_empty());
+        assert_eq!(pending.next_at(), None);
+
+        // Now the destination is known, datagrams go straight out
+        assert!(matches!(
+            pending.send_udp(&iface, 1, PEER_IP, 2, &[3], &mut tx, 70),
+            Ok(Some(_))
+        ));
+    }
+
+    #[test]
+    fn test_unreachable() {
+        let mut iface = iface();
+        let mut pending: PendingQueue<4, 128> = PendingQueue::new(2, 3, 100);
+        let mut tx = [0_u8; 128];
+        pending.enqueue(PEER_IP, &[0; 60], 0).unwrap();
+
+        // Requests back off exponentially
+        let mut requests = [0; 3];
+        let mut n = 0;
+        for now in 0..1000 {
+            match pending.poll(&mut iface, &mut tx, now) {
+                PendingEvent::Transmit(_) => {
+                    requests[n] = now;
+                    n += 1;
+                }
+                PendingEvent::HostUnreachable(x) => {
+                    assert_eq!(x, PEER_IP);
+                    assert_eq!(now, 700);
+                    break;
+                }
+                PendingEvent::None => {}
+                x => panic!("{x:?}"),
+            }
+        }
+        assert_eq!(requests, [0, 100, 300]);
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn test_small_buffer() {
+        let mut iface = iface();
+        let mut pending: PendingQueue<4, 128> = PendingQueue::new(2, 3, 100);
+        let mut tx = [0_u8; 128];
+        let other_ip = IpV4Addr::new([10, 0, 0, 3]);
+        pending.enqueue(PEER_IP, &[0; 60], 0).unwrap();
+        pending.enqueue(other_ip, &[0; 60], 0).unwrap();
+
+        // Requests that don't fit still back off, and neither destination holds up the other
+        for now in [0, 100, 300] {
+            assert_eq!(
+                pending.poll(&mut iface, &mut tx[..20], now),
+                PendingEvent::None
+            );
+        }
+        assert_eq!(pending.next_at(), Some(700));
+        assert_eq!(
+            pending.poll(&mut iface, &mut tx[..20], 700),
+            PendingEvent::HostUnreachable(PEER_IP)
+        );
+        assert_eq!(
+            pending.poll(&mut iface, &mut tx[..20], 700),
+            PendingEvent::HostUnreachable(other_ip)
+        );
+        assert!(pending.is_empty());
+
+        // A released frame that doesn't fit is reported and discarded
+        pending.enqueue(PEER_IP, &[0; 60], 800).unwrap();
+        let reply = EthernetFrame::arp(
+            OUR_MAC,
+            ArpPayload::new(PEER_MAC, PEER_IP, OUR_MAC, OUR_IP, ArpOperation::Response),
+        );
+        iface.poll(&reply.to_be_bytes(), &mut tx, 800);
+        assert_eq!(
+            pending.poll(&mut iface, &mut tx[..40], 800),
+            PendingEvent::Dropped(PEER_IP)
+        );
+        assert!(pending.is_empty());
+        assert_eq!(pending.poll(&mut iface, &mut tx, 800), PendingEvent::None);
+    }
+}
@@ -0,0 +1,209 @@
+//! Reverse Address Resolution Protocol (RFC 903).
+//!
+//! Hosts with no configuration storage, such as some older PLCs, broadcast a RARP request
+//! at boot to learn their IP address from a server that keeps a table keyed by MAC address.
+//! RARP messages use the same layout as ARP, with their own ethertype and operations.
+//!
+//! ```rust
+//! use catnip::*;
+//!
+//! let server_mac = MacAddr::new([0x02, 0, 0, 0, 0, 1]);
+//! let server_ip = IpV4Addr::new([10, 0, 0, 1]);
+//! let plc_mac = MacAddr::new([0x02, 0, 0, 0, 0, 2]);
+//! let plc_ip = IpV4Addr::new([10, 0, 0, 2]);
+//! let mut server = RarpResponder::new(server_mac, server_ip, [(plc_mac, plc_ip)]);
+//!
+//! // The PLC asks for its address and the server answers from its table
+//! let request = EthernetFrame::rarp_request(plc_mac);
+//! let reply = server.respond(&request).unwrap();
+//!
+//! assert_eq!(reply.header.dst_macaddr, plc_mac);
+//! assert_eq!(reply.rarp_assigned(plc_mac), Some(plc_ip));
+//! ```
+
+use crate::*;
+
+impl ArpPayload {
+    /// RARP request for the IP address of `target_mac`, which is usually our own.
+    /// The protocol addresses are not known yet and are left as 0.0.0.0.
+    pub fn rarp_request(src_mac: MacAddr, target_mac: MacAddr) -> Self {
+        Self::new(
+            src_mac,
+            IpV4Addr::ANY,
+            target_mac,
+            IpV4Addr::ANY,
+            ArpOperation::RarpRequest,
+        )
+    }
+
+    /// RARP reply from a server at `src_mac` and `src_ipaddr`, giving `ipaddr` as the address of `target_mac`
+    pub fn rarp_reply(
+        src_mac: MacAddr,
+        src_ipaddr: IpV4Addr,
+        target_mac: MacAddr,
+        ipaddr: IpV4Addr,
+    ) -> Self {
+        Self::new(
+            src_mac,
+            src_ipaddr,
+            target_mac,
+            ipaddr,
+            ArpOperation::RarpReply,
+        )
+    }
+}
+
+impl EthernetFrame<ArpPayload> {
+    /// Ethernet frame from us carrying a RARP message
+    pub fn rarp(dst_macaddr: MacAddr, msg: ArpPayload) -> Self {
+        EthernetFrame {
+            header: EthernetHeader {
+                dst_macaddr,
+                src_macaddr: msg.src_mac,
+                ethertype: EtherType::Rarp,
+            },
+            data: msg,
+            checksum: 0,
+        }
+    }
+
+    /// Broadcast RARP request for our own IP address
+    pub fn rarp_request(src_mac: MacAddr) -> Self {
+        Self::rarp(
+            MacAddr::BROADCAST,
+            ArpPayload::rarp_request(src_mac, src_mac),
+        )
+    }
+
+    /// The IP address this frame assigns to `macaddr`, if it is a valid RARP reply for that address
+    pub fn rarp_assigned(&self, macaddr: MacAddr) -> Option<IpV4Addr> {
+        let msg = &self.data;
+        if self.header.ethertype != EtherType::Rarp
+            || msg.validate().is_err()
+            || msg.operation != ArpOperation::RarpReply
+            || msg.dst_mac != macaddr
+        {
+            return None;
+        }
+
+        Some(msg.dst_ipaddr)
+    }
+}
+
+/// Answers RARP requests from a fixed table of up to `N` MAC addresses and their IP addresses
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RarpResponder<const N: usize> {
+    /// Our MAC address, given in replies
+    pub macaddr: MacAddr,
+    /// Our IP address, given in replies
+    pub ipaddr: IpV4Addr,
+    /// Hosts' MAC addresses and the IP addresses to give them
+    pub table: [(MacAddr, IpV4Addr); N],
+    answered: u32,
+}
+
+impl<const N: usize> RarpResponder<N> {
+    /// New responder for the hosts in the table
+    pub fn new(macaddr: MacAddr, ipaddr: IpV4Addr, table: [(MacAddr, IpV4Addr); N]) -> Self {
+        RarpResponder {
+            macaddr,
+            ipaddr,
+            table,
+            answered: 0,
+        }
+    }
+
+    /// The IP address to give a host, if it is in the table
+    pub fn lookup(&self, macaddr: MacAddr) -> Option<IpV4Addr> {
+        self.table
+            .iter()
+            .find(|(mac, _)| *mac == macaddr)
+            .map(|(_, ip)| *ip)
+    }
+
+    /// Number of requests that were answered
+    pub fn answered(&self) -> u32 {
+        self.answered
+    }
+
+    /// Build the reply to a received frame, addressed to the requester,
+    /// or `None` if the frame is not a valid RARP request for a host in the table
+    pub fn respond(
+        &mut self,
+        frame: &EthernetFrame<ArpPayload>,
+    ) -> Option<EthernetFrame<ArpPayload>> {
+        let msg = &frame.data;
+        if frame.header.ethertype != EtherType::Rarp
+            || msg.validate().is_err()
+            || msg.operation != ArpOperation::RarpRequest
+        {
+            return None;
+        }
+
+        let ipaddr = self.lookup(msg.dst_mac)?;
+        self.answered = self.answered.wrapping_add(1);
+
+        Some(EthernetFrame::rarp(
+            msg.src_mac,
+            ArpPayload::rarp_reply(self.macaddr, self.ipaddr, msg.dst_mac, ipaddr),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SERVER_MAC: MacAddr = ByteArray([0x02, 0, 0, 0, 0, 1]);
+    const SERVER_IP: IpV4Addr = ByteArray([192, 168, 1, 1]);
+    const PLC_MAC: MacAddr = ByteArray([0x02, 0, 0, 0, 0, 2]);
+    const PLC_IP: IpV4Addr = ByteArray([192, 168, 1, 2]);
+    const OTHER_MAC: MacAddr = ByteArray([0x02, 0, 0, 0, 0, 3]);
+
+    #[test]
+    fn test_rarp() {
+        let mut server = RarpResponder::new(SERVER_MAC, SERVER_IP, [(PLC_MAC, PLC_IP)]);
+
+        // Request for ourselves, broadcast with unknown protocol addresses
+        let request = EthernetFrame::rarp_request(PLC_MAC);
+        assert_eq!(request.header.dst_macaddr, MacAddr::BROADCAST);
+        assert_eq!(request.header.ethertype, EtherType::Rarp);
+        assert_eq!(request.data.operation, ArpOperation::RarpRequest);
+        assert_eq!(request.data.dst_mac, PLC_MAC);
+        assert_eq!(request.data.src_ipaddr, IpV4Addr::ANY);
+
+        // Reply goes back to the requester with the server's addresses as the sender
+        let reply = server.respond(&request).unwrap();
+        assert_eq!(reply.header.dst_macaddr, PLC_MAC);
+        assert_eq!(reply.header.ethertype, EtherType::Rarp);
+        assert_eq!(reply.data.src_mac, SERVER_MAC);
+        assert_eq!(reply.data.src_ipaddr, SERVER_IP);
+        assert_eq!(reply.rarp_assigned(PLC_MAC), Some(PLC_IP));
+        assert_eq!(reply.rarp_assigned(OTHER_MAC), None);
+        assert_eq!(server.answered(), 1);
+
+        // A third party may ask on another host's behalf
+        let request = EthernetFrame::rarp(
+            MacAddr::BROADCAST,
+            ArpPayload::rarp_request(OTHER_MAC, PLC_MAC),
+        );
+        let reply = server.respond(&request).unwrap();
+        assert_eq!(reply.header.dst_macaddr, OTHER_MAC);
+        assert_eq!(reply.rarp_assigned(PLC_MAC), Some(PLC_IP));
+
+        // Hosts not in the table, replies, and plain ARP are not answered
+        assert_eq!(
+            server.respond(&EthernetFrame::rarp_request(OTHER_MAC)),
+            None
+        );
+        assert_eq!(server.respond(&reply), None);
+        let mut arp = request;
+        arp.header.ethertype = EtherType::Arp;
+        assert_eq!(server.respond(&arp), None);
+        assert_eq!(server.answered(), 2);
+
+        // An ARP response is not a RARP reply
+        let arp_reply = EthernetFrame::gratuitous_arp_reply(SERVER_MAC, PLC_IP);
+        assert_eq!(arp_reply.rarp_assigned(SERVER_MAC), None);
+    }
+}
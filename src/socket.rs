@@ -0,0 +1,474 @@
+//! UDP pseudo-sockets with fixed-capacity send and receive queues.
+//!
+//! A [UdpSocket] is bound to a local port. The application queues outgoing datagrams with
+//! [DatagramSocket::send_to] and reads incoming ones with [DatagramSocket::recv_from], while the
+//! poll loop moves datagrams between the socket's queues and the [Interface] and [Device].
+//!
+//! ```rust
+//! use catnip::*;
+//!
+//! let mut iface: Interface = Interface::new(
+//!     MacAddr::new([0x02, 0xAF, 0xFF, 0x1A, 0xE5, 0x3C]),
+//!     IpV4Addr::new([10, 0, 0, 120]),
+//!     IpV4Addr::new([255, 255, 255, 0]),
+//!     IpV4Addr::ANY,
+//! );
+//! let mut device: Loopback<4, 1514> = Loopback::new();
+//! let mut socket: UdpSocket<4, 64> = UdpSocket::bind(8125);
+//! let mut rx_buffer = [0_u8; 1514];
+//! let mut tx_buffer = [0_u8; 1514];
+//!
+//! // Queue a datagram and send it
+//! socket.send_to(IpV4Addr::new([10, 0, 0, 255]), 8125, &[1, 2, 3]).unwrap();
+//! assert_eq!(socket.dispatch(&iface, &mut device, &mut tx_buffer), Ok(true));
+//!
+//! // Receive it and hand it to the socket
+//! if let Some(InterfaceEvent::Udp(udp)) = iface.poll_device(&mut device, &mut rx_buffer, &mut tx_buffer, 0) {
+//!     socket.process(&udp);
+//! }
+//!
+//! let mut buffer = [0_u8; 64];
+//! assert_eq!(
+//!     socket.recv_from(&mut buffer),
+//!     Ok((IpV4Addr::new([10, 0, 0, 120]), 8125, 3))
+//! );
+//! assert_eq!(&buffer[..3], &[1, 2, 3]);
+//! ```
+
+use crate::*;
+
+use core::task::{Context, Poll};
+
+/// Why a socket operation could not be completed
+#[derive(Clone, Copy, uDebug, Debug, PartialEq, Eq)]
+pub enum SocketError {
+    /// No datagram is waiting to be received, or there is no room to queue one for sending
+    WouldBlock,
+    /// The payload is larger than the socket's datagram capacity
+    PayloadTooLong,
+    /// The buffer is too small for the next received datagram, which is left in the queue
+    BufferTooSmall,
+    /// The datagram could not be written as a frame
+    Send(SendError),
+    /// The device would not send the frame
+    Device(DeviceError),
+}
+
+/// Sending and receiving datagrams by remote address and port
+pub trait DatagramSocket {
+    /// Queue a datagram for sending to a remote address and port
+    fn send_to(
+        &mut self,
+        dst_ipaddr: IpV4Addr,
+        dst_port: u16,
+        payload: &[u8],
+    ) -> Result<(), SocketError>;
+
+    /// Copy the oldest received datagram into the start of the buffer, returning the
+    /// remote address and port it came from and its length
+    fn recv_from(&mut self, buffer: &mut [u8]) -> Result<(IpV4Addr, u16, usize), SocketError>;
+}
+
+/// A datagram waiting in a socket's queue, with the remote address and port
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Datagram<const M: usize> {
+    ipaddr: IpV4Addr,
+    port: u16,
+    len: usize,
+    data: [u8; M],
+}
+
+impl<const M: usize> Datagram<M> {
+    const EMPTY: Self = Datagram {
+        ipaddr: IpV4Addr::ANY,
+        port: 0,
+        len: 0,
+        data: [0; M],
+    };
+
+    fn payload(&self) -> &[u8] {
+        self.data.get(..self.len).unwrap_or(&[])
+    }
+}
+
+/// Fixed-capacity first-in, first-out queue of up to `N` datagrams of up to `M` bytes each
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct DatagramQueue<const N: usize, const M: usize> {
+    slots: [Datagram<M>; N],
+    head: usize,
+    count: usize,
+}
+
+impl<const N: usize, const M: usize> DatagramQueue<N, M> {
+    const fn new() -> Self {
+        DatagramQueue {
+            slots: [Datagram::EMPTY; N],
+            head: 0,
+            count: 0,
+        }
+    }
+
+    fn push(&mut self, ipaddr: IpV4Addr, port: u16, payload: &[u8]) -> Result<(), SocketError> {
+        if payload.len() > M {
+            return Err(SocketError::PayloadTooLong);
+        }
+        if self.count >= N {
+            return Err(SocketError::WouldBlock);
+        }
+        let slot = self
+            .slots
+            .get_mut((self.head + self.count) % N)
+            .ok_or(SocketError::WouldBlock)?;
+        slot.ipaddr = ipaddr;
+        slot.port = port;
+        slot.len = payload.len();
+        set_array(&mut slot.data, 0, payload);
+        self.count += 1;
+        Ok(())
+    }
+
+    fn front(&self) -> Option<&Datagram<M>> {
+        match self.count {
+            0 => None,
+            _ => self.slots.get(self.head),
+        }
+    }
+
+    fn pop(&mut self) {
+        if self.count > 0 {
+            self.head = (self.head + 1) % N;
+            self.count -= 1;
+        }
+    }
+}
+
+/// A UDP socket bound to a local port, holding up to `N` received and `N` outgoing
+/// datagrams of up to `M` bytes each
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UdpSocket<const N: usize, const M: usize> {
+    port: u16,
+    rx: DatagramQueue<N, M>,
+    tx: DatagramQueue<N, M>,
+    rx_dropped: usize,
+    rx_waker: WakerRegistration,
+    tx_waker: WakerRegistration,
+}
+
+impl<const N: usize, const M: usize> UdpSocket<N, M> {
+    /// New socket bound to a local port, with empty queues
+    pub const fn bind(port: u16) -> Self {
+        UdpSocket {
+            port,
+            rx: DatagramQueue::new(),
+            tx: DatagramQueue::new(),
+            rx_dropped: 0,
+            rx_waker: WakerRegistration::new(),
+            tx_waker: WakerRegistration::new(),
+        }
+    }
+
+    /// Local port the socket is bound to
+    pub fn local_port(&self) -> u16 {
+        self.port
+    }
+
+    /// Number of received datagrams waiting to be read
+    pub fn rx_len(&self) -> usize {
+        self.rx.count
+    }
+
+    /// Number of datagrams waiting to be sent
+    pub fn tx_len(&self) -> usize {
+        self.tx.count
+    }
+
+    /// Number of datagrams addressed to this socket that were discarded
+    /// because the receive queue was full or they were too long
+    pub fn rx_dropped(&self) -> usize {
+        self.rx_dropped
+    }
+
+    /// Queue a received datagram if it is addressed to this socket's port,
+    /// returning whether it was addressed to this socket
+    pub fn process(&mut self, packet: &UdpPacket) -> bool {
+        if packet.udp.dst_port != self.port {
+            return false;
+        }
+        match self
+            .rx
+            .push(packet.ip.src_ipaddr, packet.udp.src_port, packet.payload)
+        {
+            Ok(()) => self.rx_waker.wake(),
+            Err(_) => self.rx_dropped = self.rx_dropped.saturating_add(1),
+        }
+        true
+    }
+
+    /// Send the oldest queued datagram on the device, returning whether there was one to send.
+    ///
+    /// If the next hop's hardware address is not known or the device is busy, the datagram
+    /// stays in the queue to be tried again. A datagram that cannot be written as a frame is discarded.
+    pub fn dispatch<D: Device, const K: usize, const R: usize>(
+        &mut self,
+        iface: &Interface<K, R>,
+        device: &mut D,
+        tx_buffer: &mut [u8],
+    ) -> Result<bool, SocketError> {
+        let datagram = match self.tx.front() {
+            Some(x) => x,
+            None => return Ok(false),
+        };
+        let result = iface.write_udp(
+            self.port,
+            datagram.ipaddr,
+            datagram.port,
+            datagram.payload(),
+            tx_buffer,
+        );
+
+        match result {
+            Ok(len) => {
+                let frame = tx_buffer.get(..len).unwrap_or(&[]);
+                device.transmit(frame).map_err(SocketError::Device)?;
+                self.tx.pop();
+                self.tx_waker.wake();
+                Ok(true)
+            }
+            Err(x @ SendError::Unresolved(_)) => Err(SocketError::Send(x)),
+            Err(x) => {
+                self.tx.pop();
+                self.tx_waker.wake();
+                Err(SocketError::Send(x))
+            }
+        }
+    }
+
+    /// Like [UdpSocket::dispatch], but a datagram whose next hop's hardware address is not
+    /// known is moved to a [PendingQueue] to be sent once ARP resolution completes.
+    ///
+    /// Returns whether there was a datagram to send. If the pending queue is full,
+    /// the datagram stays in the socket's queue and [SocketError::WouldBlock] is returned.
+    pub fn dispatch_pending<
+        D: Device,
+        const K: usize,
+        const R: usize,
+        const P: usize,
+        const L: usize,
+    >(
+        &mut self,
+        iface: &Interface<K, R>,
+        pending: &mut PendingQueue<P, L>,
+        device: &mut D,
+        tx_buffer: &mut [u8],
+        now: u64,
+    ) -> Result<bool, SocketError> {
+        let datagram = match self.tx.front() {
+            Some(x) => x,
+            None => return Ok(false),
+        };
+        let result = pending.send_udp(
+            iface,
+            self.port,
+            datagram.ipaddr,
+            datagram.port,
+            datagram.payload(),
+            tx_buffer,
+            now,
+        );
+
+        let result = match result {
+            Ok(Some(len)) => {
+                let frame = tx_buffer.get(..len).unwrap_or(&[]);
+                device.transmit(frame).map_err(SocketError::Device)?;
+                Ok(true)
+            }
+            Ok(None) => Ok(true),
+            Err(PendingError::QueueFull) => return Err(SocketError::WouldBlock),
+            Err(PendingError::FrameTooLong) => Err(SocketError::PayloadTooLong),
+            Err(PendingError::Send(x)) => Err(SocketError::Send(x)),
+        };
+        self.tx.pop();
+        self.tx_waker.wake();
+        result
+    }
+}
+
+impl<const N: usize, const M: usize> DatagramSocket for UdpSocket<N, M> {
+    fn send_to(
+        &mut self,
+        dst_ipaddr: IpV4Addr,
+        dst_port: u16,
+        payload: &[u8],
+    ) -> Result<(), SocketError> {
+        self.tx.push(dst_ipaddr, dst_port, payload)
+    }
+
+    fn recv_from(&mut self, buffer: &mut [u8]) -> Result<(IpV4Addr, u16, usize), SocketError> {
+        let datagram = self.rx.front().ok_or(SocketError::WouldBlock)?;
+        let payload = datagram.payload();
+        buffer
+            .get_mut(..payload.len())
+            .ok_or(SocketError::BufferTooSmall)?
+            .copy_from_slice(payload);
+        let out = (datagram.ipaddr, datagram.port, payload.len());
+        self.rx.pop();
+        Ok(out)
+    }
+}
+
+impl<const N: usize, const M: usize> AsyncDatagramSocket for UdpSocket<N, M> {
+    fn poll_send_to(
+        &mut self,
+        cx: &mut Context<'_>,
+        dst_ipaddr: IpV4Addr,
+        dst_port: u16,
+        payload: &[u8],
+    ) -> Poll<Result<(), SocketError>> {
+        match self.send_to(dst_ipaddr, dst_port, payload) {
+            Err(SocketError::WouldBlock) => {
+                self.tx_waker.register(cx.waker());
+                Poll::Pending
+            }
+            x => Poll::Ready(x),
+        }
+    }
+
+    fn poll_recv_from(
+        &mut self,
+        cx: &mut Context<'_>,
+        buffer: &mut [u8],
+    ) -> Poll<Result<(IpV4Addr, u16, usize), SocketError>> {
+        match self.recv_from(buffer) {
+            Err(SocketError::WouldBlock) => {
+                self.rx_waker.register(cx.waker());
+                Poll::Pending
+            }
+            x => Poll::Ready(x),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const MAC_A: MacAddr = ByteArray([0x02, 0, 0, 0, 0, 1]);
+    const MAC_B: MacAddr = ByteArray([0x02, 0, 0, 0, 0, 2]);
+    const IP_A: IpV4Addr = ByteArray([10, 0, 0, 1]);
+    const IP_B: IpV4Addr = ByteArray([10, 0, 0, 2]);
+
+    #[test]
+    fn test_socket() {
+        let mask = IpV4Addr::new([255, 255, 255, 0]);
+        let mut iface_a: Interface = Interface::new(MAC_A, IP_A, mask, IpV4Addr::ANY);
+        let mut iface_b: Interface = Interface::new(MAC_B, IP_B, mask, IpV4Addr::ANY);
+        let mut device_a: QueueDevice<4, 1514> = QueueDevice::new();
+        let mut socket_a: UdpSocket<2, 16> = UdpSocket::bind(1000);
+        let mut socket_b: UdpSocket<2, 16> = UdpSocket::bind(2000);
+        let mut tx = [0_u8; 1514];
+        let mut wire = [0_u8; 1514];
+
+        // Send queue is bounded
+        socket_a.send_to(IP_B, 2000, &[1, 2, 3]).unwrap();
+        socket_a.send_to(IP_B, 2001, &[4]).unwrap();
+        assert_eq!(
+            socket_a.send_to(IP_B, 2000, &[5]),
+            Err(SocketError::WouldBlock)
+        );
+        assert_eq!(
+            socket_a.send_to(IP_B, 2000, &[0; 17]),
+            Err(SocketError::PayloadTooLong)
+        );
+
+        // B's hardware address is not known yet, so the datagram waits
+        assert_eq!(
+            socket_a.dispatch(&iface_a, &mut device_a, &mut tx),
+            Err(SocketError::Send(SendError::Unresolved(IP_B)))
+        );
+        assert_eq!(socket_a.tx_len(), 2);
+
+        // Resolve B's address
+        let len = iface_a.write_arp_request(IP_B, &mut tx, 0).unwrap();
+        let len = match iface_b.poll(&tx[..len], &mut wire, 0) {
+            InterfaceEvent::Transmit(len) => len,
+            x => panic!("{x:?}"),
+        };
+        assert_eq!(iface_a.poll(&wire[..len], &mut tx, 0), InterfaceEvent::None);
+
+        // Both datagrams go out and only the one for B's port is accepted
+        assert_eq!(
+            socket_a.dispatch(&iface_a, &mut device_a, &mut tx),
+            Ok(true)
+        );
+        assert_eq!(
+            socket_a.dispatch(&iface_a, &mut device_a, &mut tx),
+            Ok(true)
+        );
+        assert_eq!(
+            socket_a.dispatch(&iface_a, &mut device_a, &mut tx),
+            Ok(false)
+        );
+        let mut accepted = 0;
+        while let Some(len) = device_a.pop_tx(&mut wire) {
+            if let InterfaceEvent::Udp(udp) = iface_b.poll(&wire[..len], &mut tx, 0) {
+                accepted += socket_b.process(&udp) as usize;
+            }
+        }
+        assert_eq!(accepted, 1);
+
+        // Receive
+        let mut buffer = [0_u8; 16];
+        assert_eq!(
+            socket_b.recv_from(&mut buffer[..2]),
+            Err(SocketError::BufferTooSmall)
+        );
+        assert_eq!(socket_b.recv_from(&mut buffer), Ok((IP_A, 1000, 3)));
+        assert_eq!(&buffer[..3], &[1, 2, 3]);
+        assert_eq!(
+            socket_b.recv_from(&mut buffer),
+            Err(SocketError::WouldBlock)
+        );
+    }
+
+    #[test]
+    fn test_dispatch_pending() {
+        let mask = IpV4Addr::new([255, 255, 255, 0]);
+        let mut iface_a: Interface = Interface::new(MAC_A, IP_A, mask, IpV4Addr::ANY);
+        let mut iface_b: Interface = Interface::new(MAC_B, IP_B, mask, IpV4Addr::ANY);
+        let mut device_a: QueueDevice<4, 1514> = QueueDevice::new();
+        let mut pending: PendingQueue<4, 1514> = PendingQueue::default();
+        let mut socket_a: UdpSocket<2, 16> = UdpSocket::bind(1000);
+        let mut tx = [0_u8; 1514];
+        let mut wire = [0_u8; 1514];
+
+        // Datagram moves to the pending queue while B is resolved
+        socket_a.send_to(IP_B, 2000, &[1, 2, 3]).unwrap();
+        assert_eq!(
+            socket_a.dispatch_pending(&iface_a, &mut pending, &mut device_a, &mut tx, 0),
+            Ok(true)
+        );
+        assert_eq!(socket_a.tx_len(), 0);
+        assert_eq!(pending.len(), 1);
+        assert_eq!(device_a.tx_len(), 0);
+
+        let len = match pending.poll(&mut iface_a, &mut tx, 0) {
+            PendingEvent::Transmit(len) => len,
+            x => panic!("{x:?}"),
+        };
+        let len = match iface_b.poll(&tx[..len], &mut wire, 0) {
+            InterfaceEvent::Transmit(len) => len,
+            x => panic!("{x:?}"),
+        };
+        iface_a.poll(&wire[..len], &mut tx, 0);
+
+        // ... then is released to B
+        let len = match pending.poll(&mut iface_a, &mut tx, 0) {
+            PendingEvent::Transmit(len) => len,
+            x => panic!("{x:?}"),
+        };
+        match iface_b.poll(&tx[..len], &mut wire, 0) {
+            InterfaceEvent::Udp(udp) => assert_eq!(udp.payload, &[1, 2, 3]),
+            x => panic!("{x:?}"),
+        }
+    }
+}
@@ -1,7 +1,11 @@
 //! Transport layer: User Datagram Protocol
 
-use crate::ip::{IpV4Frame, IpV4Header};
-use crate::{calc_ip_checksum_finalize, calc_ip_checksum_incomplete};
+use crate::enet::{EtherType, EthernetFrame, EthernetHeader};
+use crate::ip::{Fragmentation, IpV4Frame, IpV4Header, VersionAndHeaderLength};
+use crate::{
+    calc_ip_checksum, calc_ip_checksum_finalize, calc_ip_checksum_incomplete, get_u16, set_u16,
+    BuildError, IpV4Addr, MacAddr, ParseError, Protocol, TryByteStruct, DSCP,
+};
 use byte_struct::*;
 pub use ufmt::derive::uDebug;
 
@@ -35,6 +39,32 @@ impl UdpHeader {
     }
 }
 
+impl UdpHeader {
+    /// Check the length field against its own header size and against the
+    /// length of the data of the IP packet carrying it.
+    pub fn validate(&self, ip_header: &IpV4Header) -> Result<(), ParseError> {
+        let ip_header_length = 4 * ip_header.version_and_header_length.header_length() as u16;
+        let ip_data_length = ip_header.total_length.saturating_sub(ip_header_length);
+        if (self.length as usize) < Self::BYTE_LEN || self.length > ip_data_length {
+            return Err(ParseError::BadLength(self.length));
+        }
+
+        Ok(())
+    }
+}
+
+impl TryByteStruct for UdpHeader {
+    fn try_read_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        let bytes = bytes.get(..Self::BYTE_LEN).ok_or(ParseError::Truncated)?;
+        let header = Self::read_bytes(bytes);
+        if (header.length as usize) < Self::BYTE_LEN {
+            return Err(ParseError::BadLength(header.length));
+        }
+
+        Ok(header)
+    }
+}
+
 /// IPV4 message frame for UDP protocol.
 #[derive(Clone, Copy, uDebug, Debug, PartialEq, Eq)]
 pub struct UdpFrame<T: ByteStruct> {
@@ -58,7 +88,7 @@ impl<T> ByteStructLen for UdpFrame<T>
 where
     T: ByteStruct,
 {
-    const BYTE_LEN: usize = IpV4Header::BYTE_LEN + UdpHeader::BYTE_LEN + T::BYTE_LEN;
+    const BYTE_LEN: usize = UdpHeader::BYTE_LEN + T::BYTE_LEN;
 }
 
 impl<T> ByteStruct for UdpFrame<T>
@@ -79,6 +109,100 @@ where
     }
 }
 
+impl<T: ByteStruct> IpV4Frame<UdpFrame<T>>
+where
+    [(); UdpFrame::<T>::BYTE_LEN]:,
+{
+    /// Check the IP header fields and checksum, the UDP length against the IP length,
+    /// and the UDP checksum if one is present.
+    pub fn validate(&self) -> Result<(), ParseError> {
+        self.header.validate(Self::BYTE_LEN)?;
+        self.header.verify_checksum()?;
+        self.data.header.validate(&self.header)?;
+        let received = self.data.header.checksum;
+        let sum = udp_pseudoheader_sum(
+            self.header.src_ipaddr,
+            self.header.dst_ipaddr,
+            self.data.header.length,
+        ) + calc_ip_checksum_incomplete(&self.data.to_be_bytes());
+
+        verify_udp_checksum(received, sum)
+    }
+}
+
+impl<T> TryByteStruct for UdpFrame<T>
+where
+    T: TryByteStruct,
+{
+    /// Parse the header, then parse the data from the part of the input
+    /// covered by the header's length field.
+    fn try_read_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        let header = UdpHeader::try_read_bytes(bytes)?;
+        let data_bytes = bytes
+            .get(UdpHeader::BYTE_LEN..header.length as usize)
+            .ok_or(ParseError::BadLength(header.length))?;
+        if data_bytes.len() < T::BYTE_LEN {
+            return Err(ParseError::BadLength(header.length));
+        }
+
+        Ok(UdpFrame::<T> {
+            header,
+            data: T::try_read_bytes(data_bytes)?,
+        })
+    }
+}
+
+/// UDP datagram with a typed header and data whose length is only known at run time,
+/// borrowed from the buffer it was parsed from.
+///
+/// This is the counterpart to [UdpFrame] for receiving datagrams of any size;
+/// the data is bounded by the header's length field. Parsing it from the data of an
+/// [IpV4Packet](crate::IpV4Packet) bounds it by the IP header's total length as well.
+///
+/// ```rust
+/// use catnip::*;
+///
+/// // A 3-byte datagram followed by ethernet padding
+/// let bytes = [0x1F, 0x90, 0x1F, 0x91, 0, 11, 0, 0, 1, 2, 3, 0, 0, 0];
+/// let datagram = UdpDatagram::try_read_bytes(&bytes).unwrap();
+///
+/// assert_eq!(datagram.header.dst_port, 8081);
+/// assert_eq!(datagram.data, &[1, 2, 3]);
+/// ```
+#[derive(Clone, Copy, uDebug, Debug, PartialEq, Eq)]
+pub struct UdpDatagram<'a> {
+    /// UDP packet header
+    pub header: UdpHeader,
+    /// Data following the header
+    pub data: &'a [u8],
+}
+
+impl<'a> UdpDatagram<'a> {
+    /// Parse the header and borrow the data it describes
+    pub fn try_read_bytes(bytes: &'a [u8]) -> Result<Self, ParseError> {
+        let header = UdpHeader::try_read_bytes(bytes)?;
+        let data = bytes
+            .get(UdpHeader::BYTE_LEN..header.length as usize)
+            .ok_or(ParseError::BadLength(header.length))?;
+
+        Ok(UdpDatagram { header, data })
+    }
+
+    /// Check the checksum against the addresses from the IP header, if a checksum is present
+    pub fn verify_checksum(
+        &self,
+        src_ipaddr: IpV4Addr,
+        dst_ipaddr: IpV4Addr,
+    ) -> Result<(), ParseError> {
+        let mut sum = udp_pseudoheader_sum(src_ipaddr, dst_ipaddr, self.header.length);
+        // The header is an even number of bytes, so the data sums as if it were contiguous with it
+        sum += calc_ip_checksum_incomplete(&self.header.to_be_bytes());
+        sum += calc_ip_checksum_incomplete(self.data);
+
+        verify_udp_checksum(self.header.checksum, sum)
+    }
+}
+
 /// UDP checksum calculation with pseudo-header that includes some info from IP header
 /// This is not the most efficient possible way to do this; in general, checksum calculation
 /// should be processor-offloaded and should not be run in software except for troubleshooting.
@@ -109,3 +233,435 @@ where
 
     checksum
 }
+
+/// Borrowed view of a UDP datagram in a buffer, with typed access to header fields in place.
+///
+/// The length field is checked against the buffer when the view is constructed with
+/// [UdpView::new], so the payload is bounded by the length field rather than by the size of the
+/// buffer. To assemble a datagram in a blank transmit buffer, use [UdpView::new_unchecked] and
+/// write the header first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UdpView<T: AsRef<[u8]>> {
+    buffer: T,
+}
+
+impl<T: AsRef<[u8]>> UdpView<T> {
+    /// Wrap a buffer, checking that the header's length field fits in the buffer
+    pub fn new(buffer: T) -> Result<Self, ParseError> {
+        let bytes = buffer.as_ref();
+        let header = UdpHeader::try_read_bytes(bytes)?;
+        if header.length as usize > bytes.len() {
+            return Err(ParseError::BadLength(header.length));
+        }
+
+        Ok(UdpView { buffer })
+    }
+
+    /// Wrap a buffer without checking the header, such as a transmit buffer whose header is
+    /// yet to be written. Accessors stay within the buffer regardless, and the payload is empty
+    /// until the length field describes it.
+    pub fn new_unchecked(buffer: T) -> Self {
+        UdpView { buffer }
+    }
+
+    /// Release the underlying buffer
+    pub fn into_inner(self) -> T {
+        self.buffer
+    }
+
+    /// Source port
+    pub fn src_port(&self) -> u16 {
+        get_u16(self.buffer.as_ref(), 0)
+    }
+
+    /// Destination port
+    pub fn dst_port(&self) -> u16 {
+        get_u16(self.buffer.as_ref(), 2)
+    }
+
+    /// Total length including header and data, in bytes
+    pub fn length(&self) -> u16 {
+        get_u16(self.buffer.as_ref(), 4)
+    }
+
+    /// Checksum; 0 if not used
+    pub fn checksum(&self) -> u16 {
+        get_u16(self.buffer.as_ref(), 6)
+    }
+
+    /// Copy out the header
+    pub fn header(&self) -> UdpHeader {
+        UdpHeader {
+            src_port: self.src_port(),
+            dst_port: self.dst_port(),
+            length: self.length(),
+            checksum: self.checksum(),
+        }
+    }
+
+    /// Check the checksum against the addresses from the IP header, if a checksum is present
+    pub fn verify_checksum(
+        &self,
+        src_ipaddr: IpV4Addr,
+        dst_ipaddr: IpV4Addr,
+    ) -> Result<(), ParseError> {
+        let datagram = self
+            .buffer
+            .as_ref()
+            .get(..self.length() as usize)
+            .unwrap_or(&[]);
+        let mut sum = udp_pseudoheader_sum(src_ipaddr, dst_ipaddr, self.length());
+        sum += calc_ip_checksum_incomplete(datagram);
+
+        verify_udp_checksum(self.checksum(), sum)
+    }
+
+    /// Data after the header, up to the length indicated by the header
+    pub fn payload(&self) -> &[u8] {
+        self.buffer
+            .as_ref()
+            .get(UdpHeader::BYTE_LEN..self.length() as usize)
+            .unwrap_or(&[])
+    }
+}
+
+impl<T: AsRef<[u8]> + AsMut<[u8]>> UdpView<T> {
+    /// Set source port
+    pub fn set_src_port(&mut self, value: u16) {
+        set_u16(self.buffer.as_mut(), 0, value);
+    }
+
+    /// Set destination port
+    pub fn set_dst_port(&mut self, value: u16) {
+        set_u16(self.buffer.as_mut(), 2, value);
+    }
+
+    /// Set total length including header and data, which moves the end of the payload
+    pub fn set_length(&mut self, value: u16) {
+        set_u16(self.buffer.as_mut(), 4, value);
+    }
+
+    /// Set checksum
+    pub fn set_checksum(&mut self, value: u16) {
+        set_u16(self.buffer.as_mut(), 6, value);
+    }
+
+    /// Overwrite the header
+    pub fn set_header(&mut self, value: &UdpHeader) {
+        self.set_src_port(value.src_port);
+        self.set_dst_port(value.dst_port);
+        self.set_length(value.length);
+        self.set_checksum(value.checksum);
+    }
+
+    /// Mutable access to the data after the header, up to the length indicated by the header
+    pub fn payload_mut(&mut self) -> &mut [u8] {
+        let range = UdpHeader::BYTE_LEN..self.length() as usize;
+        self.buffer.as_mut().get_mut(range).unwrap_or(&mut [])
+    }
+}
+
+/// UDP checksum calculation for a datagram whose length is only known at run time.
+///
+/// The datagram is the UDP header followed by its data. When generating a checksum for transmit,
+/// the header's checksum field should be zeroed; when checking a received datagram,
+/// leaving the received checksum in place produces 0 if the datagram is intact.
+pub fn calc_udp_checksum_bytes(src_ipaddr: IpV4Addr, dst_ipaddr: IpV4Addr, datagram: &[u8]) -> u16 {
+    let mut sum = udp_pseudoheader_sum(src_ipaddr, dst_ipaddr, datagram.len() as u16);
+    sum += calc_ip_checksum_incomplete(datagram);
+
+    calc_ip_checksum_finalize(sum)
+}
+
+/// Unfolded checksum accumulator for the parts of the pseudo-header taken from the IP header
+fn udp_pseudoheader_sum(src_ipaddr: IpV4Addr, dst_ipaddr: IpV4Addr, udp_length: u16) -> u32 {
+    let udp_length_bytes = udp_length.to_be_bytes();
+    let ip_pseudoheader: [u8; 4] = [
+        0,
+        Protocol::Udp as u8,
+        udp_length_bytes[0],
+        udp_length_bytes[1],
+    ];
+
+    let mut sum: u32 = 0;
+    sum += calc_ip_checksum_incomplete(&src_ipaddr.0);
+    sum += calc_ip_checksum_incomplete(&dst_ipaddr.0);
+    sum += calc_ip_checksum_incomplete(&ip_pseudoheader);
+
+    sum
+}
+
+/// Check a received checksum that was summed along with the data it covers.
+/// A transmitted checksum of 0 means that the sender did not calculate one.
+fn verify_udp_checksum(received: u16, sum: u32) -> Result<(), ParseError> {
+    match (received, calc_ip_checksum_finalize(sum)) {
+        (0, _) | (_, 0) => Ok(()),
+        _ => Err(ParseError::BadUdpChecksum),
+    }
+}
+
+/// Assembles complete Ethernet/IPV4/UDP frames from a pair of endpoints and a payload,
+/// deriving every length, type, and checksum field so that headers don't need to be
+/// assembled by hand.
+///
+/// The frame check sequence at the end of the ethernet frame is not calculated here;
+/// that is left to the hardware, as with [EthernetFrame].
+///
+/// ```rust
+/// # #![allow(incomplete_features)]
+/// # #![feature(generic_const_exprs)]
+/// use catnip::*;
+///
+/// let builder = UdpBuilder::new(
+///     MacAddr::new([0x02, 0xAF, 0xFF, 0x1A, 0xE5, 0x3C]),
+///     IpV4Addr::new([10, 0, 0, 120]),
+///     8123,
+///     MacAddr::BROADCAST,
+///     IpV4Addr::new([10, 0, 0, 121]),
+///     8125,
+/// );
+///
+/// // Write a frame with a run-time-length payload into a transmit buffer
+/// let mut buffer = [0_u8; 1514];
+/// let len = builder.write_bytes(&[1, 2, 3], &mut buffer).unwrap();
+/// assert_eq!(len, EthernetHeader::BYTE_LEN + IpV4Header::BYTE_LEN + UdpHeader::BYTE_LEN + 3);
+///
+/// // Or build a typed frame around a fixed-size payload
+/// let frame = builder.build_frame(ByteArray([0, 1, 2, 3, 4, 5, 6, 7]));
+/// assert_eq!(frame.data.data.header.length, 16);
+/// ```
+#[derive(Clone, Copy, uDebug, Debug, PartialEq, Eq)]
+pub struct UdpBuilder {
+    /// Source MAC address
+    pub src_macaddr: MacAddr,
+    /// Source IP address
+    pub src_ipaddr: IpV4Addr,
+    /// Source port
+    pub src_port: u16,
+    /// Destination MAC address
+    pub dst_macaddr: MacAddr,
+    /// Destination IP address
+    pub dst_ipaddr: IpV4Addr,
+    /// Destination port
+    pub dst_port: u16,
+    /// TTL counter for the IP header
+    pub time_to_live: u8,
+    /// Type of Service / Differentiated-Service for the IP header
+    pub dscp: DSCP,
+    /// Mostly-legacy id field for the IP header
+    pub identification: u16,
+    /// Whether to calculate the optional UDP checksum in software
+    pub udp_checksum: bool,
+}
+
+impl UdpBuilder {
+    /// New builder with default TTL of 64, standard DSCP, and UDP checksum enabled
+    pub fn new(
+        src_macaddr: MacAddr,
+        src_ipaddr: IpV4Addr,
+        src_port: u16,
+        dst_macaddr: MacAddr,
+        dst_ipaddr: IpV4Addr,
+        dst_port: u16,
+    ) -> Self {
+        UdpBuilder {
+            src_macaddr,
+            src_ipaddr,
+            src_port,
+            dst_macaddr,
+            dst_ipaddr,
+            dst_port,
+            time_to_live: 64,
+            dscp: DSCP::Standard,
+            identification: 0,
+            udp_checksum: true,
+        }
+    }
+
+    /// Set TTL counter for the IP header
+    pub fn with_time_to_live(mut self, time_to_live: u8) -> Self {
+        self.time_to_live = time_to_live;
+        self
+    }
+
+    /// Set Type of Service / Differentiated-Service for the IP header
+    pub fn with_dscp(mut self, dscp: DSCP) -> Self {
+        self.dscp = dscp;
+        self
+    }
+
+    /// Set id field for the IP header
+    pub fn with_identification(mut self, identification: u16) -> Self {
+        self.identification = identification;
+        self
+    }
+
+    /// Enable or disable calculation of the UDP checksum in software.
+    /// Disable this if the hardware fills in the checksum, or to send a checksum of 0 (not used).
+    pub fn with_udp_checksum(mut self, udp_checksum: bool) -> Self {
+        self.udp_checksum = udp_checksum;
+        self
+    }
+
+    /// Ethernet header addressed to the destination
+    fn ethernet_header(&self) -> EthernetHeader {
+        EthernetHeader {
+            dst_macaddr: self.dst_macaddr,
+            src_macaddr: self.src_macaddr,
+            ethertype: EtherType::IpV4,
+        }
+    }
+
+    /// IP header with its checksum filled, for a UDP datagram of the given length
+    fn ip_header(&self, udp_length: u16) -> IpV4Header {
+        let mut header = IpV4Header {
+            version_and_header_length: VersionAndHeaderLength::new()
+                .with_version(4)
+                .with_header_length((IpV4Header::BYTE_LEN / 4) as u8),
+            dscp: self.dscp,
+            total_length: IpV4Header::BYTE_LEN as u16 + udp_length,
+            identification: self.identification,
+            fragmentation: Fragmentation::default(),
+            time_to_live: self.time_to_live,
+            protocol: Protocol::Udp,
+            checksum: 0,
+            src_ipaddr: self.src_ipaddr,
+            dst_ipaddr: self.dst_ipaddr,
+        };
+        header.checksum = calc_ip_checksum(&header.to_be_bytes());
+
+        header
+    }
+
+    /// UDP header with its checksum not yet filled
+    fn udp_header(&self, udp_length: u16) -> UdpHeader {
+        UdpHeader {
+            src_port: self.src_port,
+            dst_port: self.dst_port,
+            length: udp_length,
+            checksum: 0,
+        }
+    }
+
+    /// A calculated checksum of 0 is sent as all ones, because 0 indicates that no checksum is used
+    fn udp_checksum(&self, datagram: &[u8]) -> u16 {
+        match self.udp_checksum {
+            true => match calc_udp_checksum_bytes(self.src_ipaddr, self.dst_ipaddr, datagram) {
+                0 => 0xFFFF,
+                x => x,
+            },
+            false => 0,
+        }
+    }
+
+    /// Write a complete frame around a payload of any length into the start of a buffer,
+    /// returning the number of bytes written.
+    pub fn write_bytes(&self, payload: &[u8], buffer: &mut [u8]) -> Result<usize, BuildError> {
+        let udp_length = UdpHeader::BYTE_LEN + payload.len();
+        let ip_length = IpV4Header::BYTE_LEN + udp_length;
+        if ip_length > u16::MAX as usize {
+            return Err(BuildError::PayloadTooLong);
+        }
+        let frame_length = EthernetHeader::BYTE_LEN + ip_length;
+        let buffer = buffer
+            .get_mut(..frame_length)
+            .ok_or(BuildError::BufferTooSmall)?;
+
+        let (enet_bytes, ip_bytes) = buffer.split_at_mut(EthernetHeader::BYTE_LEN);
+        let (ip_header_bytes, udp_bytes) = ip_bytes.split_at_mut(IpV4Header::BYTE_LEN);
+        let (udp_header_bytes, data_bytes) = udp_bytes.split_at_mut(UdpHeader::BYTE_LEN);
+
+        self.ethernet_header().write_bytes(enet_bytes);
+        self.ip_header(udp_length as u16)
+            .write_bytes(ip_header_bytes);
+        self.udp_header(udp_length as u16)
+            .write_bytes(udp_header_bytes);
+        data_bytes.copy_from_slice(payload);
+        let checksum = self.udp_checksum(udp_bytes);
+        set_u16(udp_bytes, 6, checksum);
+
+        Ok(frame_length)
+    }
+
+    /// Build a typed frame around a fixed-size payload.
+    ///
+    /// Like [calc_udp_checksum], this requires the calling crate to enable `generic_const_exprs`;
+    /// [UdpBuilder::write_bytes] does not.
+    pub fn build_frame<T: ByteStruct>(&self, data: T) -> EthernetFrame<IpV4Frame<UdpFrame<T>>>
+    where
+        [(); UdpFrame::<T>::BYTE_LEN]:,
+    {
+        let udp_length = UdpFrame::<T>::BYTE_LEN as u16;
+        let mut udp = UdpFrame {
+            header: self.udp_header(udp_length),
+            data,
+        };
+        udp.header.checksum = self.udp_checksum(&udp.to_be_bytes());
+
+        EthernetFrame {
+            header: self.ethernet_header(),
+            data: IpV4Frame {
+                header: self.ip_header(udp_length),
+                data: udp,
+            },
+            checksum: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::*;
+
+    #[test]
+    fn test_udp_builder() {
+        let src_ipaddr = IpV4Addr::new([10, 0, 0, 1]);
+        let dst_ipaddr = IpV4Addr::new([10, 0, 0, 2]);
+        let builder = UdpBuilder::new(
+            MacAddr::new([0x02, 0xAF, 0xFF, 0x1A, 0xE5, 0x3C]),
+            src_ipaddr,
+            8123,
+            MacAddr::BROADCAST,
+            dst_ipaddr,
+            8125,
+        );
+
+        // Typed and run-time-length paths produce the same bytes
+        let data = ByteArray([0, 1, 2, 3, 4, 5, 6, 7]);
+        let frame = builder.build_frame(data);
+        let typed_bytes = frame.to_be_bytes();
+        let mut bytes = [0_u8; 128];
+        let len = builder.write_bytes(&data.0, &mut bytes).unwrap();
+        assert_eq!(len + 4, typed_bytes.len());
+        assert_eq!(&bytes[..len], &typed_bytes[..len]);
+
+        // Lengths
+        assert_eq!(frame.data.header.total_length, 36);
+        assert_eq!(frame.data.data.header.length, 16);
+        assert_eq!(frame.data.header.protocol, Protocol::Udp);
+        assert_eq!(frame.header.ethertype, EtherType::IpV4);
+
+        // Checksums
+        assert_eq!(calc_ip_checksum(&frame.data.header.to_be_bytes()), 0);
+        assert_eq!(calc_udp_checksum(&frame.data), 0);
+        assert_eq!(
+            calc_udp_checksum_bytes(src_ipaddr, dst_ipaddr, &bytes[34..len]),
+            0
+        );
+
+        // Odd-length payload and disabled UDP checksum
+        let len = builder
+            .with_udp_checksum(false)
+            .write_bytes(&[1, 2, 3], &mut bytes)
+            .unwrap();
+        let datagram = UdpDatagram::try_read_bytes(&bytes[34..len]).unwrap();
+        assert_eq!(datagram.header.checksum, 0);
+        assert_eq!(datagram.data, &[1, 2, 3]);
+
+        // Buffer too small
+        assert_eq!(
+            builder.write_bytes(&[0_u8; 100], &mut bytes),
+            Err(BuildError::BufferTooSmall)
+        );
+    }
+}
@@ -12,6 +12,8 @@ pub fn _start() -> ! {
 
     test_arp();
     test_enet_ip_udp();
+    test_views();
+    test_dhcp_options_writer();
 
     loop {}
 }
@@ -30,6 +32,8 @@ fn test_arp() -> () {
     let bytes: [u8; ArpPayload::BYTE_LEN] = msg.to_be_bytes();
     // Deserialize
     let _msg_parsed = ArpPayload::read_bytes(&bytes);
+    // Fallible parsing of truncated input
+    let _msg_parsed = ArpPayload::try_read_bytes(&bytes[..20]);
 }
 
 fn generate_sample_frame() -> EthernetFrame::<IpV4Frame<UdpFrame<ByteArray<8>>>> {
@@ -87,5 +91,59 @@ fn test_enet_ip_udp() -> () {
 
     let bytes = frame.to_be_bytes();
     let _frame_parsed = EthernetFrame::<IpV4Frame<UdpFrame<ByteArray<8>>>>::read_bytes(&bytes);
+    // Fallible parsing of truncated input
+    let _frame_parsed = EthernetFrame::<IpV4Frame<UdpFrame<ByteArray<8>>>>::try_read_bytes(&bytes[..40]);
 
 }
+
+fn test_views() -> () {
+    let mut bytes = generate_sample_frame().to_be_bytes();
+
+    // Modify a frame in place through the borrowed views
+    if let Ok(mut enet) = EthernetView::new(&mut bytes[..]) {
+        enet.set_src_macaddr(MacAddr::new([0x02, 0, 0, 0, 0, 1]));
+        if let Ok(mut ip) = IpV4View::new(enet.payload_mut()) {
+            ip.set_time_to_live(64);
+            ip.fill_checksum();
+            if let Ok(mut udp) = UdpView::new(ip.payload_mut()) {
+                udp.set_dst_port(8126);
+            }
+        }
+    }
+
+    // Assemble a frame in a blank buffer through the views
+    let frame = generate_sample_frame();
+    let mut tx = [0_u8; 64];
+    if let Ok(mut enet) = EthernetView::new(&mut tx[..]) {
+        enet.set_header(&frame.header);
+        let mut ip = IpV4View::new_unchecked(enet.payload_mut());
+        ip.set_header(&frame.data.header);
+        ip.set_total_length(20 + 8 + 3);
+        ip.fill_checksum();
+        let mut udp = UdpView::new_unchecked(ip.payload_mut());
+        udp.set_header(&frame.data.data.header);
+        udp.set_length(8 + 3);
+        for (dst, src) in udp.payload_mut().iter_mut().zip([1, 2, 3]) {
+            *dst = src;
+        }
+    }
+}
+
+fn test_dhcp_options_writer() -> () {
+    let mut buffer = [0_u8; 400];
+    let msg = DhcpFixedPayload::new_inform(IpV4Addr::new([10, 0, 0, 1]), MacAddr::new([0x02, 0, 0, 0, 0, 1]), 7);
+
+    // Read the host name length through a volatile load so that the optimizer
+    // can't fold the option lengths into constants and must keep the bounds checks
+    let name = [b'a'; 64];
+    let name_len = unsafe { core::ptr::read_volatile(&37_usize) };
+    let name = core::str::from_utf8(name.get(..name_len).unwrap_or(&[])).unwrap_or("");
+
+    if let Ok(mut writer) = DhcpOptionsWriter::new(&mut buffer, &msg) {
+        let _ = writer.requested_ip(IpV4Addr::new([10, 0, 0, 1]));
+        let _ = writer.client_id(MacAddr::new([0x02, 0, 0, 0, 0, 1]));
+        let _ = writer.hostname(name);
+        let _ = writer.parameter_request_list(&[DhcpOptionKind::Router, DhcpOptionKind::DomainNameServers]);
+        let _len = writer.finish();
+    }
+}